@@ -0,0 +1,40 @@
+// 库用法示例：构造商户账单并生成Word通知单
+// 运行：cargo run --example generate [输出路径]
+use anyhow::Result;
+use water_and_electricity_meter::{generate_word_document_with_template, GenerateOptions, MerchantBill, Totals};
+
+fn main() -> Result<()> {
+    let output = std::env::args()
+        .nth(1)
+        .unwrap_or_else(|| std::env::temp_dir().join("example_bills.docx").to_string_lossy().into_owned());
+
+    // 单块电表的商户（构建器写法：各项设置完后由 build 统一计算金额）
+    let first = MerchantBill::builder("张三便利店".to_string(), 3.5, 0.8)
+        .shop_code("A101".to_string())
+        .water_readings(120.0, 135.0)
+        .electricity_meter("1".to_string(), 1000.0, 1250.0)
+        .meter_info(Some("李四".to_string()), None)
+        .build();
+
+    // 两块电表（分表单价不同）并收取人工费、垃圾处理费的商户
+    let mut second = MerchantBill::new("王五餐饮".to_string(), 3.5, 0.8);
+    second.set_shop_code("A102".to_string());
+    second.set_water_readings(300.0, 342.0);
+    second.add_priced_electricity_meter("1".to_string(), 5000.0, 5600.0, 0.8);
+    second.add_priced_electricity_meter("2".to_string(), 200.0, 260.0, 1.2);
+    second.water_electricity_labor_fee = 20.0;
+    second.garbage_disposal_fee = 30.0;
+    second.update_totals();
+
+    let bills = vec![first, second];
+    let options = GenerateOptions { per_page: 2, ..GenerateOptions::default() };
+    let bytes = generate_word_document_with_template(&bills, Some(options))?;
+    std::fs::write(&output, bytes)?;
+
+    let totals = Totals::from_merchants(&bills);
+    for bill in &bills {
+        println!("{} {}：合计 {:.2} 元", bill.shop_code, bill.merchant_name, bill.total_fee);
+    }
+    println!("总计 {:.2} 元，已生成: {}", totals.grand_total, output);
+    Ok(())
+}
@@ -1,40 +1,268 @@
 use anyhow::{Context, Result};
 use calamine::{open_workbook, DataType, Reader, Xlsx};
-use chrono::{Local, Datelike};
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::collections::HashMap;
 use std::fs::File;
-use std::io::{BufRead, BufReader};
+use std::io::{BufRead, BufReader, Cursor, Read, Seek};
 use std::path::Path;
 
-#[derive(Debug, Clone)]
+// 基于JSON配置的模板（TemplateConfig）
+pub mod template_simple;
+
+// 接管库内输出的回调，见 with_log_sink
+type LogSink = Box<dyn Fn(&str)>;
+
+thread_local! {
+    static LOG_SINK: std::cell::RefCell<Option<LogSink>> = std::cell::RefCell::new(None);
+}
+
+// 读取、生成过程中的警告与提示统一经此输出：默认打印到标准输出，with_log_sink 期间交给回调
+macro_rules! log_line {
+    ($($arg:tt)*) => {
+        emit_log(&format!($($arg)*))
+    };
+}
+
+fn emit_log(line: &str) {
+    LOG_SINK.with(|sink| match &*sink.borrow() {
+        Some(sink) => sink(line),
+        None => println!("{}", line),
+    })
+}
+
+// 在当前线程执行 f，期间库输出的每一行交给 sink（如Web服务加上请求ID后再写日志，测试中收集输出）；
+// 结束后恢复之前的输出方式
+pub fn with_log_sink<T>(sink: impl Fn(&str) + 'static, f: impl FnOnce() -> T) -> T {
+    struct Restore(Option<LogSink>);
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            let previous = self.0.take();
+            LOG_SINK.with(|sink| *sink.borrow_mut() = previous);
+        }
+    }
+    let _restore = Restore(LOG_SINK.with(|current| current.replace(Some(Box::new(sink)))));
+    f()
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
 pub struct ElectricityMeter {
     pub meter_id: String,
     pub prev_reading: f64,
     pub curr_reading: f64,
     pub usage: f64,
     pub amount: f64,
+    pub unit_price: Option<f64>, // 分表单价（可选列"电表N单价"），为空时使用商户电费单价
+    pub serial: Option<String>,  // 电表编号/表号（可选列"电表N编号"），显示在项目列中
+    pub estimated: bool,         // 估抄（免抄）：本期读数为估算值，项目列标注"估"
+    pub rollover_digits: Option<u32>, // 表盘位数（可选列"电表N位数"），设置后本期小于上期按翻表（走过最大读数）计算用量
 }
 
-#[derive(Debug, Clone)]
+impl ElectricityMeter {
+    // 费用表项目列中的电表名称：单表为"电表"，多表为"电表N"，有表号时附在括号中
+    fn display_name(&self, meter_idx: usize, meters_len: usize) -> String {
+        let name = if meters_len == 1 { "电表".to_string() } else { format!("电表{}", meter_idx + 1) };
+        let name = match &self.serial {
+            Some(serial) => format!("{}（{}）", name, serial),
+            None => name,
+        };
+        if self.estimated { format!("{}（估）", name) } else { name }
+    }
+
+    // 按读数计算的用量，见 meter_usage
+    pub fn reading_usage(&self) -> f64 {
+        meter_usage(self.prev_reading, self.curr_reading, self.rollover_digits)
+    }
+
+    // 按估算用量推出本期读数并标记估抄；翻表的电表估算读数同样在最大读数处归零
+    fn set_estimated_usage(&mut self, usage: f64) {
+        self.usage = usage;
+        self.curr_reading = self.prev_reading + usage;
+        if let Some(digits) = self.rollover_digits.filter(|d| (1..=MAX_ROLLOVER_DIGITS).contains(d)) {
+            self.curr_reading = round_cents(self.curr_reading % 10f64.powi(digits as i32));
+        }
+        self.estimated = true;
+    }
+}
+
+// 表盘位数的取值范围：超过12位时最大读数已超出f64能精确表示读数小数的范围
+pub const MAX_ROLLOVER_DIGITS: u32 = 12;
+
+// 电表用量：本期小于上期时，未设置表盘位数按0计算；设置了位数（如5位表最大读数 99999）视为翻表，
+// 用量 = (10^位数 - 上期) + 本期，如 99980 → 00030 为 50 度
+pub fn meter_usage(prev: f64, curr: f64, rollover_digits: Option<u32>) -> f64 {
+    match rollover_digits.filter(|d| (1..=MAX_ROLLOVER_DIGITS).contains(d)) {
+        Some(digits) if curr < prev => (10f64.powi(digits as i32) - prev + curr).max(0.0),
+        _ => (curr - prev).max(0.0),
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct MerchantBill {
     pub merchant_name: String,
     pub shop_code: String, // 铺面编号（字符串）
+    pub contact_name: Option<String>,      // 户主姓名/联系人（可选列）
+    pub tax_id: Option<String>,            // 纳税人识别号（可选列，开票用，按原文保存，不影响计费）
     pub water_unit_price: f64,
     pub electricity_unit_price: f64,
     pub prev_water_reading: f64,
     pub curr_water_reading: f64,
     pub water_usage: f64,
     pub water_amount: f64,
+    pub water_usage_rounding: UsageRounding, // 计价前对用水量取整的方式
+    pub amount_rounding: RoundingMode,       // 水费、电费金额取整到元的进位规则
+    pub electricity_tiers: Option<TieredPrice>, // 阶梯电价（可选），设置后电费按总用电量分档计价，不再使用电费单价
     pub electricity_meters: Vec<ElectricityMeter>,
     pub electricity_usage: f64,
     pub electricity_amount: f64,
     pub water_electricity_labor_fee: f64,  // 水电人工费
     pub garbage_disposal_fee: f64,         // 垃圾处理费
+    pub extra_fees: Vec<ExtraFee>,         // 其他费用（可选列"其他费用：名称"，如停车费、卫生费），计入总费用
+    pub discount: Option<Discount>,        // 优惠/折扣（可选列"优惠"或"折扣"）
+    pub discount_amount: f64,              // 优惠金额（负数，由 update_totals 计算）
     pub meter_reader: Option<String>,      // 抄表人（可选，由Web表单传入）
     pub meter_date: Option<String>,        // 抄表日期（可选，由Web表单传入）
+    pub period_start: Option<String>,      // 计费周期起始日期（可选列"计费起始日期"，或由命令行传入）
+    pub period_end: Option<String>,        // 计费周期截止日期（可选列"计费截止日期"，或由命令行传入）
+    pub received_amount: Option<f64>,      // 实收金额（可选列，仅用于对账，不影响计费）
+    pub building_name: Option<String>,     // 小区/楼宇名称（可选列"小区名称"或指定单元格）
+    pub unpriced: bool,                    // 仅读数输入（无单价列），金额为0，待 apply_prices 定价
     pub total_fee: f64,
     pub month: String,
 }
 
+// 其他费用：名称取自表头"其他费用："之后的部分，汇总表按名称各占一列
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ExtraFee {
+    pub name: String,
+    pub amount: f64,
+}
+
+// 其他费用列的表头前缀，如 "其他费用：停车费"
+pub const EXTRA_FEE_HEADER_PREFIX: &str = "其他费用";
+
+// 优惠：固定金额（如 "20"）或按比例（如 "-10%"，按小计计算），均作为扣减
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Discount {
+    Amount(f64),
+    Percent(f64), // 比例，0.1 表示 10%
+}
+
+impl Discount {
+    // 优惠列的值：带百分号按比例，否则为固定金额；正负号均视为扣减，0视为无优惠
+    pub fn parse(raw: &str) -> Result<Option<Discount>, ParseNumError> {
+        let value = parse_amount(raw)?.abs();
+        if value == 0.0 {
+            return Ok(None);
+        }
+        Ok(Some(if raw.contains(['%', '％']) { Discount::Percent(value) } else { Discount::Amount(value) }))
+    }
+
+    // 对小计的扣减金额（负数，保留到分）
+    pub fn deduction(&self, subtotal: f64) -> f64 {
+        let amount = match self {
+            Discount::Amount(v) => *v,
+            Discount::Percent(rate) => subtotal * rate,
+        };
+        -round_cents(amount.abs())
+    }
+}
+
+// 用量计价前的取整方式（部分自来水公司按整吨向上取整计费）
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UsageRounding {
+    #[default]
+    None,
+    CeilToUnit,
+    RoundToUnit,
+}
+
+impl UsageRounding {
+    pub fn apply(&self, usage: f64) -> f64 {
+        match self {
+            UsageRounding::None => usage,
+            UsageRounding::CeilToUnit => usage.ceil(),
+            UsageRounding::RoundToUnit => usage.round(),
+        }
+    }
+}
+
+// 水费、电费金额（用量 × 单价）的取整规则：取整到"元"时可四舍五入（默认）、银行家舍入（四舍六入五成双）、
+// 舍去角分（Down）或有角分即进一元（Up）；TwoDecimals 不取整到元，四舍五入保留到分。
+// 总费用是各项已取整金额之和，不再整体取整：取整到元的规则下水电费为整数元，
+// 总费用的角分只来自人工费、垃圾处理费等其他费用；TwoDecimals 下水电费本身也带角分
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RoundingMode {
+    #[default]
+    HalfUp,
+    HalfEven,
+    Down,
+    Up,
+    TwoDecimals,
+}
+
+impl RoundingMode {
+    pub fn round(&self, amount: f64) -> f64 {
+        // 先消除乘法产生的浮点误差（如 2.5000000000000004、3.4999999999999996），再判断是否恰好为 .5 或整数；各模式一致
+        let cleaned = (amount * 1e6).round() / 1e6;
+        match self {
+            RoundingMode::HalfUp => cleaned.round(),
+            RoundingMode::HalfEven => cleaned.round_ties_even(),
+            RoundingMode::Down => cleaned.floor(),
+            RoundingMode::Up => cleaned.ceil(),
+            RoundingMode::TwoDecimals => round_cents(cleaned),
+        }
+    }
+}
+
+// 阶梯电价：thresholds 为各档的用电量上限（度，递增），rates 为各档单价，比 thresholds 多一档（最后一档不封顶）。
+// 如 thresholds [200, 400]、rates [0.5, 0.6, 0.8]：前200度0.5元，200~400度0.6元，超过400度的部分0.8元
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TieredPrice {
+    pub thresholds: Vec<f64>,
+    pub rates: Vec<f64>,
+}
+
+impl TieredPrice {
+    pub fn new(thresholds: Vec<f64>, rates: Vec<f64>) -> Result<Self> {
+        if rates.len() != thresholds.len() + 1 {
+            anyhow::bail!("阶梯电价配置错误：{}个分档上限需要{}个单价，实际为{}个", thresholds.len(), thresholds.len() + 1, rates.len());
+        }
+        if thresholds.iter().any(|t| !t.is_finite() || *t <= 0.0) || thresholds.windows(2).any(|w| w[0] >= w[1]) {
+            anyhow::bail!("阶梯电价配置错误：分档上限须为递增的正数：{:?}", thresholds);
+        }
+        if rates.iter().any(|r| !r.is_finite() || *r < 0.0) {
+            anyhow::bail!("阶梯电价配置错误：单价不能为负数：{:?}", rates);
+        }
+        Ok(Self { thresholds, rates })
+    }
+
+    // 按档累计的电费（未取整）：用电量依次落入各档，每档按该档单价计价
+    pub fn amount(&self, usage: f64) -> f64 {
+        let usage = finite_or_zero(usage).max(0.0);
+        let mut lower = 0.0;
+        let mut amount = 0.0;
+        for (i, rate) in self.rates.iter().enumerate() {
+            let upper = self.thresholds.get(i).copied().unwrap_or(f64::INFINITY);
+            amount += (usage.min(upper) - lower).max(0.0) * rate;
+            if usage <= upper {
+                break;
+            }
+            lower = upper;
+        }
+        amount
+    }
+}
+
 #[derive(Debug)]
 pub struct BillTemplate {
     pub month: String,
@@ -47,66 +275,404 @@ pub struct BillTemplate {
     pub grand_total: f64,
 }
 
+// 汇总数据，所有汇总表/合计处统一由此计算，避免各处求和口径不一致
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Totals {
+    pub water_usage: f64,
+    pub electricity_usage: f64,
+    pub water_amount: f64,
+    pub electricity_amount: f64,
+    pub labor_fee: f64,        // 水电人工费合计
+    pub garbage_fee: f64,      // 垃圾处理费合计
+    pub extra_fees: Vec<ExtraFee>, // 其他费用按名称合计（顺序同 extra_fee_names）
+    pub discount_amount: f64,  // 优惠合计（负数）
+    pub received_amount: f64,  // 实收合计（仅统计填写了实收的商户）
+    pub outstanding_amount: f64,
+    pub grand_total: f64,      // 各商户总费用之和
+}
+
+impl Totals {
+    pub fn from_merchants(merchants: &[MerchantBill]) -> Self {
+        let mut totals = Totals::default();
+        // 金额逐步累加并取整到分，避免多户相加后出现 299.99999999 之类的浮点误差
+        let add = |sum: f64, amount: f64| round_cents(sum + amount);
+        for bill in merchants {
+            totals.water_usage += bill.water_usage;
+            totals.electricity_usage += bill.electricity_usage;
+            totals.water_amount = add(totals.water_amount, bill.water_amount);
+            totals.electricity_amount = add(totals.electricity_amount, bill.electricity_amount);
+            totals.labor_fee = add(totals.labor_fee, bill.water_electricity_labor_fee);
+            totals.garbage_fee = add(totals.garbage_fee, bill.garbage_disposal_fee);
+            totals.discount_amount = add(totals.discount_amount, bill.discount_amount);
+            totals.received_amount = add(totals.received_amount, bill.received_amount.unwrap_or(0.0));
+            totals.outstanding_amount = add(totals.outstanding_amount, bill.outstanding_amount().unwrap_or(0.0));
+            totals.grand_total = add(totals.grand_total, bill.total_fee);
+        }
+        totals.extra_fees = extra_fee_names(merchants)
+            .into_iter()
+            .map(|name| {
+                let amount = merchants.iter().fold(0.0, |sum, bill| add(sum, bill.extra_fee(&name)));
+                ExtraFee { name, amount }
+            })
+            .collect();
+        totals
+    }
+
+    // 指定名称的其他费用合计
+    pub fn extra_fee(&self, name: &str) -> f64 {
+        self.extra_fees.iter().find(|f| f.name == name).map(|f| f.amount).unwrap_or(0.0)
+    }
+
+    // 水电费合计（不含人工费、垃圾处理费）
+    pub fn utility_amount(&self) -> f64 {
+        round_cents(self.water_amount + self.electricity_amount)
+    }
+}
+
+// 所有商户出现过的其他费用名称（取并集，按首次出现的顺序），汇总表为每项各建一列
+pub fn extra_fee_names(merchants: &[MerchantBill]) -> Vec<String> {
+    let mut names: Vec<String> = Vec::new();
+    for fee in merchants.iter().flat_map(|b| &b.extra_fees) {
+        if !names.contains(&fee.name) {
+            names.push(fee.name.clone());
+        }
+    }
+    names
+}
+
+// 仅含汇总数据的JSON（供财务系统、看板使用）：商户数、各类用量与金额合计及总计
+pub fn summary_json(merchants: &[MerchantBill]) -> serde_json::Value {
+    let totals = Totals::from_merchants(merchants);
+    serde_json::json!({
+        "merchant_count": merchants.len(),
+        "water_usage": totals.water_usage,
+        "electricity_usage": totals.electricity_usage,
+        "water_amount": totals.water_amount,
+        "electricity_amount": totals.electricity_amount,
+        "labor_fee": totals.labor_fee,
+        "garbage_fee": totals.garbage_fee,
+        "extra_fees": totals.extra_fees,
+        "discount_amount": totals.discount_amount,
+        "received_amount": totals.received_amount,
+        "outstanding_amount": totals.outstanding_amount,
+        "grand_total": totals.grand_total,
+    })
+}
+
+// 非有限值（NaN/Inf）一律按0处理，避免在文档中渲染出"NaN"
+pub fn finite_or_zero(v: f64) -> f64 {
+    if v.is_finite() { v } else { 0.0 }
+}
+
+// 金额统一精确到分：所有金额运算结果都经此取整，保证显示值与合计值一致
+pub fn round_cents(amount: f64) -> f64 {
+    (finite_or_zero(amount) * 100.0).round() / 100.0
+}
+
+// 安全计算单价（金额/用量），用量为0或结果非有限值时返回0
+pub fn safe_rate(amount: f64, usage: f64) -> f64 {
+    if usage == 0.0 || !usage.is_finite() {
+        return 0.0;
+    }
+    finite_or_zero(amount / usage)
+}
+
+// 反序列化时未提供的字段使用空账单的默认值
+impl Default for MerchantBill {
+    fn default() -> Self {
+        Self::new(String::new(), 0.0, 0.0)
+    }
+}
+
 impl MerchantBill {
     pub fn new(merchant_name: String, water_unit_price: f64, electricity_unit_price: f64) -> Self {
         Self {
             merchant_name,
             shop_code: String::new(),
-            water_unit_price,
-            electricity_unit_price,
+            contact_name: None,
+            tax_id: None,
+            water_unit_price: finite_or_zero(water_unit_price),
+            electricity_unit_price: finite_or_zero(electricity_unit_price),
             prev_water_reading: 0.0,
             curr_water_reading: 0.0,
             water_usage: 0.0,
             water_amount: 0.0,
+            water_usage_rounding: UsageRounding::None,
+            amount_rounding: RoundingMode::HalfUp,
+            electricity_tiers: None,
             electricity_meters: Vec::new(),
             electricity_usage: 0.0,
             electricity_amount: 0.0,
             water_electricity_labor_fee: 0.0,  // 水电人工费
             garbage_disposal_fee: 0.0,         // 垃圾处理费
+            extra_fees: Vec::new(),
+            discount: None,
+            discount_amount: 0.0,
             meter_reader: None,
             meter_date: None,
+            period_start: None,
+            period_end: None,
+            received_amount: None,
+            building_name: None,
+            unpriced: false,
             total_fee: 0.0,
             month: Local::now().format("%Y年%m月").to_string(),
         }
     }
 
     pub fn set_shop_code(&mut self, code: String) { self.shop_code = code; }
+
+    // 用于去重、比较的铺面编号（全角转半角），显示仍使用原始 shop_code
+    pub fn shop_code_key(&self) -> String {
+        normalize_width(&self.shop_code)
+    }
     pub fn set_meter_info(&mut self, reader: Option<String>, date: Option<String>) {
         self.meter_reader = reader;
         self.meter_date = date;
     }
 
+    // 计费周期：起止日期均为空时不显示
+    pub fn set_billing_period(&mut self, start: Option<String>, end: Option<String>) {
+        self.period_start = start;
+        self.period_end = end;
+    }
+
     pub fn set_water_readings(&mut self, prev: f64, curr: f64) {
+        let (prev, curr) = (finite_or_zero(prev), finite_or_zero(curr));
         self.prev_water_reading = prev;
         self.curr_water_reading = curr;
-        self.water_usage = (curr - prev).max(0.0);
-        // 水费金额四舍五入到"元"（整数）
-        self.water_amount = (self.water_usage * self.water_unit_price).round();
+        self.water_usage = self.water_usage_rounding.apply((curr - prev).max(0.0));
+        // 水费金额按进位规则取整（默认到"元"）
+        self.water_amount = self.amount_rounding.round(self.water_usage * self.water_unit_price);
+        self.update_totals();
+    }
+
+    // 设置用水量取整方式，并按已有读数重新计算水费
+    pub fn set_water_usage_rounding(&mut self, rounding: UsageRounding) {
+        self.water_usage_rounding = rounding;
+        self.set_water_readings(self.prev_water_reading, self.curr_water_reading);
+    }
+
+    // 设置金额进位规则，并按已有读数重新计算水费与各电表金额
+    pub fn set_amount_rounding(&mut self, rounding: RoundingMode) {
+        self.amount_rounding = rounding;
+        self.recompute_meter_amounts();
+        self.set_water_readings(self.prev_water_reading, self.curr_water_reading);
+    }
+
+    // 设置阶梯电价（None 恢复按电费单价计价），并重新计算电费
+    pub fn set_electricity_tiers(&mut self, tiers: Option<TieredPrice>) {
+        self.electricity_tiers = tiers;
         self.update_totals();
     }
 
+    fn recompute_meter_amounts(&mut self) {
+        for meter in &mut self.electricity_meters {
+            meter.amount = self.amount_rounding.round(meter.usage * meter.unit_price.unwrap_or(self.electricity_unit_price));
+        }
+    }
+
     pub fn add_electricity_meter(&mut self, meter_id: String, prev: f64, curr: f64) {
-        let usage = (curr - prev).max(0.0);
-        // 行内展示用的单表金额（四舍五入到元，仅展示用）
-        let amount = (usage * self.electricity_unit_price).round();
+        self.push_electricity_meter(meter_id, prev, curr, None, None);
+    }
+
+    // 按分表单价计价的电表；存在分表单价时电费改为各表金额之和
+    pub fn add_priced_electricity_meter(&mut self, meter_id: String, prev: f64, curr: f64, unit_price: f64) {
+        self.push_electricity_meter(meter_id, prev, curr, Some(finite_or_zero(unit_price)), None);
+    }
+
+    // 指定表盘位数的电表：本期小于上期时按翻表计算用量（见 meter_usage）
+    pub fn add_rollover_electricity_meter(&mut self, meter_id: String, prev: f64, curr: f64, digits: u32) {
+        self.push_electricity_meter(meter_id, prev, curr, None, Some(digits));
+    }
+
+    // 设置第 index 块电表的表盘位数（None 恢复为本期小于上期按0计算），并重新计算用量与电费
+    pub fn set_meter_rollover_digits(&mut self, index: usize, digits: Option<u32>) {
+        if let Some(meter) = self.electricity_meters.get_mut(index) {
+            meter.rollover_digits = digits;
+            meter.usage = meter.reading_usage();
+        }
+        self.recompute_meter_amounts();
+        self.update_totals();
+    }
+
+    fn push_electricity_meter(&mut self, meter_id: String, prev: f64, curr: f64, unit_price: Option<f64>, rollover_digits: Option<u32>) {
+        let (prev, curr) = (finite_or_zero(prev), finite_or_zero(curr));
+        let usage = meter_usage(prev, curr, rollover_digits);
+        // 单表金额（取整到元）；未使用分表单价时仅展示用
+        let amount = self.amount_rounding.round(usage * unit_price.unwrap_or(self.electricity_unit_price));
         self.electricity_meters.push(ElectricityMeter {
             meter_id,
             prev_reading: prev,
             curr_reading: curr,
             usage,
             amount,
+            unit_price,
+            serial: None,
+            estimated: false,
+            rollover_digits,
         });
         self.update_totals();
     }
 
+    // 估抄：本月未能抄表，各电表按给定用量（如上月用量，按电表顺序）估算本期读数并标记；
+    // 未提供估算用量的电表沿用表格读数
+    pub fn mark_estimated(&mut self, estimated_usage: &[f64]) {
+        for (i, meter) in self.electricity_meters.iter_mut().enumerate() {
+            meter.estimated = true;
+            if let Some(usage) = estimated_usage.get(i).map(|u| finite_or_zero(*u).max(0.0)) {
+                meter.set_estimated_usage(usage);
+            }
+        }
+        self.recompute_meter_amounts();
+        self.update_totals();
+    }
+
+    // 按读数与单价重新计算各用量、金额与合计（如前端修改读数后提交的账单，其中的用量、金额字段可能已过时）
+    pub fn recalculate(&mut self) {
+        for meter in &mut self.electricity_meters {
+            meter.prev_reading = finite_or_zero(meter.prev_reading);
+            meter.curr_reading = finite_or_zero(meter.curr_reading);
+            meter.usage = meter.reading_usage();
+        }
+        self.recompute_meter_amounts();
+        // set_water_readings 会重算水费并调用 update_totals
+        self.set_water_readings(self.prev_water_reading, self.curr_water_reading);
+    }
+
+    // 为仅读数的账单统一定价：按新单价重算水费与各电表金额（保留分表单价）
+    pub fn apply_prices(&mut self, water_unit_price: f64, electricity_unit_price: f64) {
+        self.water_unit_price = finite_or_zero(water_unit_price);
+        self.electricity_unit_price = finite_or_zero(electricity_unit_price);
+        self.recompute_meter_amounts();
+        self.unpriced = false;
+        // set_water_readings 会重算水费并调用 update_totals
+        self.set_water_readings(self.prev_water_reading, self.curr_water_reading);
+    }
+
+    // 合表：多个分表合并为一行显示（上期取第一块表，本期取最后一块表，用量为各表之和），
+    // 账单中的各分表数据保持不变；不足两块表时返回None
+    pub fn combined_meter(&self) -> Option<ElectricityMeter> {
+        if self.electricity_meters.len() < 2 {
+            return None;
+        }
+        let first = self.electricity_meters.first()?;
+        let last = self.electricity_meters.last()?;
+        Some(ElectricityMeter {
+            meter_id: "合表".to_string(),
+            prev_reading: first.prev_reading,
+            curr_reading: last.curr_reading,
+            usage: self.electricity_usage,
+            amount: self.electricity_amount,
+            // 分表单价不同时合表行显示实际单价
+            unit_price: self.has_variable_pricing().then(|| self.effective_electricity_rate()),
+            serial: None,
+            estimated: self.electricity_meters.iter().any(|m| m.estimated),
+            rollover_digits: None,
+        })
+    }
+
+    pub fn has_per_meter_prices(&self) -> bool {
+        self.electricity_meters.iter().any(|m| m.unit_price.is_some())
+    }
+
+    // 电表实际使用的单价；阶梯电价下为实际单价（电费/用电量）
+    pub fn meter_unit_price(&self, meter: &ElectricityMeter) -> f64 {
+        match (meter.unit_price, &self.electricity_tiers) {
+            (Some(price), _) => price,
+            (None, Some(_)) => self.effective_electricity_rate(),
+            (None, None) => self.electricity_unit_price,
+        }
+    }
+
+    // 计价方式是否使单一"单价"无法反映实际单价（分表单价或阶梯电价）
+    pub fn has_variable_pricing(&self) -> bool {
+        self.has_per_meter_prices() || self.electricity_tiers.is_some()
+    }
+
+    // 实际单价 = 电费 / 计费用电量，用电量为0时为0
+    pub fn effective_electricity_rate(&self) -> f64 {
+        safe_rate(self.electricity_amount, self.electricity_usage)
+    }
+
+    // 实际单价说明文字，如 "实际单价：电费 0.8667 元/度（130 元 ÷ 150 度）"
+    pub fn effective_rate_text(&self) -> String {
+        format!("实际单价：电费 {} 元/度（{} 元 ÷ {} 度）",
+            format_price(self.effective_electricity_rate(), 2, 4), format_price(self.electricity_amount, 0, 2), self.electricity_usage)
+    }
+
     pub fn update_totals(&mut self) {
+        // 费用字段可能由调用方直接赋值，这里统一兜底
+        self.water_electricity_labor_fee = round_cents(self.water_electricity_labor_fee);
+        self.garbage_disposal_fee = round_cents(self.garbage_disposal_fee);
+        for fee in &mut self.extra_fees {
+            fee.amount = round_cents(finite_or_zero(fee.amount));
+        }
         // 总用电量
         self.electricity_usage = self.electricity_meters.iter().map(|m| m.usage).sum();
-        // 电费按规则：先合计总用电量，再乘单价，最后四舍五入到元
-        self.electricity_amount = (self.electricity_usage * self.electricity_unit_price).round();
+        // 电费按规则：先合计总用电量，再乘单价，最后按进位规则取整到元
+        // 存在分表单价时无法按总用量计价，改为各表分别计价取整后相加；阶梯电价按总用电量逐档计价后取整
+        self.electricity_amount = if self.has_per_meter_prices() {
+            self.electricity_meters.iter().map(|m| m.amount).sum()
+        } else if let Some(tiers) = &self.electricity_tiers {
+            self.amount_rounding.round(tiers.amount(self.electricity_usage))
+        } else {
+            self.amount_rounding.round(self.electricity_usage * self.electricity_unit_price)
+        };
         // 水费金额已在设置时四舍五入到元
         // 总费用根据电费总额(总用量*单价后四舍五入)、水费(四舍五入后)与其他费用直接相加
-        self.total_fee = self.water_amount + self.electricity_amount + self.water_electricity_labor_fee + self.garbage_disposal_fee;
+        let subtotal = self.water_amount + self.electricity_amount + self.water_electricity_labor_fee + self.garbage_disposal_fee + self.extra_fee_total();
+        // 优惠按小计扣减（比例优惠以小计为基数）
+        self.discount_amount = self.discount.map(|d| d.deduction(subtotal)).unwrap_or(0.0);
+        self.total_fee = round_cents(subtotal + self.discount_amount);
+    }
+
+    // 其他费用合计
+    pub fn extra_fee_total(&self) -> f64 {
+        self.extra_fees.iter().fold(0.0, |sum, f| round_cents(sum + f.amount))
+    }
+
+    // 指定名称的其他费用（同名多项相加），没有该项时为0
+    pub fn extra_fee(&self, name: &str) -> f64 {
+        self.extra_fees.iter().filter(|f| f.name == name).fold(0.0, |sum, f| round_cents(sum + f.amount))
+    }
+
+    // 比例优惠的显示文字，如 "10%"；固定金额优惠为空
+    pub fn discount_rate_text(&self) -> String {
+        match self.discount {
+            Some(Discount::Percent(rate)) => format!("{}%", format_price(rate * 100.0, 0, 2)),
+            _ => String::new(),
+        }
+    }
+
+    pub fn set_discount(&mut self, discount: Option<Discount>) {
+        self.discount = discount;
+        self.update_totals();
+    }
+
+    // 表格中未填（或为0）的水电人工费、垃圾处理费改用统一标准收费
+    pub fn apply_default_fees(&mut self, labor_fee: Option<f64>, garbage_fee: Option<f64>) {
+        if let Some(fee) = labor_fee.filter(|_| self.water_electricity_labor_fee == 0.0) {
+            self.water_electricity_labor_fee = fee;
+        }
+        if let Some(fee) = garbage_fee.filter(|_| self.garbage_disposal_fee == 0.0) {
+            self.garbage_disposal_fee = fee;
+        }
+        self.update_totals();
+    }
+
+    // 合并单元格显示的电费（先合计用量再取整）与各表分别取整之和的差额；阶梯电价不按单表计价，没有此差额
+    pub fn electricity_rounding_drift(&self) -> f64 {
+        if self.electricity_tiers.is_some() {
+            return 0.0;
+        }
+        let per_meter: f64 = self.electricity_meters.iter().map(|m| m.amount).sum();
+        self.electricity_amount - per_meter
+    }
+
+    // 差额 = 应收合计 - 实收金额（未填写实收时为None）
+    pub fn outstanding_amount(&self) -> Option<f64> {
+        self.received_amount.map(|received| round_cents(self.total_fee - received))
     }
 
     pub fn get_electricity_details(&self) -> String {
@@ -123,6 +689,103 @@ impl MerchantBill {
     }
 }
 
+/// 商户账单构建器：读数、电表、费用可按任意顺序设置，build 时统一计算一次用量、金额与合计，
+/// 避免逐个调用 set_water_readings、add_electricity_meter 等方法时因调用顺序不同导致金额不一致
+///
+/// ```
+/// use water_and_electricity_meter::MerchantBill;
+///
+/// let built = MerchantBill::builder("商户甲".to_string(), 3.5, 1.2)
+///     .labor_fee(20.0)
+///     .electricity_meter("1".to_string(), 100.0, 150.0)
+///     .shop_code("A01".to_string())
+///     .water_readings(10.0, 16.0)
+///     .garbage_fee(15.0)
+///     .build();
+///
+/// let mut bill = MerchantBill::new("商户甲".to_string(), 3.5, 1.2);
+/// bill.set_shop_code("A01".to_string());
+/// bill.set_water_readings(10.0, 16.0);
+/// bill.add_electricity_meter("1".to_string(), 100.0, 150.0);
+/// bill.water_electricity_labor_fee = 20.0;
+/// bill.garbage_disposal_fee = 15.0;
+/// bill.update_totals();
+///
+/// assert_eq!(built.water_amount, bill.water_amount);
+/// assert_eq!(built.electricity_amount, bill.electricity_amount);
+/// assert_eq!(built.total_fee, bill.total_fee);
+/// ```
+#[derive(Debug, Clone)]
+pub struct MerchantBillBuilder {
+    bill: MerchantBill,
+}
+
+impl MerchantBill {
+    pub fn builder(merchant_name: String, water_unit_price: f64, electricity_unit_price: f64) -> MerchantBillBuilder {
+        MerchantBillBuilder { bill: MerchantBill::new(merchant_name, water_unit_price, electricity_unit_price) }
+    }
+}
+
+impl MerchantBillBuilder {
+    pub fn shop_code(mut self, code: String) -> Self {
+        self.bill.shop_code = code;
+        self
+    }
+
+    pub fn water_readings(mut self, prev: f64, curr: f64) -> Self {
+        self.bill.prev_water_reading = prev;
+        self.bill.curr_water_reading = curr;
+        self
+    }
+
+    pub fn electricity_meter(self, meter_id: String, prev: f64, curr: f64) -> Self {
+        self.meter(meter_id, prev, curr, None)
+    }
+
+    // 按分表单价计价的电表，同 add_priced_electricity_meter
+    pub fn priced_electricity_meter(self, meter_id: String, prev: f64, curr: f64, unit_price: f64) -> Self {
+        self.meter(meter_id, prev, curr, Some(finite_or_zero(unit_price)))
+    }
+
+    fn meter(mut self, meter_id: String, prev: f64, curr: f64, unit_price: Option<f64>) -> Self {
+        self.bill.electricity_meters.push(ElectricityMeter {
+            meter_id,
+            prev_reading: prev,
+            curr_reading: curr,
+            unit_price,
+            ..ElectricityMeter::default()
+        });
+        self
+    }
+
+    pub fn labor_fee(mut self, fee: f64) -> Self {
+        self.bill.water_electricity_labor_fee = finite_or_zero(fee);
+        self
+    }
+
+    pub fn garbage_fee(mut self, fee: f64) -> Self {
+        self.bill.garbage_disposal_fee = finite_or_zero(fee);
+        self
+    }
+
+    pub fn meter_info(mut self, reader: Option<String>, date: Option<String>) -> Self {
+        self.bill.set_meter_info(reader, date);
+        self
+    }
+
+    pub fn amount_rounding(mut self, rounding: RoundingMode) -> Self {
+        self.bill.amount_rounding = rounding;
+        self
+    }
+
+    // 按已设置的读数、单价与费用计算用量、金额与总费用
+    pub fn build(self) -> MerchantBill {
+        let mut bill = self.bill;
+        bill.recalculate();
+        bill
+    }
+}
+
 impl BillTemplate {
     pub fn new(month: String, year: String) -> Self {
         Self {
@@ -138,12 +801,13 @@ impl BillTemplate {
     }
 
     pub fn add_merchant(&mut self, merchant: MerchantBill) {
-        self.total_water_usage += merchant.water_usage;
-        self.total_electric_usage += merchant.electricity_usage;
-        self.total_water_amount += merchant.water_amount;
-        self.total_electric_amount += merchant.electricity_amount;
-        self.grand_total += merchant.total_fee;
         self.merchants.push(merchant);
+        let totals = Totals::from_merchants(&self.merchants);
+        self.total_water_usage = totals.water_usage;
+        self.total_electric_usage = totals.electricity_usage;
+        self.total_water_amount = totals.water_amount;
+        self.total_electric_amount = totals.electricity_amount;
+        self.grand_total = totals.grand_total;
     }
 }
 
@@ -166,21 +830,141 @@ pub struct HeadersMap<'a> {
 
 fn normalize(s: &str) -> String { s.trim().to_lowercase() }
 
-fn find_electricity_columns(headers: &[String], prefix: &str) -> Result<Vec<(usize, usize)>> {
+// 电表表头匹配：表头须以完整模式（前缀+编号+上期读数等）开头，其后只允许单位等注释，
+// 如 "电表1上期读数（度）"；避免前缀过于宽泛（如 "表"）时误匹配 "水表1上期读数" 之类的列
+pub fn meter_header_matches(header: &str, pattern: &str) -> bool {
+    match header.strip_prefix(pattern) {
+        Some(rest) => !rest.starts_with(|c: char| c.is_alphanumeric()),
+        None => false,
+    }
+}
+
+// 读取时识别的列：逻辑字段名、可识别的表头（含别名，"N"为电表编号）、是否必需
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ColumnSpec {
+    pub field: &'static str,
+    pub headers: &'static [&'static str],
+    pub required: bool,
+    pub note: &'static str,
+}
+
+// 与 read_excel_bytes / read_csv_bytes 的列查找保持一致，供前端构建表头映射
+pub const COLUMN_SCHEMA: &[ColumnSpec] = &[
+    ColumnSpec { field: "shop_code", headers: &["铺面编号"], required: true, note: "" },
+    ColumnSpec { field: "merchant_name", headers: &["店铺名称"], required: true, note: "" },
+    ColumnSpec { field: "prev_water_reading", headers: &["上期水表读数"], required: true, note: "" },
+    ColumnSpec { field: "curr_water_reading", headers: &["本期水表读数"], required: true, note: "" },
+    ColumnSpec { field: "meter_prev_reading", headers: &["电表N上期读数"], required: true, note: "至少需要电表1；表头写法可通过 meter_header_templates 配置，多个前缀可通过 electricity_prefixes 配置" },
+    ColumnSpec { field: "meter_curr_reading", headers: &["电表N本期读数"], required: true, note: "至少需要电表1；表头写法可通过 meter_header_templates 配置，多个前缀可通过 electricity_prefixes 配置" },
+    ColumnSpec { field: "water_unit_price", headers: &["水费单价"], required: true, note: "与电费单价同时缺失时按仅读数模式读取" },
+    ColumnSpec { field: "electricity_unit_price", headers: &["电费单价"], required: true, note: "与水费单价同时缺失时按仅读数模式读取" },
+    ColumnSpec { field: "meter_unit_price", headers: &["电表N单价"], required: false, note: "分表单价" },
+    ColumnSpec { field: "meter_serial", headers: &["电表N编号"], required: false, note: "电表表号" },
+    ColumnSpec { field: "meter_rollover_digits", headers: &["电表N位数"], required: false, note: "表盘位数，本期小于上期时按翻表计算用量" },
+    ColumnSpec { field: "water_electricity_labor_fee", headers: &["水电人工费"], required: false, note: "缺失或为0时可使用标准收费" },
+    ColumnSpec { field: "garbage_disposal_fee", headers: &["垃圾处理费"], required: false, note: "缺失或为0时可使用标准收费" },
+    ColumnSpec { field: "contact_name", headers: &["户主姓名", "联系人"], required: false, note: "" },
+    ColumnSpec { field: "tax_id", headers: &["纳税人识别号", "税号"], required: false, note: "开票用，显示在信息行" },
+    ColumnSpec { field: "month", headers: &["账单月份"], required: false, note: "" },
+    ColumnSpec { field: "period_start", headers: &["计费起始日期", "计费开始日期"], required: false, note: "与计费截止日期一起显示为「计费周期：X 至 Y」" },
+    ColumnSpec { field: "period_end", headers: &["计费截止日期", "计费结束日期"], required: false, note: "与计费起始日期一起显示为「计费周期：X 至 Y」" },
+    ColumnSpec { field: "building_name", headers: &["小区名称"], required: false, note: "" },
+    ColumnSpec { field: "received_amount", headers: &["实收金额"], required: false, note: "仅用于对账" },
+    ColumnSpec { field: "extra_fees", headers: &["其他费用：名称"], required: false, note: "可有多列，如「其他费用：停车费」；汇总表按名称各占一列" },
+    ColumnSpec { field: "discount", headers: &["优惠", "折扣"], required: false, note: "固定金额或比例（如 -10%）" },
+    ColumnSpec { field: "estimated", headers: &["免抄", "估抄"], required: false, note: "标记后按 usage_history 中的上月用量估算电表读数" },
+];
+
+// 其他费用列：表头为"其他费用：名称"（冒号可为全角或半角），返回列下标与费用名称
+fn find_extra_fee_columns(headers: &[String]) -> Vec<(usize, String)> {
+    headers
+        .iter()
+        .enumerate()
+        .filter_map(|(i, h)| {
+            let name = h.trim().strip_prefix(EXTRA_FEE_HEADER_PREFIX)?.strip_prefix(['：', ':'])?.trim();
+            (!name.is_empty()).then(|| (i, name.to_string()))
+        })
+        .collect()
+}
+
+// 水费单价、电费单价列；两列都没有时为"仅读数"输入（返回None，金额留待之后统一定价），
+// 只缺其中一列多半是表头写错，仍按错误处理
+fn find_price_columns(headers: &[String]) -> Result<Option<(usize, usize)>> {
+    let water = headers.iter().position(|h| h.contains("水费单价"));
+    let electricity = headers.iter().position(|h| h.contains("电费单价"));
+    match (water, electricity) {
+        (Some(w), Some(e)) => Ok(Some((w, e))),
+        (None, None) => {
+            log_line!("警告：未找到水费单价、电费单价列，按仅读数模式读取，金额为0，需另行定价");
+            Ok(None)
+        }
+        (None, _) => anyhow::bail!("找不到水费单价列"),
+        (_, None) => anyhow::bail!("找不到电费单价列"),
+    }
+}
+
+// 常见的电表表头写法，可整体传入 ReadOptions::meter_header_templates
+pub const COMMON_METER_HEADER_TEMPLATES: &[&str] = &["电表{n}", "{n}号电表", "电表（{n}）", "电表({n})"];
+
+// 电表名称模板（{n} 为电表编号），按顺序尝试；未配置时为 "前缀{n}"，
+// 配置了 electricity_prefixes 时每个前缀各一个模板（如 "电表{n}"、"用电{n}"）
+fn meter_header_templates(prefix: &str, options: &ReadOptions) -> Vec<String> {
+    if !options.meter_header_templates.is_empty() {
+        return options.meter_header_templates.clone();
+    }
+    let mut templates = vec![format!("{}{{n}}", prefix)];
+    for extra in &options.electricity_prefixes {
+        let template = format!("{}{{n}}", extra.trim());
+        if !extra.trim().is_empty() && !templates.contains(&template) {
+            templates.push(template);
+        }
+    }
+    templates
+}
+
+// 同一电表编号在多个前缀/模板下都有读数列（如同时有 "电表1上期读数" 与 "用电1上期读数"）时，
+// 只会使用先匹配到的一组，返回提示信息以免另一组数据被悄悄忽略
+fn meter_id_collisions(headers: &[String], templates: &[String], meter_count: usize) -> Vec<String> {
+    (1..=meter_count)
+        .filter_map(|meter_id| {
+            let mut columns: Vec<usize> = Vec::new();
+            for template in templates {
+                if let Some(i) = find_meter_column(headers, std::slice::from_ref(template), meter_id, "上期读数").filter(|i| !columns.contains(i)) {
+                    columns.push(i);
+                }
+            }
+            (columns.len() > 1).then(|| {
+                let names: Vec<String> = columns.iter().map(|&i| format!("「{}」", headers[i])).collect();
+                format!("电表{}同时出现在{}列，按{}读取，其余列已忽略，请确认是否为同一块电表", meter_id, names.join("、"), names[0])
+            })
+        })
+        .collect()
+}
+
+// 按 模板+后缀 查找电表相关列（锚定匹配，见 meter_header_matches）；
+// "上期读数""本期读数" 也接受省略"读数"的写法，如 "1号电表上期"
+fn find_meter_column(headers: &[String], templates: &[String], meter_id: usize, suffix: &str) -> Option<usize> {
+    let short_suffix = suffix.strip_suffix("读数").filter(|s| !s.is_empty());
+    templates.iter().find_map(|template| {
+        let name = template.replace("{n}", &meter_id.to_string());
+        std::iter::once(suffix).chain(short_suffix).find_map(|suffix| {
+            let pattern = normalize(&format!("{}{}", name, suffix));
+            headers.iter().position(|h| meter_header_matches(&normalize(h), &pattern))
+        })
+    })
+}
+
+fn find_electricity_columns(headers: &[String], templates: &[String]) -> Result<Vec<(usize, usize)>> {
     let mut columns = Vec::new();
-    let headers_norm: Vec<String> = headers.iter().map(|h| normalize(h)).collect();
     
     // 查找电表列的模式：电表1上期读数、电表1本期读数、电表2上期读数、电表2本期读数...
     let mut meter_id = 1;
     loop {
-        let prev_pattern = format!("{}{}上期读数", prefix, meter_id);
-        let curr_pattern = format!("{}{}本期读数", prefix, meter_id);
-        
-        let prev_idx = headers_norm.iter().position(|h| h.contains(&normalize(&prev_pattern)));
-        let curr_idx = headers_norm.iter().position(|h| h.contains(&normalize(&curr_pattern)));
+        let prev_idx = find_meter_column(headers, templates, meter_id, "上期读数");
+        let curr_idx = find_meter_column(headers, templates, meter_id, "本期读数");
         
-        if prev_idx.is_some() && curr_idx.is_some() {
-            columns.push((prev_idx.unwrap(), curr_idx.unwrap()));
+        if let (Some(prev_idx), Some(curr_idx)) = (prev_idx, curr_idx) {
+            columns.push((prev_idx, curr_idx));
             meter_id += 1;
         } else {
             break;
@@ -194,554 +978,4561 @@ fn find_electricity_columns(headers: &[String], prefix: &str) -> Result<Vec<(usi
     Ok(columns)
 }
 
-// 已不再使用的函数移除，避免未使用告警
+// 可选的分表单价列："电表1单价"、"电表2单价"...，与电表列一一对应
+fn find_meter_price_columns(headers: &[String], templates: &[String], meter_count: usize) -> Vec<Option<usize>> {
+    (1..=meter_count)
+        .map(|meter_id| find_meter_column(headers, templates, meter_id, "单价"))
+        .collect()
+}
 
-fn as_f64(cell: &DataType) -> f64 {
-    match cell {
-        DataType::Float(f) => *f,
-        DataType::Int(i) => *i as f64,
-        DataType::String(s) => s.trim().parse::<f64>().unwrap_or(0.0),
-        _ => 0.0,
+// 可选的电表编号列："电表1编号"、"电表2编号"...（表号，便于资产核对）
+fn find_meter_serial_columns(headers: &[String], templates: &[String], meter_count: usize) -> Vec<Option<usize>> {
+    (1..=meter_count)
+        .map(|meter_id| find_meter_column(headers, templates, meter_id, "编号"))
+        .collect()
+}
+
+// 可选的表盘位数列："电表1位数"、"电表2位数"...，填写后该表本期小于上期按翻表计算用量
+fn find_meter_digits_columns(headers: &[String], templates: &[String], meter_count: usize) -> Vec<Option<usize>> {
+    (1..=meter_count)
+        .map(|meter_id| find_meter_column(headers, templates, meter_id, "位数"))
+        .collect()
+}
+
+// 表盘位数：1~MAX_ROLLOVER_DIGITS 的整数，空白为None，其他值记为解析错误
+fn rollover_digits(result: Result<f64, ParseNumError>, errors: &mut Vec<String>, line: usize, column: &str) -> Option<u32> {
+    let digits = optional_amount(result, errors, line, column)?;
+    if digits.fract() == 0.0 && (1.0..=MAX_ROLLOVER_DIGITS as f64).contains(&digits) {
+        Some(digits as u32)
+    } else {
+        errors.push(format!("第{}行「{}」应为1~{}的整数: {}，已按不翻表计算", line, column, MAX_ROLLOVER_DIGITS, digits));
+        None
     }
 }
 
-pub struct GenerateOptions {
-    pub custom_title: Option<String>,
-    pub per_page: usize,
+// 已不再使用的函数移除，避免未使用告警
+
+// 数值解析错误：空单元格单独区分，调用方可按0处理
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParseNumError {
+    Empty,
+    Invalid(String),
+    // Excel 公式错误单元格（#REF!、#DIV/0! 等）
+    FormulaError(String),
 }
 
-pub fn generate_word_document_with_template(
-    merchants: &[MerchantBill],
-    options: Option<GenerateOptions>,
-) -> Result<Vec<u8>, anyhow::Error> {
-    // 生成专业的抄表计费通知单格式（表格版）
-    use docx_rs::*;
-    
-    let mut doc = Docx::new();
+impl std::fmt::Display for ParseNumError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParseNumError::Empty => write!(f, "空值"),
+            ParseNumError::Invalid(raw) => write!(f, "无法解析数值: {}", raw),
+            ParseNumError::FormulaError(e) => write!(f, "公式错误 {}，请检查该单元格的公式", e),
+        }
+    }
+}
 
-    let per_page = options.as_ref().map(|o| o.per_page).unwrap_or(1);
+impl std::error::Error for ParseNumError {}
 
-    // 根据每页数量动态调整字体大小
-    // 表格字体和表头字体都使用与标题一样的大小
-    let (title_size, info_size, header_size, data_size, notice_size, row_height_header, row_height_data) = match per_page {
-        1 => (24, 18, 24, 24, 12, 480.0, 430.0),  // 一页一份
-        2 => (22, 16, 22, 22, 11, 420.0, 380.0),  // 一页两份
-        3 => (20, 14, 20, 20, 10, 350.0, 330.0),   // 一页三份
-        _ => (18, 12, 18, 18, 9, 310.0, 290.0),   // 一页四份或更多
-    };
+// 全角ASCII字符（Ａ－１、（）等）与全角空格转为半角，并去掉首尾空白；用于铺面编号等的匹配
+pub fn normalize_width(s: &str) -> String {
+    s.chars()
+        .map(|c| match c {
+            '\u{FF01}'..='\u{FF5E}' => char::from_u32(c as u32 - 0xFEE0).unwrap_or(c),
+            '\u{3000}' => ' ',
+            _ => c,
+        })
+        .collect::<String>()
+        .trim()
+        .to_string()
+}
 
-    // 为每个商家生成通知单
-    for (index, bill) in merchants.iter().enumerate() {
-        let now = Local::now();
-        let year = now.year();
-        let month = now.month();
-        let day = now.day();
+// parse_amount 可去掉的单位后缀（英文单位不区分大小写）；较长的写法在前，避免 "立方米" 只去掉 "米"
+const READING_UNITS: &[&str] = &["元/立方米", "元/度", "元/吨", "立方米", "立方", "kWh", "m³", "m3", "度", "吨"];
 
-        // 标题：自定义或默认 "yyyy年MM月抄表计费通知单"
-        let title = options
-            .as_ref()
-            .and_then(|o| o.custom_title.clone())
-            .unwrap_or_else(|| format!("{}年{:02}月抄表计费通知单", year, month));
-        doc = doc.add_paragraph(
-            Paragraph::new()
-                .add_run(Run::new().add_text(&title).bold().size(title_size))
-                .align(AlignmentType::Center)
-        );
+// 统一的金额/读数解析：支持全角数字、货币符号（￥/¥/$/元）、单位（度/吨/立方米/kWh）、千分位、科学计数法与百分号
+pub fn parse_amount(raw: &str) -> Result<f64, ParseNumError> {
+    let invalid = || ParseNumError::Invalid(raw.trim().to_string());
 
-        // 编号和基本信息行（编号使用CSV的铺面编号；抄表人/日期来自页面输入）
-        let meter_reader = bill.meter_reader.clone().unwrap_or_else(|| "".to_string());
-        let meter_date = bill.meter_date.clone().unwrap_or_else(|| format!("{}年{:02}月{:02}日", year, month, day));
-        let info_text = format!("编号：\t{}\t姓名\t{}\t抄表人：\t{}\t抄表日期：{}",
-            bill.shop_code, bill.merchant_name, meter_reader, meter_date);
-        doc = doc.add_paragraph(
-            Paragraph::new()
-                .add_run(Run::new().add_text(&info_text).size(info_size))
-        );
-        
-        // 空行
-        doc = doc.add_paragraph(Paragraph::new());
-        
-        // 创建费用明细表格
-        let mut table_rows = vec![
-            TableRow::new(vec![
-                TableCell::new().add_paragraph(Paragraph::new().add_run(Run::new().add_text("项目").bold().size(header_size)).align(AlignmentType::Center)),
-                TableCell::new().add_paragraph(Paragraph::new().add_run(Run::new().add_text("上月表底").bold().size(header_size)).align(AlignmentType::Center)),
-                TableCell::new().add_paragraph(Paragraph::new().add_run(Run::new().add_text("本月抄表数").bold().size(header_size)).align(AlignmentType::Center)),
-                TableCell::new().add_paragraph(Paragraph::new().add_run(Run::new().add_text("实用度数").bold().size(header_size)).align(AlignmentType::Center)),
-                TableCell::new().add_paragraph(Paragraph::new().add_run(Run::new().add_text("公共分摊").bold().size(header_size)).align(AlignmentType::Center)),
-                TableCell::new().add_paragraph(Paragraph::new().add_run(Run::new().add_text("单价（元）").bold().size(header_size)).align(AlignmentType::Center)),
-                TableCell::new().add_paragraph(Paragraph::new().add_run(Run::new().add_text("金额").bold().size(header_size)).align(AlignmentType::Center)),
-            ])
-            .row_height(row_height_header),
-        ];
-        
-        // 为每个电表生成行；若电表>1，仅在最后一行显示合并后的“金额”
-        let meters_len = bill.electricity_meters.len();
-        for (meter_idx, meter) in bill.electricity_meters.iter().enumerate() {
-            let meter_name = if meters_len == 1 {
-                "电表".to_string()
-            } else {
-                format!("电表{}", meter_idx + 1)
-            };
+    // 全角字符转半角
+    let halfwidth: String = raw
+        .chars()
+        .map(|c| match c {
+            '０'..='９' | '．' | '，' | '－' | '＋' | '％' | '＄' => {
+                char::from_u32(c as u32 - 0xFEE0).unwrap_or(c)
+            }
+            '　' => ' ',
+            _ => c,
+        })
+        .collect();
+    let mut s = halfwidth.trim();
+    if s.is_empty() {
+        return Err(ParseNumError::Empty);
+    }
+
+    // 符号可能写在货币符号之前，如 "-￥5"
+    let negative = s.starts_with('-');
+    if negative || s.starts_with('+') {
+        s = s[1..].trim_start();
+    }
+    for symbol in ["￥", "¥", "$", "RMB", "CNY"] {
+        if let Some(rest) = s.strip_prefix(symbol) {
+            s = rest.trim_start();
+            break;
+        }
+    }
+    // 读数/单价后的单位，如 "1234度"、"56吨"、"0.8元/度"
+    for unit in READING_UNITS {
+        if s.len() > unit.len() && s.is_char_boundary(s.len() - unit.len()) && s[s.len() - unit.len()..].eq_ignore_ascii_case(unit) {
+            s = s[..s.len() - unit.len()].trim_end();
+            break;
+        }
+    }
+    if let Some(rest) = s.strip_suffix('元') {
+        s = rest.trim_end();
+    }
+    let percent = s.ends_with('%');
+    if percent {
+        s = s[..s.len() - 1].trim_end();
+    }
 
-            // 单价与金额列：若>1电表，对这两列做纵向合并（类似Excel合并单元格）
-            // 合并策略：
-            // - 单价列：首行显示单价并 vMerge Restart，其余行 vMerge Continue
-            // - 金额列：首行显示合并后的电费总额并 vMerge Restart，其余行 vMerge Continue
-            // 若仅1个电表，则正常显示，无合并
+    // 千分位：逗号分组必须是标准的3位一组，避免把"1,5"之类误当成15
+    let plain = if s.contains(',') {
+        let int_part = s.split(['.', 'e', 'E']).next().unwrap_or("");
+        let groups: Vec<&str> = int_part.split(',').collect();
+        let well_formed = !groups[0].is_empty()
+            && groups[0].len() <= 3
+            && groups[1..].iter().all(|g| g.len() == 3);
+        if !well_formed || s[int_part.len()..].contains(',') {
+            return Err(invalid());
+        }
+        s.replace(',', "")
+    } else {
+        s.to_string()
+    };
 
-            // 构造单价列单元格（第6列）
-            let unit_price_cell = if meters_len > 1 {
-                if meter_idx == 0 {
-                    TableCell::new()
-                        .vertical_merge(VMergeType::Restart)
-                        .add_paragraph(Paragraph::new().add_run(Run::new().add_text(&format!("{:.2}", bill.electricity_unit_price)).size(data_size)).align(AlignmentType::Center))
-                } else {
-                    TableCell::new()
-                        .vertical_merge(VMergeType::Continue)
-                }
-            } else {
-                TableCell::new().add_paragraph(Paragraph::new().add_run(Run::new().add_text(&format!("{:.2}", bill.electricity_unit_price)).size(data_size)).align(AlignmentType::Center))
-            };
+    // f64::from_str 会接受 "inf"/"NaN"，这里只允许数字形式
+    if !plain.chars().next().is_some_and(|c| c.is_ascii_digit() || c == '.') {
+        return Err(invalid());
+    }
+    let value = plain.parse::<f64>().map_err(|_| invalid())?;
+    if !value.is_finite() {
+        return Err(invalid());
+    }
+    let value = if percent { value / 100.0 } else { value };
+    Ok(if negative { -value } else { value })
+}
 
-            // 构造金额列单元格（第7列）
-            let amount_cell = if meters_len > 1 {
-                if meter_idx == 0 {
-                    TableCell::new()
-                        .vertical_merge(VMergeType::Restart)
-                        .add_paragraph(Paragraph::new().add_run(Run::new().add_text(&format!("{:.0}", bill.electricity_amount)).size(data_size)).align(AlignmentType::Center))
-                } else {
-                    TableCell::new()
-                        .vertical_merge(VMergeType::Continue)
-                }
-            } else {
-                TableCell::new().add_paragraph(Paragraph::new().add_run(Run::new().add_text(&format!("{:.0}", bill.electricity_amount)).size(data_size)).align(AlignmentType::Center))
-            };
+// Excel 单元格地址，如 (0, 5) -> "A5"；col 从0开始，line 为表格中的行号
+pub fn excel_cell_ref(col: usize, line: usize) -> String {
+    let mut letters = Vec::new();
+    let mut n = col + 1;
+    while n > 0 {
+        letters.push((b'A' + ((n - 1) % 26) as u8) as char);
+        n = (n - 1) / 26;
+    }
+    letters.iter().rev().collect::<String>() + &line.to_string()
+}
 
-            table_rows.push(TableRow::new(vec![
-                TableCell::new().add_paragraph(Paragraph::new().add_run(Run::new().add_text(&meter_name).size(data_size)).align(AlignmentType::Center)),
-                TableCell::new().add_paragraph(Paragraph::new().add_run(Run::new().add_text(&format!("{:.0}", meter.prev_reading)).size(data_size)).align(AlignmentType::Center)),
-                TableCell::new().add_paragraph(Paragraph::new().add_run(Run::new().add_text(&format!("{:.0}", meter.curr_reading)).size(data_size)).align(AlignmentType::Center)),
-                TableCell::new().add_paragraph(Paragraph::new().add_run(Run::new().add_text(&format!("{:.0}", meter.usage)).size(data_size)).align(AlignmentType::Center)),
-                TableCell::new().add_paragraph(Paragraph::new().add_run(Run::new().add_text("")).align(AlignmentType::Center)),
-                unit_price_cell,
-                amount_cell,
-            ])
-            .row_height(row_height_data));
-        }
-        
-        // 如果没有电表，添加一个空行
-        if bill.electricity_meters.is_empty() {
-            table_rows.push(TableRow::new(vec![
-                TableCell::new().add_paragraph(Paragraph::new().add_run(Run::new().add_text("电表").size(data_size)).align(AlignmentType::Center)),
-                TableCell::new().add_paragraph(Paragraph::new().add_run(Run::new().add_text("0").size(data_size)).align(AlignmentType::Center)),
-                TableCell::new().add_paragraph(Paragraph::new().add_run(Run::new().add_text("0").size(data_size)).align(AlignmentType::Center)),
-                TableCell::new().add_paragraph(Paragraph::new().add_run(Run::new().add_text("0").size(data_size)).align(AlignmentType::Center)),
-                TableCell::new().add_paragraph(Paragraph::new().add_run(Run::new().add_text("")).align(AlignmentType::Center)),
-                TableCell::new().add_paragraph(Paragraph::new().add_run(Run::new().add_text(&format!("{:.2}", bill.electricity_unit_price)).size(data_size)).align(AlignmentType::Center)),
-                TableCell::new().add_paragraph(Paragraph::new().add_run(Run::new().add_text("0").size(data_size)).align(AlignmentType::Center)),
-            ])
-            .row_height(row_height_data));
+// 文本型单元格的内容；整数值按完整数字输出（纯数字的编号被Excel存成数值时避免显示为科学计数法）
+fn cell_text(cell: &DataType) -> String {
+    match cell {
+        DataType::Float(f) if f.fract() == 0.0 && f.abs() < 1e21 => format!("{:.0}", f),
+        other => other.to_string().trim().to_string(),
+    }
+}
+
+fn cell_amount(cell: Option<&DataType>) -> Result<f64, ParseNumError> {
+    match cell {
+        Some(DataType::Float(f)) => Ok(*f),
+        Some(DataType::Int(i)) => Ok(*i as f64),
+        Some(DataType::String(s)) => parse_amount(s),
+        None | Some(DataType::Empty) => Err(ParseNumError::Empty),
+        Some(DataType::Error(e)) => Err(ParseNumError::FormulaError(e.to_string())),
+        _ => Ok(0.0),
+    }
+}
+
+// Excel 中的优惠：文本按 Discount::parse 解析（支持 "-10%"），数值单元格为固定金额
+fn cell_discount(cell: Option<&DataType>) -> Result<Option<Discount>, ParseNumError> {
+    match cell {
+        Some(DataType::String(s)) => Discount::parse(s),
+        _ => cell_amount(cell).map(|v| Some(v.abs()).filter(|v| *v != 0.0).map(Discount::Amount)),
+    }
+}
+
+// 可选金额列：空值为None，格式错误记录后同样为None
+fn optional_amount(result: Result<f64, ParseNumError>, errors: &mut Vec<String>, line: usize, column: &str) -> Option<f64> {
+    match result {
+        Ok(v) => Some(v),
+        Err(ParseNumError::Empty) => None,
+        Err(e) => {
+            errors.push(format!("第{}行「{}」{}", line, column, e));
+            None
         }
-        
-        // 添加水费行（去掉"损耗/实用"子行，仅保留单价与金额）
-        table_rows.push(TableRow::new(vec![
-            TableCell::new().add_paragraph(Paragraph::new().add_run(Run::new().add_text("水费").size(data_size)).align(AlignmentType::Center)),
-            TableCell::new().add_paragraph(Paragraph::new().add_run(Run::new().add_text(&format!("{:.0}", bill.prev_water_reading)).size(data_size)).align(AlignmentType::Center)),
-            TableCell::new().add_paragraph(Paragraph::new().add_run(Run::new().add_text(&format!("{:.0}", bill.curr_water_reading)).size(data_size)).align(AlignmentType::Center)),
-            TableCell::new().add_paragraph(Paragraph::new().add_run(Run::new().add_text(&format!("{:.0}", bill.water_usage)).size(data_size)).align(AlignmentType::Center)),
-            TableCell::new().add_paragraph(Paragraph::new().add_run(Run::new().add_text("")).align(AlignmentType::Center)),
-            TableCell::new().add_paragraph(Paragraph::new().add_run(Run::new().add_text(&format!("{:.3}", bill.water_unit_price)).size(data_size)).align(AlignmentType::Center)),
-            TableCell::new().add_paragraph(Paragraph::new().add_run(Run::new().add_text(&format!("{:.0}", bill.water_amount)).size(data_size)).align(AlignmentType::Center)),
-        ])
-        .row_height(row_height_data));
-
-        table_rows.push(TableRow::new(vec![
-            TableCell::new().add_paragraph(Paragraph::new().add_run(Run::new().add_text("水电人工费").size(data_size)).align(AlignmentType::Center)),
-            TableCell::new().add_paragraph(Paragraph::new().add_run(Run::new().add_text("")).align(AlignmentType::Center)),
-            TableCell::new().add_paragraph(Paragraph::new().add_run(Run::new().add_text("")).align(AlignmentType::Center)),
-            TableCell::new().add_paragraph(Paragraph::new().add_run(Run::new().add_text("")).align(AlignmentType::Center)),
-            TableCell::new().add_paragraph(Paragraph::new().add_run(Run::new().add_text("")).align(AlignmentType::Center)),
-            TableCell::new().add_paragraph(Paragraph::new().add_run(Run::new().add_text("")).align(AlignmentType::Center)),
-            TableCell::new().add_paragraph(Paragraph::new().add_run(Run::new().add_text(&format!("{:.2}", bill.water_electricity_labor_fee)).size(data_size)).align(AlignmentType::Center))
-        ])
-        .row_height(row_height_data));
-
-        table_rows.push(TableRow::new(vec![
-            TableCell::new().add_paragraph(Paragraph::new().add_run(Run::new().add_text("垃圾处理费").size(data_size)).align(AlignmentType::Center)),
-            TableCell::new().add_paragraph(Paragraph::new().add_run(Run::new().add_text("")).align(AlignmentType::Center)),
-            TableCell::new().add_paragraph(Paragraph::new().add_run(Run::new().add_text("")).align(AlignmentType::Center)),
-            TableCell::new().add_paragraph(Paragraph::new().add_run(Run::new().add_text("")).align(AlignmentType::Center)),
-            TableCell::new().add_paragraph(Paragraph::new().add_run(Run::new().add_text("")).align(AlignmentType::Center)),
-            TableCell::new().add_paragraph(Paragraph::new().add_run(Run::new().add_text("")).align(AlignmentType::Center)),
-            TableCell::new().add_paragraph(Paragraph::new().add_run(Run::new().add_text(&format!("{:.2}", bill.garbage_disposal_fee)).size(data_size)).align(AlignmentType::Center))
-        ])
-        .row_height(row_height_data));
-
-        // 添加滞纳金行（占位，金额为0）
-        table_rows.push(TableRow::new(vec![
-            TableCell::new().add_paragraph(Paragraph::new().add_run(Run::new().add_text("滞纳金").size(data_size)).align(AlignmentType::Center)),
-            TableCell::new().add_paragraph(Paragraph::new().add_run(Run::new().add_text("")).align(AlignmentType::Center)),
-            TableCell::new().add_paragraph(Paragraph::new().add_run(Run::new().add_text("")).align(AlignmentType::Center)),
-            TableCell::new().add_paragraph(Paragraph::new().add_run(Run::new().add_text("")).align(AlignmentType::Center)),
-            TableCell::new().add_paragraph(Paragraph::new().add_run(Run::new().add_text("")).align(AlignmentType::Center)),
-            TableCell::new().add_paragraph(Paragraph::new().add_run(Run::new().add_text("")).align(AlignmentType::Center)),
-            TableCell::new().add_paragraph(Paragraph::new().add_run(Run::new().add_text("0.00").size(data_size)).align(AlignmentType::Center))
-        ])
-        .row_height(row_height_data));
-
-        // 添加广告费行（占位，金额为0）
-        table_rows.push(TableRow::new(vec![
-            TableCell::new().add_paragraph(Paragraph::new().add_run(Run::new().add_text("广告费").size(data_size)).align(AlignmentType::Center)),
-            TableCell::new().add_paragraph(Paragraph::new().add_run(Run::new().add_text("")).align(AlignmentType::Center)),
-            TableCell::new().add_paragraph(Paragraph::new().add_run(Run::new().add_text("")).align(AlignmentType::Center)),
-            TableCell::new().add_paragraph(Paragraph::new().add_run(Run::new().add_text("")).align(AlignmentType::Center)),
-            TableCell::new().add_paragraph(Paragraph::new().add_run(Run::new().add_text("")).align(AlignmentType::Center)),
-            TableCell::new().add_paragraph(Paragraph::new().add_run(Run::new().add_text("")).align(AlignmentType::Center)),
-            TableCell::new().add_paragraph(Paragraph::new().add_run(Run::new().add_text("0.00").size(data_size)).align(AlignmentType::Center))
-        ])
-        .row_height(row_height_data));
-
-        // 合计行（整行合并，先大写后小写，独占一行）
-        let total_val = bill.total_fee;
-        table_rows.push(TableRow::new(vec![
-            // 第一列：项目名称（"合计"）
-            TableCell::new().add_paragraph(Paragraph::new().add_run(Run::new().add_text("合计").bold().size(header_size)).align(AlignmentType::Center)),
-            // 第二列到第七列合并：显示大写和小写金额
-            TableCell::new()
-                .grid_span(6)
-                .add_paragraph(Paragraph::new().add_run(Run::new().add_text(&format!("大写：{}    小写：{:.2}", rmb_upper(total_val), total_val)).bold().size(header_size)).align(AlignmentType::Center))
-        ])
-        .row_height(row_height_header));
+    }
+}
 
-        let table = Table::new(table_rows);
-        
-        // 添加表格到文档
-        doc = doc.add_table(table);
-        
-        // 已合并其他费用与合计到主表，不再添加第二个表格或表外合计
-        
-        // 空行
-        doc = doc.add_paragraph(Paragraph::new());
-        
-        // 说明文字
-        let notice_text = "1、此单可对账不做凭证；\n\n2、每月5日前为收费时间，超期按5%收滞纳金或停电；\n\n3、以上费用如有不明或差\n请到管理处核对。";
-        doc = doc.add_paragraph(
-            Paragraph::new()
-                .add_run(Run::new().add_text(notice_text).size(notice_size))
-        );
-        
-        // 表格之间的分隔符，以及按每页数量分页
-        if index < merchants.len() - 1 {
-            // 页面分隔：每页显示 per_page 个表格
-            if per_page != 0 && ((index + 1) % per_page == 0) {
-                // 添加分页符
-                doc = doc.add_paragraph(Paragraph::new().add_run(Run::new().add_break(BreakType::Page)));
-            } else {
-                // 不分页时添加分隔线
-                doc = doc.add_paragraph(
-                    Paragraph::new()
-                        .add_run(Run::new().add_text("=".repeat(40)))
-                );
-            }
+// 优惠列：空值为无优惠，格式错误记录后同样按无优惠处理
+fn optional_discount(result: Result<Option<Discount>, ParseNumError>, errors: &mut Vec<String>, line: usize, column: &str) -> Option<Discount> {
+    match result {
+        Ok(discount) => discount,
+        Err(ParseNumError::Empty) => None,
+        Err(e) => {
+            errors.push(format!("第{}行「{}」{}", line, column, e));
+            None
         }
     }
+}
 
-    // 汇总表之前添加分页符，使其单独成页
-    // 只有在不是刚分完页的情况下才添加分页符
-    if per_page == 0 || merchants.len() % per_page != 0 {
-        doc = doc.add_paragraph(Paragraph::new().add_run(Run::new().add_break(BreakType::Page)));
+// 空值按0处理；格式错误同样按0处理并记录，读取结束后统一提示
+fn amount_or_zero(result: Result<f64, ParseNumError>, errors: &mut Vec<String>, line: usize, column: &str) -> f64 {
+    match result {
+        Ok(v) => v,
+        Err(ParseNumError::Empty) => 0.0,
+        Err(e) => {
+            errors.push(format!("第{}行「{}」{}", line, column, e));
+            0.0
+        }
     }
+}
 
-    // 添加汇总表格
-    doc = add_summary_table(doc, merchants)?;
-    
-    // 生成文档
-    let mut buf = Vec::new();
-    doc.build().pack(&mut std::io::Cursor::new(&mut buf))?;
-    Ok(buf)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GenerateOptions {
+    pub custom_title: Option<String>,
+    pub per_page: usize,
+    pub table_headers: TableHeaders,
+    // 日期格式：命名样式 cn/iso/slash 或 chrono 格式串，默认 "%Y年%m月%d日"
+    pub date_format: Option<String>,
+    // 信息行"姓名"处显示的字段
+    pub name_source: NameSource,
+    // 多电表取整差额不为0时，在表格下方注明
+    pub show_rounding_note: bool,
+    // 草稿：页眉标注"草稿 - 请勿发送"，合计金额显示为灰色
+    pub draft: bool,
+    // 水费、电费金额旁同时显示大写，如 "22（贰拾贰元整）"
+    pub capitalize_all_amounts: bool,
+    // 单价最多显示的小数位数，默认按实际精度显示（最多 PRICE_MAX_DECIMALS 位）
+    pub price_max_decimals: Option<usize>,
+    // 多电表时单价/金额列的合并方式
+    pub meter_merge_mode: MeterMergeMode,
+    // 小区/楼宇名称（页眉与文件名），表格中提供"小区名称"时以表格为准
+    pub building_name: Option<String>,
+    // 分表单价等计价方式下，在表格下方显示"实际单价"（金额/用量）
+    pub show_effective_rate: bool,
+    // 负数金额（扣减、预付抵扣等）的显示方式，负数金额同时以红色显示
+    pub negative_style: NegativeStyle,
+    // 费用表中水费行与电表行的先后顺序
+    pub row_order: RowOrder,
+    // 统一标准收费：表格未提供（或为0）水电人工费、垃圾处理费时使用
+    pub default_labor_fee: Option<f64>,
+    pub default_garbage_fee: Option<f64>,
+    // 合表：多块电表合并为一行"电表"显示（用量为各表之和），不逐表列出
+    pub combine_meters: bool,
+    // 单次生成的商家数量上限，避免误传超大文件生成上千页文档
+    pub max_merchants: usize,
+    // 抄表人、抄表日期的显示位置
+    pub meter_info_layout: MeterInfoLayout,
+    // 汇总表分组方式，默认不分组
+    pub summary_group_by: Option<SummaryGroup>,
+    // 金额、单价的小数点与千分位分隔符
+    pub money_format: MoneyFormat,
+    // 汇总表的费用列：哪些类别并入合计列、哪些单独成列
+    pub summary_columns: SummaryColumns,
+    // 单据编号起始值：设置后每份通知单依次显示"单据编号：No.0001"等（与铺面编号无关）
+    pub start_sequence: Option<u32>,
+    // 同一页的多份通知单之间显示 "====" 分隔线，关闭时以空行分隔
+    pub show_separator: bool,
+    // 每页页脚显示"本批合计：¥X"及页码
+    pub include_footer_total: bool,
+    // 费用表行高（twip，按最小行高）与表格正文字号（半磅），默认按每页份数自动选择
+    pub row_height: Option<f32>,
+    pub body_font_size: Option<usize>,
+    // 信息行中空字段（如未填写抄表人）的显示方式
+    pub empty_info_field: EmptyFieldStyle,
+    // 费用表显示"公共分摊"列（默认显示；不做分摊的楼宇可关闭，其余列左移）
+    pub show_shared_column: bool,
+    // 读数、用量的小数位数：None 时整数照常显示、有小数时最多显示2位（与计价用量一致）
+    pub usage_decimals: Option<usize>,
+    // 单价单元格附带单位，如 "0.80 元/度"、"2.850 元/吨"（默认不显示）
+    pub show_price_units: bool,
+    // 版式：HalfPage 为半页版式，每页固定上下排两份，缩小字号与页边距（此时忽略 per_page）
+    pub page_layout: PageLayout,
+    // 计费周期起止日期（如 "2025/07/01"），信息行显示"计费周期：X 至 Y"；表格中提供计费周期列时以表格为准
+    pub period_start: Option<String>,
+    pub period_end: Option<String>,
 }
 
-pub fn read_excel_file(file_path: &str, headers_map: &HeadersMap) -> Result<Vec<MerchantBill>> {
-    let mut workbook: Xlsx<_> = open_workbook(file_path)
-        .with_context(|| format!("无法打开Excel文件: {}", file_path))?;
-    let sheet_name = workbook.sheet_names()[0].clone();
-    let range = workbook
-        .worksheet_range(&sheet_name)
-        .with_context(|| format!("无法读取工作表: {}", sheet_name))??;
+// 第 index 份通知单（从0开始）的单据编号文本
+pub fn sequence_number_text(start: u32, index: usize) -> String {
+    format!("单据编号：No.{:04}", start as usize + index)
+}
 
-    let mut rows = range.rows();
-    let header_row = rows.next().context("Excel中缺少表头行")?;
-    let headers: Vec<String> = header_row.iter().map(|c| c.to_string()).collect();
-    
-    println!("调试：Excel表头: {:?}", headers);
-    
-    // 直接查找列索引，不使用find_indices
-    let code_i = headers.iter().position(|h| h.contains("铺面编号")).context("找不到铺面编号列")?;
-    let m_i = headers.iter().position(|h| h.contains("店铺名称")).context("找不到店铺名称列")?;
-    // 新排序：优先电表1，然后水表，上到下
-    let e1p_i = headers.iter().position(|h| h.contains("电表1上期读数")).context("找不到电表1上期读数列")?;
-    let e1c_i = headers.iter().position(|h| h.contains("电表1本期读数")).context("找不到电表1本期读数列")?;
-    let wp_i = headers.iter().position(|h| h.contains("上期水表读数")).context("找不到上期水表读数列")?;
-    let wc_i = headers.iter().position(|h| h.contains("本期水表读数")).context("找不到本期水表读数列")?;
-    let wprice_i = headers.iter().position(|h| h.contains("水费单价")).context("找不到水费单价列")?;
-    let eprice_i = headers.iter().position(|h| h.contains("电费单价")).context("找不到电费单价列")?;
+// 汇总表中的费用类别
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FeeCategory {
+    Water,
+    Electricity,
+    LaborFee,
+    GarbageFee,
+}
 
-    // 找到水电人工费和垃圾处理费列
-    let labor_fee_i = headers.iter().position(|h| h.contains("水电人工费")).context("找不到水电人工费列")?;
-    let garbage_fee_i = headers.iter().position(|h| h.contains("垃圾处理费")).context("找不到垃圾处理费列")?;
+impl FeeCategory {
+    pub fn label(&self) -> &'static str {
+        match self {
+            FeeCategory::Water => "水费",
+            FeeCategory::Electricity => "电费",
+            FeeCategory::LaborFee => "水电人工费",
+            FeeCategory::GarbageFee => "垃圾处理费",
+        }
+    }
 
-    // 找到所有电表相关的列（包含已知的电表1）
-    let mut electricity_columns = find_electricity_columns(&headers, headers_map.electricity_prefix)?;
-    // 确保电表1优先（若已存在则不重复）
-    if !electricity_columns.iter().any(|(p,c)| *p==e1p_i && *c==e1c_i) {
-        electricity_columns.insert(0, (e1p_i, e1c_i));
+    pub fn amount(&self, bill: &MerchantBill) -> f64 {
+        match self {
+            FeeCategory::Water => bill.water_amount,
+            FeeCategory::Electricity => bill.electricity_amount,
+            FeeCategory::LaborFee => bill.water_electricity_labor_fee,
+            FeeCategory::GarbageFee => bill.garbage_disposal_fee,
+        }
     }
 
-    println!("调试：Excel基础列索引 - 商家:{}, 水表上期:{}, 水表本期:{}, 水费单价:{}, 电费单价:{}, 水电人工费:{}, 垃圾处理费:{}", 
-             m_i, wp_i, wc_i, wprice_i, eprice_i, labor_fee_i, garbage_fee_i);
-    println!("调试：Excel电表列: {:?}", electricity_columns);
+    pub fn total(&self, totals: &Totals) -> f64 {
+        match self {
+            FeeCategory::Water => totals.water_amount,
+            FeeCategory::Electricity => totals.electricity_amount,
+            FeeCategory::LaborFee => totals.labor_fee,
+            FeeCategory::GarbageFee => totals.garbage_fee,
+        }
+    }
+}
 
-    let mut bills = Vec::new();
-    for row in rows {
-        if row.is_empty() { continue; }
-        let merchant_name = row.get(m_i).map(|c| c.to_string()).unwrap_or_default();
-        let shop_code = row.get(code_i).map(|c| c.to_string()).unwrap_or_default();
-        if merchant_name.trim().is_empty() { continue; }
-        
-        let water_price = row.get(wprice_i).map(as_f64).unwrap_or(0.0);
-        let electricity_price = row.get(eprice_i).map(as_f64).unwrap_or(0.0);
-        let prev_water = row.get(wp_i).map(as_f64).unwrap_or(0.0);
-        let curr_water = row.get(wc_i).map(as_f64).unwrap_or(0.0);
+// 汇总表费用列配置；默认水费、电费并入"水电费合计"，人工费、垃圾处理费单独成列。
+// "优惠""总价"（及docx中的"实收""差额"）列始终按数据自动显示，总价包含全部费用
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SummaryColumns {
+    // 并入合计列的类别，为空时不显示合计列
+    pub combined: Vec<FeeCategory>,
+    // 单独成列的类别，按顺序显示在合计列之后
+    pub separate: Vec<FeeCategory>,
+}
 
-        let mut bill = MerchantBill::new(merchant_name, water_price, electricity_price);
-        bill.set_water_readings(prev_water, curr_water);
-        bill.set_shop_code(shop_code);
+impl Default for SummaryColumns {
+    fn default() -> Self {
+        Self {
+            combined: vec![FeeCategory::Water, FeeCategory::Electricity],
+            separate: vec![FeeCategory::LaborFee, FeeCategory::GarbageFee],
+        }
+    }
+}
 
-        // 处理每个电表
-        for (meter_id, (prev_col, curr_col)) in electricity_columns.iter().enumerate() {
-            let prev_reading = row.get(*prev_col).map(as_f64).unwrap_or(0.0);
-            let curr_reading = row.get(*curr_col).map(as_f64).unwrap_or(0.0);
-            if prev_reading > 0.0 || curr_reading > 0.0 {
-                bill.add_electricity_meter(format!("{}", meter_id + 1), prev_reading, curr_reading);
-            }
+impl SummaryColumns {
+    // 合计列表头：水费+电费为"水电费合计（元）"，其他组合列出各类别
+    fn combined_title(&self) -> String {
+        if self.combined == [FeeCategory::Water, FeeCategory::Electricity] {
+            return "水电费合计（元）".to_string();
         }
+        let labels: Vec<&str> = self.combined.iter().map(FeeCategory::label).collect();
+        format!("{}合计（元）", labels.join("+"))
+    }
+}
 
-        // 从Excel读取水电人工费和垃圾处理费
-        let labor_fee = row.get(labor_fee_i).map(as_f64).unwrap_or(0.0);
-        let garbage_fee = row.get(garbage_fee_i).map(as_f64).unwrap_or(0.0);
-        bill.water_electricity_labor_fee = labor_fee;
-        bill.garbage_disposal_fee = garbage_fee;
-        bill.update_totals();
+// 汇总表分组：按电费单价分档，每档之后附小计行
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SummaryGroup {
+    ElectricityPrice,
+}
 
-        bills.push(bill);
+// 按电费单价将商家分组（容差内视为同一档）：各档按单价从低到高排列，档内保持原顺序
+pub fn group_by_electricity_price(merchants: &[MerchantBill]) -> Vec<(f64, Vec<MerchantBill>)> {
+    let mut groups: Vec<(f64, Vec<MerchantBill>)> = Vec::new();
+    for bill in merchants {
+        match groups.iter_mut().find(|(price, _)| prices_equal(*price, bill.electricity_unit_price)) {
+            Some((_, group)) => group.push(bill.clone()),
+            None => groups.push((bill.electricity_unit_price, vec![bill.clone()])),
+        }
     }
-    Ok(bills)
+    groups.sort_by(|a, b| a.0.total_cmp(&b.0));
+    groups
 }
 
-pub fn read_csv_file(file_path: &str, headers_map: &HeadersMap) -> Result<Vec<MerchantBill>> {
-    let file = File::open(file_path)
-        .with_context(|| format!("无法打开CSV文件: {}", file_path))?;
-    let mut lines = BufReader::new(file).lines();
-    let header_line = lines.next().transpose()?.context("CSV中缺少表头行")?;
-    let headers: Vec<String> = header_line.split(',').map(|s| s.trim().to_string()).collect();
-
-    println!("调试：找到的表头: {:?}", headers);
+// 抄表人/抄表日期显示在信息行、费用表底部的单独行，或两处都显示
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MeterInfoLayout {
+    #[default]
+    InfoLine,
+    TableRows,
+    Both,
+}
 
-    // 直接查找列索引，不使用find_indices
-    let code_i = headers.iter().position(|h| h.contains("铺面编号")).context("找不到铺面编号列")?;
-    let m_i = headers.iter().position(|h| h.contains("店铺名称")).context("找不到店铺名称列")?;
-    let e1p_i = headers.iter().position(|h| h.contains("电表1上期读数")).context("找不到电表1上期读数列")?;
-    let e1c_i = headers.iter().position(|h| h.contains("电表1本期读数")).context("找不到电表1本期读数列")?;
-    let wp_i = headers.iter().position(|h| h.contains("上期水表读数")).context("找不到上期水表读数列")?;
-    let wc_i = headers.iter().position(|h| h.contains("本期水表读数")).context("找不到本期水表读数列")?;
-    let wprice_i = headers.iter().position(|h| h.contains("水费单价")).context("找不到水费单价列")?;
-    let eprice_i = headers.iter().position(|h| h.contains("电费单价")).context("找不到电费单价列")?;
-    
-    // 找到水电人工费和垃圾处理费列
-    let labor_fee_i = headers.iter().position(|h| h.contains("水电人工费")).context("找不到水电人工费列")?;
-    let garbage_fee_i = headers.iter().position(|h| h.contains("垃圾处理费")).context("找不到垃圾处理费列")?;
+impl MeterInfoLayout {
+    pub fn in_info_line(&self) -> bool {
+        *self != MeterInfoLayout::TableRows
+    }
 
-    let mut electricity_columns = find_electricity_columns(&headers, headers_map.electricity_prefix)?;
-    if !electricity_columns.iter().any(|(p,c)| *p==e1p_i && *c==e1c_i) {
-        electricity_columns.insert(0, (e1p_i, e1c_i));
+    pub fn in_table(&self) -> bool {
+        *self != MeterInfoLayout::InfoLine
     }
+}
 
-    println!("调试：基础列索引 - 商家:{}, 水表上期:{}, 水表本期:{}, 水费单价:{}, 电费单价:{}, 水电人工费:{}, 垃圾处理费:{}", 
-             m_i, wp_i, wc_i, wprice_i, eprice_i, labor_fee_i, garbage_fee_i);
-    println!("调试：电表列: {:?}", electricity_columns);
+// 通知单版式：标准版式按 per_page 排版；半页版式每张A4上下两份，可沿分隔线裁开
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PageLayout {
+    #[default]
+    Standard,
+    HalfPage,
+}
 
-    let mut bills = Vec::new();
-    for line in lines {
-        let line = line?;
-        if line.trim().is_empty() { continue; }
-        let parts: Vec<&str> = line.split(',').collect();
-        if parts.len() < 5 { continue; } // 确保至少有基础列
-        
-        let get = |i: usize| -> &str { parts.get(i).copied().unwrap_or("") };
-        
-        let merchant_name = get(m_i).trim().to_string();
-        let shop_code = get(code_i).trim().to_string();
-        if merchant_name.is_empty() { continue; }
-        
-        let water_price = get(wprice_i).trim().parse::<f64>().unwrap_or(0.0);
-        let electricity_price = get(eprice_i).trim().parse::<f64>().unwrap_or(0.0);
-        let prev_water = get(wp_i).trim().parse::<f64>().unwrap_or(0.0);
-        let curr_water = get(wc_i).trim().parse::<f64>().unwrap_or(0.0);
+// 半页版式的页边距（twip），比默认页边距窄，保证两份通知单放得下
+const HALF_PAGE_MARGIN: (i32, i32) = (720, 851); // (上下, 左右)
 
-        let mut bill = MerchantBill::new(merchant_name, water_price, electricity_price);
-        bill.set_water_readings(prev_water, curr_water);
-        bill.set_shop_code(shop_code);
+// 信息行空字段的显示方式：保留空位、整项省略，或显示下划线供手工填写
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EmptyFieldStyle {
+    #[default]
+    Blank,
+    Omit,
+    Underline,
+}
 
-        // 处理每个电表
-        for (meter_id, (prev_col, curr_col)) in electricity_columns.iter().enumerate() {
-            let prev_reading = get(*prev_col).trim().parse::<f64>().unwrap_or(0.0);
-            let curr_reading = get(*curr_col).trim().parse::<f64>().unwrap_or(0.0);
-            if prev_reading > 0.0 || curr_reading > 0.0 {
-                bill.add_electricity_meter(format!("{}", meter_id + 1), prev_reading, curr_reading);
-            }
+pub const EMPTY_FIELD_UNDERLINE: &str = "________";
+
+impl EmptyFieldStyle {
+    // 返回字段的显示值；None 表示该项（含标签）整项不显示
+    pub fn render(&self, value: &str) -> Option<String> {
+        if !value.trim().is_empty() {
+            return Some(value.to_string());
+        }
+        match self {
+            EmptyFieldStyle::Blank => Some(value.to_string()),
+            EmptyFieldStyle::Omit => None,
+            EmptyFieldStyle::Underline => Some(EMPTY_FIELD_UNDERLINE.to_string()),
         }
+    }
+}
 
-        // 从CSV读取水电人工费和垃圾处理费
-        let labor_fee = get(labor_fee_i).trim().parse::<f64>().unwrap_or(0.0);
-        let garbage_fee = get(garbage_fee_i).trim().parse::<f64>().unwrap_or(0.0);
-        bill.water_electricity_labor_fee = labor_fee;
-        bill.garbage_disposal_fee = garbage_fee;
-        bill.update_totals();
+pub const DEFAULT_MAX_MERCHANTS: usize = 2000;
 
-        bills.push(bill);
+// 商家数量超过上限时直接报错，提示拆分文件
+fn check_merchant_limit(count: usize, options: Option<&GenerateOptions>) -> Result<()> {
+    let limit = options.map(|o| o.max_merchants).unwrap_or(DEFAULT_MAX_MERCHANTS);
+    if count > limit {
+        anyhow::bail!("商家数量超过上限 {}，请拆分文件", limit);
     }
-    Ok(bills)
+    Ok(())
 }
 
-pub fn read_data_file(file_path: &str, headers_map: &HeadersMap) -> Result<Vec<MerchantBill>> {
-    let path = Path::new(file_path);
-    let extension = path.extension().and_then(|e| e.to_str()).unwrap_or("").to_lowercase();
-    match extension.as_str() {
-        "xlsx" => read_excel_file(file_path, headers_map),
-        "csv" => read_csv_file(file_path, headers_map),
-        _ => {
-            if file_path.ends_with(".xlsx") { read_excel_file(file_path, headers_map) }
-            else if file_path.ends_with(".csv") { read_csv_file(file_path, headers_map) }
-            else { anyhow::bail!("不支持的文件格式: {}", extension) }
-        }
-    }
+// 负数金额显示方式："-50.00" 或 "(50.00)"
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NegativeStyle {
+    #[default]
+    Minus,
+    Parentheses,
 }
 
-// 将数值金额转换为中文大写人民币（元到分）
-fn rmb_upper(amount: f64) -> String {
-    // 四舍五入到分
-    let cents = (amount * 100.0).round() as i64;
-    if cents == 0 {
-        return "零元整".to_string();
+impl NegativeStyle {
+    // 保留两位小数；四舍五入后为0的负数按 "0.00" 显示，避免出现 "-0.00"
+    pub fn format(&self, amount: f64) -> String {
+        self.format_money(amount, &MoneyFormat::default())
     }
 
-    let digits = ["零","壹","贰","叁","肆","伍","陆","柒","捌","玖"]; 
-    let units = ["分","角","元","拾","佰","仟","万","拾","佰","仟","亿","拾","佰","仟","万"]; // 足够长
+    // 同 format，数字部分按 MoneyFormat 的小数点与千分位显示
+    pub fn format_money(&self, amount: f64, money: &MoneyFormat) -> String {
+        if (amount * 100.0).round() >= 0.0 {
+            return money.format(amount.max(0.0), 2);
+        }
+        match self {
+            NegativeStyle::Minus => format!("-{}", money.format(-amount, 2)),
+            NegativeStyle::Parentheses => format!("({})", money.format(-amount, 2)),
+        }
+    }
+}
 
-    let mut num = cents;
-    let mut parts: Vec<String> = Vec::new();
-    let mut unit_idx = 0usize;
-    let mut last_zero = false;
+// 金额的小数点与千分位分隔符，默认 "1234.56"；如 decimal_separator=',' 且 grouping_separator=Some('.') 时为 "1.234,56"
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MoneyFormat {
+    pub decimal_separator: char,
+    pub grouping_separator: Option<char>,
+}
 
-    while num > 0 && unit_idx < units.len() {
-        let d = (num % 10) as usize;
-        let unit = units[unit_idx];
-        if d == 0 {
-            if (unit == "元" || unit == "万" || unit == "亿") && !parts.iter().any(|p| p.contains(unit)) {
-                parts.push(unit.to_string());
-            }
-            if !last_zero { parts.push("零".to_string()); }
-            last_zero = true;
-        } else {
-            let mut seg = String::new();
-            seg.push_str(units[unit_idx]);
-            seg.insert_str(0, digits[d]);
-            parts.push(seg);
-            last_zero = false;
-        }
-        num /= 10;
-        unit_idx += 1;
-    }
-
-    parts.reverse();
-    let mut s = parts.join("");
-    // 清理多余的零
-    while s.contains("零零") { s = s.replace("零零", "零"); }
-    s = s.replace("零亿", "亿").replace("零万", "万").replace("零元", "元");
-    if s.ends_with("零") { s.pop(); }
-    if !s.contains("角") && !s.contains("分") { s.push_str("整"); }
-    s
+impl Default for MoneyFormat {
+    fn default() -> Self {
+        Self { decimal_separator: '.', grouping_separator: None }
+    }
 }
 
-fn add_summary_table(mut doc: docx_rs::Docx, merchants: &[MerchantBill]) -> Result<docx_rs::Docx, anyhow::Error> {
-    use docx_rs::*;
+impl MoneyFormat {
+    // 四舍五入保留 decimals 位小数并按配置替换分隔符。
+    // 先自行四舍五入再格式化：直接格式化对 0.125 这类恰好为5的位按"银行家舍入"得到 0.12，
+    // 与大写金额（四舍五入到分，壹角叁分）不一致；带厘的金额一律先四舍五入到分再显示
+    pub fn format(&self, amount: f64, decimals: usize) -> String {
+        let factor = 10f64.powi(decimals as i32);
+        self.localize(&format!("{:.*}", decimals, (amount * factor).round() / factor))
+    }
 
-    // 添加汇总表格标题
-    doc = doc.add_paragraph(
-        Paragraph::new()
-            .add_run(Run::new().add_text("费用汇总表").size(36).bold())
-            .align(AlignmentType::Center)
-    );
+    // 将 "1234.56" 形式的数字文本（可带负号）转换为配置的分隔符
+    pub fn localize(&self, text: &str) -> String {
+        let (sign, digits) = match text.strip_prefix('-') {
+            Some(rest) => ("-", rest),
+            None => ("", text),
+        };
+        let (int_part, frac_part) = match digits.split_once('.') {
+            Some((i, f)) => (i, Some(f)),
+            None => (digits, None),
+        };
+        let mut out = String::from(sign);
+        for (idx, c) in int_part.chars().enumerate() {
+            if idx > 0 && (int_part.len() - idx) % 3 == 0 {
+                if let Some(sep) = self.grouping_separator {
+                    out.push(sep);
+                }
+            }
+            out.push(c);
+        }
+        if let Some(frac) = frac_part {
+            out.push(self.decimal_separator);
+            out.push_str(frac);
+        }
+        out
+    }
+}
 
-    // 空行
-    doc = doc.add_paragraph(Paragraph::new());
+pub const NEGATIVE_AMOUNT_COLOR: &str = "FF0000";
 
-    // 创建表格，设置较大的字体，保持原有宽度
-    let mut table = Table::new(vec![
-        TableRow::new(vec![
-            TableCell::new()
-                .add_paragraph(Paragraph::new().add_run(Run::new().add_text("店铺名称").bold().size(24)).align(AlignmentType::Center)),
-            TableCell::new()
-                .add_paragraph(Paragraph::new().add_run(Run::new().add_text("水电费合计（元）").bold().size(24)).align(AlignmentType::Center)),
-            TableCell::new()
-                .add_paragraph(Paragraph::new().add_run(Run::new().add_text("水电人工费").bold().size(24)).align(AlignmentType::Center)),
-            TableCell::new()
-                .add_paragraph(Paragraph::new().add_run(Run::new().add_text("垃圾处理费").bold().size(24)).align(AlignmentType::Center)),
-            TableCell::new()
-                .add_paragraph(Paragraph::new().add_run(Run::new().add_text("总价").bold().size(24)).align(AlignmentType::Center)),
-        ])
-        .row_height(600.0)
-    ]);
+// 与不传选项（None）时的行为一致：每页1份、默认标题
+impl Default for GenerateOptions {
+    fn default() -> Self {
+        Self {
+            custom_title: None,
+            per_page: 1,
+            table_headers: TableHeaders::default(),
+            date_format: None,
+            name_source: NameSource::default(),
+            show_rounding_note: false,
+            draft: false,
+            capitalize_all_amounts: false,
+            price_max_decimals: None,
+            meter_merge_mode: MeterMergeMode::default(),
+            building_name: None,
+            show_effective_rate: false,
+            negative_style: NegativeStyle::default(),
+            row_order: RowOrder::default(),
+            default_labor_fee: None,
+            default_garbage_fee: None,
+            combine_meters: false,
+            max_merchants: DEFAULT_MAX_MERCHANTS,
+            meter_info_layout: MeterInfoLayout::default(),
+            summary_group_by: None,
+            money_format: MoneyFormat::default(),
+            summary_columns: SummaryColumns::default(),
+            start_sequence: None,
+            show_separator: true,
+            include_footer_total: false,
+            row_height: None,
+            body_font_size: None,
+            empty_info_field: EmptyFieldStyle::default(),
+            show_shared_column: true,
+            usage_decimals: None,
+            show_price_units: false,
+            page_layout: PageLayout::default(),
+            period_start: None,
+            period_end: None,
+        }
+    }
+}
 
-    // 添加数据行
+// 按 GenerateOptions 中的统一标准收费补齐各商家的人工费、垃圾处理费
+pub fn apply_default_fees(merchants: &mut [MerchantBill], options: &GenerateOptions) {
     for bill in merchants {
-        let water_electricity_total = bill.water_amount + bill.electricity_amount;
-        table = table.add_row(TableRow::new(vec![
-            TableCell::new()
-                .add_paragraph(Paragraph::new().add_run(Run::new().add_text(&bill.merchant_name).size(20)).align(AlignmentType::Center)),
-            TableCell::new()
-                .add_paragraph(Paragraph::new().add_run(Run::new().add_text(format!("{:.2}", water_electricity_total)).size(20)).align(AlignmentType::Center)),
-            TableCell::new()
-                .add_paragraph(Paragraph::new().add_run(Run::new().add_text(format!("{:.2}", bill.water_electricity_labor_fee)).size(20)).align(AlignmentType::Center)),
-            TableCell::new()
-                .add_paragraph(Paragraph::new().add_run(Run::new().add_text(format!("{:.2}", bill.garbage_disposal_fee)).size(20)).align(AlignmentType::Center)),
-            TableCell::new()
-                .add_paragraph(Paragraph::new().add_run(Run::new().add_text(format!("{:.2}", bill.total_fee)).size(20)).align(AlignmentType::Center)),
-        ])
-        .row_height(500.0));
+        bill.apply_default_fees(options.default_labor_fee, options.default_garbage_fee);
     }
+}
+
+// 生成前按需补齐标准收费；未配置时直接借用原数据，不做拷贝
+fn with_default_fees<'a>(merchants: &'a [MerchantBill], options: Option<&GenerateOptions>) -> Cow<'a, [MerchantBill]> {
+    match options.filter(|o| o.default_labor_fee.is_some() || o.default_garbage_fee.is_some()) {
+        Some(o) => {
+            let mut bills = merchants.to_vec();
+            apply_default_fees(&mut bills, o);
+            Cow::Owned(bills)
+        }
+        None => Cow::Borrowed(merchants),
+    }
+}
+
+// 文档使用的小区名称：优先取表格数据中的名称，其次为选项中的配置
+pub fn resolve_building_name(merchants: &[MerchantBill], options: Option<&GenerateOptions>) -> Option<String> {
+    merchants
+        .iter()
+        .find_map(|b| b.building_name.clone())
+        .or_else(|| options.and_then(|o| o.building_name.clone()))
+        .map(|n| n.trim().to_string())
+        .filter(|n| !n.is_empty())
+}
+
+// 信息行中的计费周期，如 "2025/07/01 至 2025/07/31"：起止日期优先取账单（表格列），其次为选项；
+// 两者都没有时不显示，只有一端时另一端按 empty_info_field 显示
+pub fn billing_period_text(bill: &MerchantBill, options: Option<&GenerateOptions>) -> Option<String> {
+    let pick = |own: &Option<String>, fallback: Option<&Option<String>>| {
+        own.clone().or_else(|| fallback.cloned().flatten()).map(|d| d.trim().to_string()).filter(|d| !d.is_empty())
+    };
+    let start = pick(&bill.period_start, options.map(|o| &o.period_start));
+    let end = pick(&bill.period_end, options.map(|o| &o.period_end));
+    if start.is_none() && end.is_none() {
+        return None;
+    }
+    let empty_info_field = options.map(|o| o.empty_info_field).unwrap_or_default();
+    let render = |date: Option<String>| empty_info_field.render(&date.unwrap_or_default()).unwrap_or_default();
+    Some(format!("{} 至 {}", render(start), render(end)))
+}
+
+// 多电表账单中单价、金额列的纵向合并方式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MeterMergeMode {
+    // 单价、金额两列都合并（默认）
+    #[default]
+    MergePriceAndAmount,
+    // 仅合并金额列，单价每行显示
+    MergeAmountOnly,
+    // 不合并，每行都显示单价与合并后的电费总额
+    NoMerge,
+}
+
+// 费用表中水费行与电表行的先后顺序
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RowOrder {
+    // 先电表后水费（默认）
+    #[default]
+    ElectricityFirst,
+    // 先水费后电表
+    WaterFirst,
+}
+
+pub const PRICE_MAX_DECIMALS: usize = 6;
+
+// 按实际精度显示单价：至少 min_decimals 位，去掉多余的尾随0，最多 max_decimals 位
+// 例如 format_price(2.8567, 3, 6) => "2.8567"，format_price(1.0, 3, 6) => "1.000"
+pub fn format_price(price: f64, min_decimals: usize, max_decimals: usize) -> String {
+    let max_decimals = max_decimals.max(min_decimals);
+    let mut s = format!("{:.*}", max_decimals, price);
+    if let Some(dot) = s.find('.') {
+        let keep = dot + 1 + min_decimals;
+        while s.len() > keep && s.ends_with('0') {
+            s.pop();
+        }
+        if s.ends_with('.') {
+            s.pop();
+        }
+    }
+    s
+}
+
+pub const WATER_PRICE_UNIT: &str = "元/吨";
+pub const ELECTRICITY_PRICE_UNIT: &str = "元/度";
+
+pub const USAGE_MAX_DECIMALS: usize = 2;
+
+// 读数、用量文本：指定位数时固定显示；默认整数不带小数，有小数时最多显示2位，
+// 使"用量 × 单价"与显示的金额能对上（如 10.5 度）
+pub fn format_usage(value: f64, decimals: Option<usize>) -> String {
+    match decimals {
+        Some(decimals) => format!("{:.*}", decimals, value),
+        None => format_price(value, 0, USAGE_MAX_DECIMALS),
+    }
+}
+
+// 水费、电费金额文本；capitalize 时附带大写，如 "22（贰拾贰元整）"。
+// 取整到元时显示整数，保留到分（RoundingMode::TwoDecimals）时显示两位小数
+fn amount_text(amount: f64, capitalize: bool, money: &MoneyFormat) -> String {
+    let text = money.format(amount, yuan_decimals(amount));
+    if capitalize {
+        format!("{}（{}）", text, rmb_upper(amount))
+    } else {
+        text
+    }
+}
+
+// 整数元的金额不显示小数，带角分时显示两位
+fn yuan_decimals(amount: f64) -> usize {
+    if round_cents(amount).fract() == 0.0 { 0 } else { 2 }
+}
+
+pub const DRAFT_BANNER: &str = "草稿 - 请勿发送";
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NameSource {
+    // 优先户主姓名/联系人，缺失时回退到店铺名称
+    #[default]
+    ContactName,
+    // 始终使用店铺名称
+    ShopName,
+}
+
+impl NameSource {
+    pub fn display_name<'a>(&self, bill: &'a MerchantBill) -> &'a str {
+        match self {
+            NameSource::ContactName => bill
+                .contact_name
+                .as_deref()
+                .filter(|n| !n.trim().is_empty())
+                .unwrap_or(&bill.merchant_name),
+            NameSource::ShopName => &bill.merchant_name,
+        }
+    }
+}
+
+// 解析日期格式并校验，避免非法格式串在渲染时panic
+pub fn resolve_date_format(format: Option<&str>) -> Result<String> {
+    use chrono::format::{Item, StrftimeItems};
+
+    let fmt = match format.map(str::trim) {
+        None | Some("") | Some("cn") => "%Y年%m月%d日",
+        Some("iso") => "%Y-%m-%d",
+        Some("slash") => "%Y/%m/%d",
+        Some(other) => other,
+    };
+    if StrftimeItems::new(fmt).any(|item| matches!(item, Item::Error)) {
+        anyhow::bail!("日期格式无效: {}", fmt);
+    }
+    Ok(fmt.to_string())
+}
+
+// 由日期格式得到只到月份的格式（去掉"%d"及紧邻的"日"或分隔符），用于"账单期间"等按月显示的位置
+pub fn month_format(date_format: &str) -> String {
+    let Some(pos) = date_format.find("%d") else { return date_format.to_string() };
+    let (before, after) = (&date_format[..pos], &date_format[pos + 2..]);
+    if let Some(rest) = after.strip_prefix('日') {
+        return format!("{}{}", before, rest);
+    }
+    let is_separator = |c: char| !c.is_alphanumeric() && c != '%';
+    match (before.chars().next_back(), after.chars().next()) {
+        (Some(c), _) if is_separator(c) => format!("{}{}", &before[..before.len() - c.len_utf8()], after),
+        (None, Some(c)) if is_separator(c) => after[c.len_utf8()..].to_string(),
+        _ => format!("{}{}", before, after),
+    }
+}
+
+// 费用明细表的表头文字（不同楼宇用语不同，如"起度"/"止度"）
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TableHeaders {
+    pub item: String,
+    pub prev_reading: String,
+    pub curr_reading: String,
+    pub usage: String,
+    pub shared: String,
+    pub unit_price: String,
+    pub amount: String,
+}
+
+impl Default for TableHeaders {
+    fn default() -> Self {
+        Self {
+            item: "项目".to_string(),
+            prev_reading: "上月表底".to_string(),
+            curr_reading: "本月抄表数".to_string(),
+            usage: "实用度数".to_string(),
+            shared: "公共分摊".to_string(),
+            unit_price: "单价（元）".to_string(),
+            amount: "金额".to_string(),
+        }
+    }
+}
+
+// 费用表单元格的纵向合并：多电表共用单价/金额时，首行开始合并，其余行延续（不显示内容）
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CellMerge {
+    #[default]
+    None,
+    Start,
+    Continue,
+}
+
+// 费用表单元格：显示文字、合并状态；negative 表示按负数金额突出显示（Word 中为红色）
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BillCell {
+    pub text: String,
+    pub merge: CellMerge,
+    pub negative: bool,
+}
+
+impl BillCell {
+    pub fn text(text: impl Into<String>) -> Self {
+        Self { text: text.into(), ..Self::default() }
+    }
+}
+
+// 费用表行的类型，各输出格式据此决定样式（如合计行整行合并、占位行是否输出）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BillRowKind {
+    Water,
+    Meter,
+    Fee,
+    // 滞纳金、广告费等占位行（金额固定为0）
+    Placeholder,
+    Discount,
+    // 合计行：单元格依次为 标签、小写金额、大写金额
+    Total,
+    // 抄表人/抄表日期行：单元格依次为 标签、内容
+    MeterInfo,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BillRow {
+    pub kind: BillRowKind,
+    pub cells: Vec<BillCell>,
+}
+
+// 费用明细表的结构化表示：由 MerchantBill::fee_table 统一生成，Word、Markdown 等输出共用，
+// 避免各格式的取整、标签与合并规则不一致
+#[derive(Debug, Clone, PartialEq)]
+pub struct BillTable {
+    pub header: Vec<String>,
+    pub rows: Vec<BillRow>,
+    // "公共分摊"列的位置（该列仅供手工填写，Markdown 中省略）
+    pub shared_column: Option<usize>,
+}
+
+impl BillTable {
+    // 去掉"公共分摊"列，其余列左移（合计行、抄表信息行为整行合并，不受影响）
+    fn remove_shared_column(&mut self) {
+        let Some(column) = self.shared_column.take() else { return };
+        self.header.remove(column);
+        for row in &mut self.rows {
+            if !matches!(row.kind, BillRowKind::Total | BillRowKind::MeterInfo) {
+                row.cells.remove(column);
+            }
+        }
+    }
+
+    // 合计行的小写金额文字
+    pub fn total_text(&self) -> Option<&str> {
+        self.rows.iter().find(|r| r.kind == BillRowKind::Total).map(|r| r.cells[1].text.as_str())
+    }
+}
+
+impl MerchantBill {
+    // 按生成选项构造费用明细表；meter_reader/meter_date 用于表格底部的抄表信息行（按 meter_info_layout）
+    pub fn fee_table(&self, options: &GenerateOptions, meter_reader: &str, meter_date: &str) -> BillTable {
+        let headers = &options.table_headers;
+        let price_max_decimals = options.price_max_decimals.unwrap_or(PRICE_MAX_DECIMALS);
+        let capitalize = options.capitalize_all_amounts;
+        let money_format = &options.money_format;
+        let money = |amount: f64| BillCell { negative: amount < 0.0, ..BillCell::text(options.negative_style.format_money(amount, money_format)) };
+        let price = |price: f64, min_decimals: usize, unit: &str| {
+            let text = money_format.localize(&format_price(price, min_decimals, price_max_decimals));
+            BillCell::text(if options.show_price_units { format!("{} {}", text, unit) } else { text })
+        };
+        let reading = |value: f64| BillCell::text(format_usage(value, options.usage_decimals));
+        let fee_row = |kind: BillRowKind, label: &str, unit_price: BillCell, amount: BillCell| BillRow {
+            kind,
+            cells: vec![BillCell::text(label), BillCell::default(), BillCell::default(), BillCell::default(), BillCell::default(), unit_price, amount],
+        };
+
+        let mut rows = Vec::new();
+        // 水费行按 row_order 放在电表行之前或之后
+        let mut water_row = Some(BillRow {
+            kind: BillRowKind::Water,
+            cells: vec![
+                BillCell::text("水费"),
+                reading(self.prev_water_reading),
+                reading(self.curr_water_reading),
+                reading(self.water_usage),
+                BillCell::default(),
+                price(self.water_unit_price, 3, WATER_PRICE_UNIT),
+                BillCell::text(amount_text(self.water_amount, capitalize, money_format)),
+            ],
+        });
+        if options.row_order == RowOrder::WaterFirst {
+            rows.extend(water_row.take());
+        }
+
+        // 多电表时按 meter_merge_mode 纵向合并单价、金额列；存在分表单价时每行显示各自的单价与金额
+        // 合表时只显示一行合并后的电表
+        let combined = if options.combine_meters { self.combined_meter() } else { None };
+        let display_meters = combined.as_ref().map(std::slice::from_ref).unwrap_or(&self.electricity_meters);
+        let meters_len = display_meters.len();
+        let per_meter_prices = self.has_per_meter_prices();
+        let merge_price = meters_len > 1 && !per_meter_prices && options.meter_merge_mode == MeterMergeMode::MergePriceAndAmount;
+        let merge_amount = meters_len > 1 && !per_meter_prices && options.meter_merge_mode != MeterMergeMode::NoMerge;
+        for (meter_idx, meter) in display_meters.iter().enumerate() {
+            let merged = |merge: bool, cell: BillCell| match (merge, meter_idx) {
+                (false, _) => cell,
+                (true, 0) => BillCell { merge: CellMerge::Start, ..cell },
+                (true, _) => BillCell { merge: CellMerge::Continue, ..BillCell::default() },
+            };
+            let row_amount = if per_meter_prices { meter.amount } else { self.electricity_amount };
+            rows.push(BillRow {
+                kind: BillRowKind::Meter,
+                cells: vec![
+                    BillCell::text(meter.display_name(meter_idx, meters_len)),
+                    reading(meter.prev_reading),
+                    reading(meter.curr_reading),
+                    reading(meter.usage),
+                    BillCell::default(),
+                    merged(merge_price, price(self.meter_unit_price(meter), 2, ELECTRICITY_PRICE_UNIT)),
+                    merged(merge_amount, BillCell::text(amount_text(row_amount, capitalize, money_format))),
+                ],
+            });
+        }
+        // 没有电表时保留一行空的电表行
+        if self.electricity_meters.is_empty() {
+            rows.push(BillRow {
+                kind: BillRowKind::Meter,
+                cells: vec![
+                    BillCell::text("电表"),
+                    BillCell::text("0"),
+                    BillCell::text("0"),
+                    BillCell::text("0"),
+                    BillCell::default(),
+                    price(self.electricity_unit_price, 2, ELECTRICITY_PRICE_UNIT),
+                    BillCell::text("0"),
+                ],
+            });
+        }
+        rows.extend(water_row);
+
+        rows.push(fee_row(BillRowKind::Fee, "水电人工费", BillCell::default(), money(self.water_electricity_labor_fee)));
+        rows.push(fee_row(BillRowKind::Fee, "垃圾处理费", BillCell::default(), money(self.garbage_disposal_fee)));
+        // 滞纳金、广告费为固定占位行，填写了同名其他费用时显示实际金额
+        for name in ["滞纳金", "广告费"] {
+            if self.extra_fees.iter().any(|f| f.name == name) {
+                rows.push(fee_row(BillRowKind::Fee, name, BillCell::default(), money(self.extra_fee(name))));
+            } else {
+                rows.push(fee_row(BillRowKind::Placeholder, name, BillCell::default(), money(0.0)));
+            }
+        }
+        for fee in self.extra_fees.iter().filter(|f| f.name != "滞纳金" && f.name != "广告费") {
+            rows.push(fee_row(BillRowKind::Fee, &fee.name, BillCell::default(), money(fee.amount)));
+        }
+        // 优惠行（有优惠时显示，金额为负数；比例优惠在单价列注明比例）
+        if self.discount_amount != 0.0 {
+            rows.push(fee_row(BillRowKind::Discount, "优惠", BillCell::text(self.discount_rate_text()), money(self.discount_amount)));
+        }
+
+        rows.push(BillRow {
+            kind: BillRowKind::Total,
+            cells: vec![BillCell::text("合计"), money(self.total_fee), BillCell::text(rmb_upper(self.total_fee))],
+        });
+        if options.meter_info_layout.in_table() {
+            for (label, value) in [("抄表人", meter_reader), ("抄表日期", meter_date)] {
+                if let Some(value) = options.empty_info_field.render(value) {
+                    rows.push(BillRow { kind: BillRowKind::MeterInfo, cells: vec![BillCell::text(label), BillCell::text(value)] });
+                }
+            }
+        }
+
+        let mut table = BillTable {
+            header: vec![
+                headers.item.clone(),
+                headers.prev_reading.clone(),
+                headers.curr_reading.clone(),
+                headers.usage.clone(),
+                headers.shared.clone(),
+                headers.unit_price.clone(),
+                headers.amount.clone(),
+            ],
+            rows,
+            shared_column: Some(4),
+        };
+        if !options.show_shared_column {
+            table.remove_shared_column();
+        }
+        table
+    }
+}
+
+pub fn generate_word_document_with_template(
+    merchants: &[MerchantBill],
+    options: Option<GenerateOptions>,
+) -> Result<Vec<u8>, anyhow::Error> {
+    render_word_document(merchants, options, true)
+}
+
+// 单户通知单：只含该商户的一份通知单，不附汇总表、不分页（用于逐户生成、单户快速计算）
+pub fn generate_single_bill(bill: &MerchantBill, options: Option<GenerateOptions>) -> Result<Vec<u8>> {
+    render_word_document(std::slice::from_ref(bill), options, false)
+}
+
+fn render_word_document(
+    merchants: &[MerchantBill],
+    options: Option<GenerateOptions>,
+    with_summary: bool,
+) -> Result<Vec<u8>, anyhow::Error> {
+    // 生成专业的抄表计费通知单格式（表格版）
+    use docx_rs::*;
+    
+    check_merchant_limit(merchants.len(), options.as_ref())?;
+    let merchants = &*with_default_fees(merchants, options.as_ref());
+    let mut doc = Docx::new();
+
+    let page_layout = options.as_ref().map(|o| o.page_layout).unwrap_or_default();
+    let per_page = match page_layout {
+        PageLayout::HalfPage => 2,
+        PageLayout::Standard => options.as_ref().map(|o| o.per_page).unwrap_or(1),
+    };
+    let date_format = resolve_date_format(options.as_ref().and_then(|o| o.date_format.as_deref()))?;
+    let name_source = options.as_ref().map(|o| o.name_source).unwrap_or_default();
+    let show_rounding_note = options.as_ref().map(|o| o.show_rounding_note).unwrap_or(false);
+    let draft = options.as_ref().map(|o| o.draft).unwrap_or(false);
+    let meter_info_layout = options.as_ref().map(|o| o.meter_info_layout).unwrap_or_default();
+    let show_effective_rate = options.as_ref().map(|o| o.show_effective_rate).unwrap_or(false);
+    let negative_style = options.as_ref().map(|o| o.negative_style).unwrap_or_default();
+    let money_format = options.as_ref().map(|o| o.money_format).unwrap_or_default();
+    let start_sequence = options.as_ref().and_then(|o| o.start_sequence);
+    let show_separator = options.as_ref().map(|o| o.show_separator).unwrap_or(true);
+    let include_footer_total = options.as_ref().map(|o| o.include_footer_total).unwrap_or(false);
+    let empty_info_field = options.as_ref().map(|o| o.empty_info_field).unwrap_or_default();
+    let table_options = options.clone().unwrap_or_default();
+
+    // 根据每页数量动态调整字体大小
+    // 表格字体和表头字体都使用与标题一样的大小
+    let (title_size, info_size, header_size, data_size, notice_size, row_height_header, row_height_data) = match per_page {
+        _ if page_layout == PageLayout::HalfPage => (18, 11, 16, 16, 8, 300.0, 270.0),  // 半页版式
+        1 => (24, 18, 24, 24, 12, 480.0, 430.0),  // 一页一份
+        2 => (22, 16, 22, 22, 11, 420.0, 380.0),  // 一页两份
+        3 => (20, 14, 20, 20, 10, 350.0, 330.0),   // 一页三份
+        _ => (18, 12, 18, 18, 9, 310.0, 290.0),   // 一页四份或更多
+    };
+    let row_height = options.as_ref().and_then(|o| o.row_height);
+    let (row_height_header, row_height_data) = (row_height.unwrap_or(row_height_header), row_height.unwrap_or(row_height_data));
+    let data_size = options.as_ref().and_then(|o| o.body_font_size).unwrap_or(data_size);
+    if page_layout == PageLayout::HalfPage {
+        let (vertical, horizontal) = HALF_PAGE_MARGIN;
+        doc = doc.page_margin(PageMargin::new().top(vertical).bottom(vertical).left(horizontal).right(horizontal).header(425).footer(425));
+    }
+
+    // 页眉：小区名称与草稿标识
+    let building_name = resolve_building_name(merchants, options.as_ref());
+    if building_name.is_some() || draft {
+        let mut header = Header::new();
+        if let Some(name) = &building_name {
+            header = header.add_paragraph(
+                Paragraph::new()
+                    .add_run(Run::new().add_text(name).bold().size(info_size))
+                    .align(AlignmentType::Center)
+            );
+        }
+        if draft {
+            header = header.add_paragraph(
+                Paragraph::new()
+                    .add_run(Run::new().add_text(DRAFT_BANNER).bold().color("FF0000").size(info_size))
+                    .align(AlignmentType::Center)
+            );
+        }
+        doc = doc.header(header);
+    }
+
+    // 页脚：本批合计与页码
+    if include_footer_total {
+        let grand_total = Totals::from_merchants(merchants).grand_total;
+        doc = doc.footer(Footer::new().add_paragraph(
+            Paragraph::new()
+                .add_run(Run::new().add_text(format!("本批合计：¥{}    第 ", negative_style.format_money(grand_total, &money_format))).size(notice_size))
+                .add_page_num(PageNum::new())
+                .add_run(Run::new().add_text(" 页").size(notice_size))
+                .align(AlignmentType::Center)
+        ));
+    }
+
+    // 为每个商家生成通知单
+    for (index, bill) in merchants.iter().enumerate() {
+        let now = Local::now();
+
+        // 标题：自定义或默认 "yyyy年MM月抄表计费通知单"（月份取自账单，默认为当月）
+        let title = options
+            .as_ref()
+            .and_then(|o| o.custom_title.clone())
+            .unwrap_or_else(|| format!("{}抄表计费通知单", bill.month));
+        doc = doc.add_paragraph(
+            Paragraph::new()
+                .add_run(Run::new().add_text(&title).bold().size(title_size))
+                .align(AlignmentType::Center)
+        );
+
+        // 单据编号（右对齐，便于归档）
+        if let Some(start) = start_sequence {
+            doc = doc.add_paragraph(
+                Paragraph::new()
+                    .add_run(Run::new().add_text(sequence_number_text(start, index)).size(info_size))
+                    .align(AlignmentType::Right)
+            );
+        }
+
+        // 编号和基本信息行（编号使用CSV的铺面编号；抄表人/日期来自页面输入）
+        let meter_reader = bill.meter_reader.clone().unwrap_or_else(|| "".to_string());
+        let meter_date = bill.meter_date.clone().unwrap_or_else(|| now.format(&date_format).to_string());
+        let mut info_fields = Vec::new();
+        if let Some(code) = empty_info_field.render(&bill.shop_code) {
+            info_fields.push(format!("编号：\t{}", code));
+        }
+        if let Some(name) = empty_info_field.render(name_source.display_name(bill)) {
+            info_fields.push(format!("姓名\t{}", name));
+        }
+        if let Some(tax_id) = &bill.tax_id {
+            info_fields.push(format!("纳税人识别号：\t{}", tax_id));
+        }
+        if meter_info_layout.in_info_line() {
+            if let Some(reader) = empty_info_field.render(&meter_reader) {
+                info_fields.push(format!("抄表人：\t{}", reader));
+            }
+            if let Some(date) = empty_info_field.render(&meter_date) {
+                info_fields.push(format!("抄表日期：{}", date));
+            }
+        }
+        if let Some(period) = billing_period_text(bill, options.as_ref()) {
+            info_fields.push(format!("计费周期：{}", period));
+        }
+        let info_text = info_fields.join("\t");
+        doc = doc.add_paragraph(
+            Paragraph::new()
+                .add_run(Run::new().add_text(&info_text).size(info_size))
+        );
+        
+        // 空行
+        doc = doc.add_paragraph(Paragraph::new());
+        
+        // 创建费用明细表格（行与单元格内容由 fee_table 统一生成）
+        let fee_table = bill.fee_table(&table_options, &meter_reader, &meter_date);
+        let span = fee_table.header.len() - 1;
+        let fee_cell = |cell: &BillCell| {
+            if cell.merge == CellMerge::Continue {
+                return TableCell::new().vertical_merge(VMergeType::Continue);
+            }
+            let run = if cell.text.is_empty() { Run::new().add_text("") } else { Run::new().add_text(&cell.text).size(data_size) };
+            let run = if cell.negative { run.color(NEGATIVE_AMOUNT_COLOR) } else { run };
+            let table_cell = TableCell::new().add_paragraph(Paragraph::new().add_run(run).align(AlignmentType::Center));
+            if cell.merge == CellMerge::Start { table_cell.vertical_merge(VMergeType::Restart) } else { table_cell }
+        };
+        let mut table_rows = vec![
+            TableRow::new(fee_table.header.iter().map(|title| {
+                TableCell::new().add_paragraph(Paragraph::new().add_run(Run::new().add_text(title).bold().size(header_size)).align(AlignmentType::Center))
+            }).collect())
+            .row_height(row_height_header),
+        ];
+        for row in &fee_table.rows {
+            table_rows.push(match row.kind {
+                // 合计行（整行合并，先大写后小写，独占一行）
+                BillRowKind::Total => {
+                    let (lower, upper) = (&row.cells[1], &row.cells[2]);
+                    let mut total_run = Run::new().add_text(format!("大写：{}    小写：{}", upper.text, lower.text)).bold().size(header_size);
+                    if draft {
+                        total_run = total_run.color("808080");
+                    } else if lower.negative {
+                        total_run = total_run.color(NEGATIVE_AMOUNT_COLOR);
+                    }
+                    TableRow::new(vec![
+                        TableCell::new().add_paragraph(Paragraph::new().add_run(Run::new().add_text(&row.cells[0].text).bold().size(header_size)).align(AlignmentType::Center)),
+                        TableCell::new()
+                            .grid_span(span)
+                            .add_paragraph(Paragraph::new().add_run(total_run).align(AlignmentType::Center))
+                    ])
+                    .row_height(row_height_header)
+                }
+                // 抄表人、抄表日期作为表格底部的单独行
+                BillRowKind::MeterInfo => TableRow::new(vec![
+                    TableCell::new().add_paragraph(Paragraph::new().add_run(Run::new().add_text(&row.cells[0].text).size(data_size)).align(AlignmentType::Center)),
+                    TableCell::new()
+                        .grid_span(span)
+                        .add_paragraph(Paragraph::new().add_run(Run::new().add_text(&row.cells[1].text).size(data_size)).align(AlignmentType::Center))
+                ])
+                .row_height(row_height_data),
+                _ => TableRow::new(row.cells.iter().map(fee_cell).collect()).row_height(row_height_data),
+            });
+        }
+
+        let table = Table::new(table_rows);
+        
+        // 添加表格到文档
+        doc = doc.add_table(table);
+        
+        // 已合并其他费用与合计到主表，不再添加第二个表格或表外合计
+
+        // 电费合并单元格金额与分表金额之和不一致时按需加注（提示由 find_rounding_drifts 在读取时统一输出）
+        let drift = bill.electricity_rounding_drift();
+        if show_rounding_note && bill.electricity_meters.len() > 1 && drift != 0.0 {
+            doc = doc.add_paragraph(
+                Paragraph::new()
+                    .add_run(Run::new().add_text(format!("注：电费按总用电量计价后取整，与各电表分别取整之和相差{}元", format_price(drift, 0, 2))).size(notice_size))
+            );
+        }
+
+        if show_effective_rate && bill.has_variable_pricing() {
+            doc = doc.add_paragraph(
+                Paragraph::new()
+                    .add_run(Run::new().add_text(bill.effective_rate_text()).size(notice_size))
+            );
+        }
+        
+        // 空行
+        doc = doc.add_paragraph(Paragraph::new());
+        
+        // 说明文字
+        let notice_text = "1、此单可对账不做凭证；\n\n2、每月5日前为收费时间，超期按5%收滞纳金或停电；\n\n3、以上费用如有不明或差\n请到管理处核对。";
+        doc = doc.add_paragraph(
+            Paragraph::new()
+                .add_run(Run::new().add_text(notice_text).size(notice_size))
+        );
+        
+        // 表格之间的分隔符，以及按每页数量分页
+        if index < merchants.len() - 1 {
+            // 页面分隔：每页显示 per_page 个表格
+            if per_page != 0 && ((index + 1) % per_page == 0) {
+                // 添加分页符
+                doc = doc.add_paragraph(Paragraph::new().add_run(Run::new().add_break(BreakType::Page)));
+            } else if show_separator {
+                // 不分页时添加分隔线
+                doc = doc.add_paragraph(
+                    Paragraph::new()
+                        .add_run(Run::new().add_text("=".repeat(40)))
+                );
+            } else {
+                doc = doc.add_paragraph(Paragraph::new());
+            }
+        }
+    }
+
+    if with_summary {
+        // 汇总表之前添加分页符，使其单独成页
+        // 只有在不是刚分完页的情况下才添加分页符
+        if per_page == 0 || !merchants.len().is_multiple_of(per_page) {
+            doc = doc.add_paragraph(Paragraph::new().add_run(Run::new().add_break(BreakType::Page)));
+        }
+
+        // 添加汇总表格
+        let summary_group_by = options.as_ref().and_then(|o| o.summary_group_by);
+        let summary_columns = options.as_ref().map(|o| o.summary_columns.clone()).unwrap_or_default();
+        let layout = SummaryLayout {
+            columns: &summary_columns,
+            extra_fees: extra_fee_names(merchants),
+            money_format,
+            // 有优惠时在"总价"前追加"优惠"列
+            show_discount: merchants.iter().any(|b| b.discount_amount != 0.0),
+            // 有实收金额数据时追加"实收""差额"两列用于对账
+            show_received: merchants.iter().any(|b| b.received_amount.is_some()),
+        };
+        doc = add_summary_table(doc, merchants, summary_group_by, &layout)?;
+    }
+    
+    // 生成文档
+    let mut buf = Vec::new();
+    doc.build().pack(&mut std::io::Cursor::new(&mut buf))?;
+    Ok(buf)
+}
+
+// 读取选项
+#[derive(Debug, Clone, Default)]
+pub struct ReadOptions {
+    // 列出所有识别到的电表（即使读数为0或为空），默认仅保留有读数的电表
+    pub list_all_meters: bool,
+    // 小区名称所在单元格（如 "B1"），未提供"小区名称"列或该列为空时使用
+    pub building_name_cell: Option<String>,
+    // 严格模式：单价为负数等数据问题直接报错，默认仅输出警告
+    pub strict: bool,
+    // 电表表头模板（{n} 为电表编号，如 "{n}号电表"），按顺序尝试，默认为 "前缀{n}"
+    pub meter_header_templates: Vec<String>,
+    // 额外的电表前缀（如 "用电"）：与表头映射中的前缀一起识别，各前缀下的电表按编号合并（未配置 meter_header_templates 时生效）
+    pub electricity_prefixes: Vec<String>,
+    // 读取时将铺面编号、店铺名称中的全角字母数字、标点与空格转为半角（默认保留原文，仅匹配时转换）
+    pub normalize_full_width: bool,
+    // 店铺名称为空但有铺面编号的行：保留并以铺面编号作为名称（默认跳过该行）
+    pub keep_nameless_rows: bool,
+    // 宽松解码：CSV含少量无效UTF-8字节时替换后继续读取并警告（默认报错）
+    pub lenient_utf8: bool,
+    // CSV文件编码：默认自动识别（UTF-8，带或不带BOM；不是有效UTF-8时按GBK尝试）
+    pub csv_encoding: CsvEncoding,
+    // 估抄用的历史用量：铺面编号 -> 各电表上月用量（按电表顺序），用于"免抄"/"估抄"列标记的行
+    pub usage_history: HashMap<String, Vec<f64>>,
+    // 读数整列颠倒的判定比例：本期小于上期的读数占比达到该值时提示上期、本期列可能贴反（默认 0.8）
+    pub swapped_readings_ratio: Option<f64>,
+    // 阶梯电价：设置后各商户电费按总用电量分档计价（有分表单价的商户仍按分表单价）
+    pub electricity_tiers: Option<TieredPrice>,
+}
+
+// 默认的读数颠倒判定比例
+pub const SWAPPED_READINGS_RATIO: f64 = 0.8;
+
+// "免抄"/"估抄"列的值：非空且不是"否"、"0"、"N"等即视为已标记
+fn is_flag_set(raw: &str) -> bool {
+    let raw = raw.trim();
+    !raw.is_empty() && !["否", "0", "n", "no", "false", "×"].iter().any(|v| raw.eq_ignore_ascii_case(v))
+}
+
+// 标记为估抄的行：按历史用量估算电表读数；未提供历史用量时沿用表格读数并提示
+fn apply_estimation(bill: &mut MerchantBill, line: usize, options: &ReadOptions) {
+    let key = bill.shop_code_key();
+    let history = options.usage_history.iter().find(|(code, _)| normalize_width(code) == key).map(|(_, usage)| usage.as_slice());
+    if history.is_none() {
+        log_line!("警告：第{}行 商户「{}」标记为估抄，但未提供历史用量，沿用表格读数", line, bill.merchant_name);
+    }
+    bill.mark_estimated(history.unwrap_or(&[]));
+}
+
+// 读数缺失的电表按历史用量估算：本期读数为空（读作0）而上期读数不为0时，
+// 本期读数 = 上期读数 + 历史月均用量（设置了表盘位数的电表超过最大读数时归零），并标记为估抄。
+// history：铺面编号 -> 各电表（按电表顺序）最近若干个月的用量；返回估算的电表数
+pub fn estimate_missing_readings(bills: &mut [MerchantBill], history: &HashMap<String, Vec<Vec<f64>>>) -> usize {
+    let mut estimated = 0;
+    for bill in bills.iter_mut() {
+        let key = bill.shop_code_key();
+        let meters_history = history.iter().find(|(code, _)| normalize_width(code) == key).map(|(_, meters)| meters.as_slice()).unwrap_or(&[]);
+        let mut changed = false;
+        for (i, meter) in bill.electricity_meters.iter_mut().enumerate() {
+            if meter.curr_reading != 0.0 || meter.prev_reading == 0.0 {
+                continue;
+            }
+            let usages: Vec<f64> = meters_history.get(i).map(|u| u.iter().copied().filter(|v| v.is_finite()).collect()).unwrap_or_default();
+            if usages.is_empty() {
+                log_line!("警告：商户「{}」电表{}本期读数缺失，且没有历史用量可供估算，用量按0计算", bill.merchant_name, i + 1);
+                continue;
+            }
+            let average = round_cents(usages.iter().sum::<f64>() / usages.len() as f64).max(0.0);
+            meter.set_estimated_usage(average);
+            changed = true;
+            estimated += 1;
+        }
+        if changed {
+            bill.recompute_meter_amounts();
+            bill.update_totals();
+        }
+    }
+    estimated
+}
+
+// 店铺名称为空的行默认跳过；keep_nameless_rows 时以铺面编号代替名称并提示，避免漏掉计费单元
+fn resolve_merchant_name(merchant_name: String, shop_code: &str, line: usize, options: &ReadOptions) -> Option<String> {
+    if !merchant_name.trim().is_empty() {
+        return Some(merchant_name);
+    }
+    let shop_code = shop_code.trim();
+    if options.keep_nameless_rows && !shop_code.is_empty() {
+        log_line!("警告：第{}行店铺名称为空，以铺面编号「{}」作为名称", line, shop_code);
+        return Some(shop_code.to_string());
+    }
+    None
+}
+
+// 单价为负数多半是误输入的负号，会产生负金额并影响合计；返回问题描述（行号、商户、列名）
+fn negative_price_issues(bill: &MerchantBill, line: usize, electricity_prefix: &str) -> Vec<String> {
+    let mut prices = vec![("水费单价".to_string(), bill.water_unit_price), ("电费单价".to_string(), bill.electricity_unit_price)];
+    prices.extend(bill.electricity_meters.iter().filter_map(|m| {
+        m.unit_price.map(|price| (format!("{}{}单价", electricity_prefix, m.meter_id), price))
+    }));
+    prices
+        .into_iter()
+        .filter(|(_, price)| *price < 0.0)
+        .map(|(column, price)| format!("第{}行 商户「{}」「{}」为负数: {}", line, bill.merchant_name, column, price))
+        .collect()
+}
+
+// 严格模式下单价问题作为错误返回，否则输出警告
+fn report_price_issues(issues: &[String], options: &ReadOptions) -> Result<()> {
+    if options.strict && !issues.is_empty() {
+        anyhow::bail!("单价校验失败：\n{}", issues.join("\n"));
+    }
+    for issue in issues {
+        log_line!("警告：{}", issue);
+    }
+    Ok(())
+}
+
+// 整个文件合计为0几乎总是读取问题（单价列为空、读数列未识别等）：默认警告，严格模式下报错；
+// 仅读数输入（金额待统一定价）不检查
+fn check_zero_total(bills: &[MerchantBill], options: &ReadOptions) -> Result<()> {
+    if bills.is_empty() || bills.iter().all(|b| b.unpriced) || bills.iter().any(|b| b.total_fee != 0.0) {
+        return Ok(());
+    }
+    let message = format!("全部{}户的合计金额均为0，可能是单价或读数列未正确读取，请检查表头与数据", bills.len());
+    if options.strict {
+        anyhow::bail!(message);
+    }
+    log_line!("警告：{}", message);
+    Ok(())
+}
+
+// 上期、本期列整列贴反时，用量按0计（max(0)）会让整批账单悄悄变成0：
+// 水表、电表读数中本期小于上期的占比达到 swapped_readings_ratio 时醒目提示，严格模式下报错
+fn check_swapped_readings(bills: &[MerchantBill], options: &ReadOptions) -> Result<()> {
+    let ratio = options.swapped_readings_ratio.unwrap_or(SWAPPED_READINGS_RATIO);
+    let water: Vec<(f64, f64)> = bills.iter().map(|b| (b.prev_water_reading, b.curr_water_reading)).collect();
+    // 设置了表盘位数的电表本期小于上期按翻表计算，不计入
+    let electricity: Vec<(f64, f64)> = bills
+        .iter()
+        .flat_map(|b| b.electricity_meters.iter().filter(|m| m.rollover_digits.is_none()).map(|m| (m.prev_reading, m.curr_reading)))
+        .collect();
+    let mut issues = Vec::new();
+    for (kind, readings) in [("水表", water), ("电表", electricity)] {
+        // 上期、本期相同（含均为0）的读数无法判断方向，不计入
+        let changed = readings.iter().filter(|(prev, curr)| prev != curr).count();
+        let reversed = readings.iter().filter(|(prev, curr)| curr < prev).count();
+        if changed > 0 && reversed as f64 / changed as f64 >= ratio {
+            issues.push(format!(
+                "{}读数中有{}/{}个本期读数小于上期读数，用量均已按0计算，上期、本期读数列可能整列贴反了，请核对表头与数据",
+                kind, reversed, changed
+            ));
+        }
+    }
+    if issues.is_empty() {
+        return Ok(());
+    }
+    if options.strict {
+        anyhow::bail!(issues.join("；"));
+    }
+    for issue in issues {
+        log_line!("警告：{}", issue);
+    }
+    Ok(())
+}
+
+// 解析单元格引用（如 "B2"），返回从0开始的 (行, 列)
+pub fn parse_cell_ref(cell: &str) -> Option<(usize, usize)> {
+    let cell = cell.trim().to_uppercase();
+    let letters_end = cell.find(|c: char| !c.is_ascii_uppercase())?;
+    let (letters, digits) = cell.split_at(letters_end);
+    if letters.is_empty() || letters.len() > 3 || digits.is_empty() || !digits.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let col = letters.bytes().fold(0usize, |acc, b| acc * 26 + (b - b'A' + 1) as usize) - 1;
+    let row = digits.parse::<usize>().ok()?.checked_sub(1)?;
+    Some((row, col))
+}
+
+fn building_name_cell_ref(options: &ReadOptions) -> Result<Option<(usize, usize)>> {
+    options
+        .building_name_cell
+        .as_deref()
+        .map(|cell| parse_cell_ref(cell).with_context(|| format!("无效的单元格引用: {}", cell)))
+        .transpose()
+}
+
+// 各行未填写小区名称时使用单元格中的名称
+fn apply_building_name(bills: &mut [MerchantBill], name: Option<String>) {
+    let Some(name) = name.map(|n| n.trim().to_string()).filter(|n| !n.is_empty()) else { return };
+    for bill in bills.iter_mut().filter(|b| b.building_name.is_none()) {
+        bill.building_name = Some(name.clone());
+    }
+}
+
+pub fn read_excel_file(file_path: &str, headers_map: &HeadersMap, options: &ReadOptions) -> Result<Vec<MerchantBill>> {
+    let workbook: Xlsx<_> = open_workbook(file_path)
+        .with_context(|| format!("无法打开Excel文件: {}", file_path))?;
+    read_excel_workbook(workbook, headers_map, options)
+}
+
+// 从内存中的xlsx内容读取（如通过URL下载的文件）
+pub fn read_excel_bytes(bytes: &[u8], headers_map: &HeadersMap, options: &ReadOptions) -> Result<Vec<MerchantBill>> {
+    let workbook = Xlsx::new(Cursor::new(bytes.to_vec())).context("无法解析Excel内容")?;
+    read_excel_workbook(workbook, headers_map, options)
+}
+
+// 第一个有数据的工作表：空表、仅含图表等无法读取的工作表跳过并提示
+pub fn first_data_sheet<RS: Read + Seek>(workbook: &mut Xlsx<RS>) -> Result<(String, calamine::Range<DataType>)> {
+    let sheet_names = workbook.sheet_names().to_vec();
+    for sheet_name in &sheet_names {
+        let range = match workbook.worksheet_range(sheet_name) {
+            Some(Ok(range)) => range,
+            Some(Err(e)) => {
+                log_line!("警告：工作表为空或无法读取：{}（{}），已跳过", sheet_name, e);
+                continue;
+            }
+            None => continue,
+        };
+        if range.rows().all(|row| row.iter().all(|c| c.to_string().trim().is_empty())) {
+            log_line!("警告：工作表为空或无法读取：{}，已跳过", sheet_name);
+            continue;
+        }
+        return Ok((sheet_name.clone(), range));
+    }
+    match sheet_names.as_slice() {
+        [] => anyhow::bail!("Excel中没有工作表"),
+        names => anyhow::bail!("工作表为空或无法读取：{}，请检查是否选错工作表", names.join("、")),
+    }
+}
+
+fn read_excel_workbook<RS: Read + Seek>(mut workbook: Xlsx<RS>, headers_map: &HeadersMap, options: &ReadOptions) -> Result<Vec<MerchantBill>> {
+    let (_, range) = first_data_sheet(&mut workbook)?;
+
+    let mut rows = range.rows();
+    let header_row = rows.next().context("Excel中缺少表头行")?;
+    let headers: Vec<String> = header_row.iter().map(|c| c.to_string()).collect();
+    
+    log_line!("调试：Excel表头: {:?}", headers);
+    
+    // 直接查找列索引，不使用find_indices
+    let code_i = headers.iter().position(|h| h.contains("铺面编号")).context("找不到铺面编号列")?;
+    let m_i = headers.iter().position(|h| h.contains("店铺名称")).context("找不到店铺名称列")?;
+    // 新排序：优先电表1，然后水表，上到下
+    let prefix = headers_map.electricity_prefix;
+    let templates = meter_header_templates(prefix, options);
+    let e1p_i = find_meter_column(&headers, &templates, 1, "上期读数").with_context(|| format!("找不到{}1上期读数列", prefix))?;
+    let e1c_i = find_meter_column(&headers, &templates, 1, "本期读数").with_context(|| format!("找不到{}1本期读数列", prefix))?;
+    let wp_i = headers.iter().position(|h| h.contains("上期水表读数")).context("找不到上期水表读数列")?;
+    let wc_i = headers.iter().position(|h| h.contains("本期水表读数")).context("找不到本期水表读数列")?;
+    let price_columns = find_price_columns(&headers)?;
+
+    // 水电人工费和垃圾处理费列（可选，缺失时按0处理，可由 GenerateOptions 的标准收费补齐）
+    let labor_fee_i = headers.iter().position(|h| h.contains("水电人工费"));
+    let garbage_fee_i = headers.iter().position(|h| h.contains("垃圾处理费"));
+    // 可选：户主姓名/联系人列
+    let contact_i = headers.iter().position(|h| h.contains("户主姓名") || h.contains("联系人"));
+    // 可选：纳税人识别号列（开票用）
+    let tax_id_i = headers.iter().position(|h| h.contains("纳税人识别号") || h.contains("税号"));
+    // 可选：账单月份列（补开往月账单时使用）
+    let month_i = headers.iter().position(|h| h.contains("账单月份"));
+    // 可选：计费周期起止日期列
+    let (period_start_i, period_end_i) = find_period_columns(&headers);
+    // 可选：小区名称列
+    let building_i = headers.iter().position(|h| h.contains("小区名称"));
+    // 可选：实收金额列（对账用）
+    let received_i = headers.iter().position(|h| h.contains("实收金额"));
+    // 可选：优惠/折扣列（固定金额或 "-10%" 形式的比例）
+    let discount_i = headers.iter().position(|h| h.contains("优惠") || h.contains("折扣"));
+    // 可选：免抄/估抄标记列
+    let estimated_i = headers.iter().position(|h| h.contains("免抄") || h.contains("估抄"));
+    // 可选：其他费用列（"其他费用：停车费"等，可有多列）
+    let extra_fee_columns = find_extra_fee_columns(&headers);
+
+    // 找到所有电表相关的列（包含已知的电表1）
+    let mut electricity_columns = find_electricity_columns(&headers, &templates)?;
+    // 确保电表1优先（若已存在则不重复）
+    if !electricity_columns.iter().any(|(p,c)| *p==e1p_i && *c==e1c_i) {
+        electricity_columns.insert(0, (e1p_i, e1c_i));
+    }
+
+    let meter_price_columns = find_meter_price_columns(&headers, &templates, electricity_columns.len());
+    let meter_serial_columns = find_meter_serial_columns(&headers, &templates, electricity_columns.len());
+    let meter_digits_columns = find_meter_digits_columns(&headers, &templates, electricity_columns.len());
+    for collision in meter_id_collisions(&headers, &templates, electricity_columns.len()) {
+        log_line!("警告：{}", collision);
+    }
+
+    log_line!("调试：Excel基础列索引 - 商家:{}, 水表上期:{}, 水表本期:{}, 单价列(水,电):{:?}, 水电人工费:{:?}, 垃圾处理费:{:?}", 
+             m_i, wp_i, wc_i, price_columns, labor_fee_i, garbage_fee_i);
+    log_line!("调试：Excel电表列: {:?}", electricity_columns);
+
+    let mut bills = Vec::new();
+    let mut parse_errors: Vec<String> = Vec::new();
+    let mut price_issues: Vec<String> = Vec::new();
+    for (row_idx, row) in rows.enumerate() {
+        if row.is_empty() { continue; }
+        let mut merchant_name = row.get(m_i).map(|c| c.to_string()).unwrap_or_default();
+        let mut shop_code = row.get(code_i).map(|c| c.to_string()).unwrap_or_default();
+        if options.normalize_full_width {
+            merchant_name = normalize_width(&merchant_name);
+            shop_code = normalize_width(&shop_code);
+        }
+        let Some(merchant_name) = resolve_merchant_name(merchant_name, &shop_code, row_idx + 2, options) else { continue };
+
+        let meter_prices: Vec<Option<f64>> = meter_price_columns
+            .iter()
+            .map(|col| col.and_then(|i| optional_amount(cell_amount(row.get(i)), &mut parse_errors, row_idx + 2, &headers[i])))
+            .collect();
+        let meter_digits: Vec<Option<u32>> = meter_digits_columns
+            .iter()
+            .map(|col| col.and_then(|i| rollover_digits(cell_amount(row.get(i)), &mut parse_errors, row_idx + 2, &headers[i])))
+            .collect();
+
+        let mut num = |i: usize| -> f64 {
+            let column = headers.get(i).map(String::as_str).unwrap_or("");
+            amount_or_zero(cell_amount(row.get(i)), &mut parse_errors, row_idx + 2, column)
+        };
+        
+        let (water_price, electricity_price) = price_columns.map(|(w, e)| (num(w), num(e))).unwrap_or((0.0, 0.0));
+        let prev_water = num(wp_i);
+        let curr_water = num(wc_i);
+
+        let mut bill = MerchantBill::new(merchant_name, water_price, electricity_price);
+        bill.unpriced = price_columns.is_none();
+        bill.electricity_tiers = options.electricity_tiers.clone();
+        bill.set_water_readings(prev_water, curr_water);
+        bill.set_shop_code(shop_code);
+        bill.contact_name = contact_i
+            .and_then(|i| row.get(i))
+            .map(|c| c.to_string().trim().to_string())
+            .filter(|n| !n.is_empty());
+        bill.tax_id = tax_id_i
+            .and_then(|i| row.get(i))
+            .map(cell_text)
+            .filter(|n| !n.is_empty());
+        // Excel数值只保留15位有效数字，数值格式的长税号可能已失真
+        if let Some(DataType::Float(f)) = tax_id_i.and_then(|i| row.get(i)) {
+            if f.abs() >= 1e15 {
+                log_line!("警告：第{}行「纳税人识别号」为数值格式，超过15位的部分可能已失真，请将该列设为文本格式", row_idx + 2);
+            }
+        }
+        bill.building_name = building_i
+            .and_then(|i| row.get(i))
+            .map(|c| c.to_string().trim().to_string())
+            .filter(|n| !n.is_empty());
+
+        // 处理每个电表
+        for (meter_id, (prev_col, curr_col)) in electricity_columns.iter().enumerate() {
+            let prev_reading = num(*prev_col);
+            let curr_reading = num(*curr_col);
+            if options.list_all_meters || prev_reading > 0.0 || curr_reading > 0.0 {
+                match meter_prices[meter_id] {
+                    Some(price) => bill.add_priced_electricity_meter(format!("{}", meter_id + 1), prev_reading, curr_reading, price),
+                    None => bill.add_electricity_meter(format!("{}", meter_id + 1), prev_reading, curr_reading),
+                }
+                if let Some(meter) = bill.electricity_meters.last_mut() {
+                    meter.serial = meter_serial_columns[meter_id]
+                        .and_then(|i| row.get(i))
+                        .map(|c| c.to_string().trim().to_string())
+                        .filter(|s| !s.is_empty());
+                }
+                if meter_digits[meter_id].is_some() {
+                    bill.set_meter_rollover_digits(bill.electricity_meters.len() - 1, meter_digits[meter_id]);
+                }
+            }
+        }
+        if estimated_i.and_then(|i| row.get(i)).is_some_and(|c| is_flag_set(&c.to_string())) {
+            apply_estimation(&mut bill, row_idx + 2, options);
+        }
+
+        // 从Excel读取水电人工费和垃圾处理费
+        let labor_fee = labor_fee_i.map(&mut num).unwrap_or(0.0);
+        let garbage_fee = garbage_fee_i.map(&mut num).unwrap_or(0.0);
+        // 其他费用：空白或为0视为该户没有此项
+        bill.extra_fees = extra_fee_columns
+            .iter()
+            .map(|(i, name)| ExtraFee { name: name.clone(), amount: num(*i) })
+            .filter(|fee| fee.amount != 0.0)
+            .collect();
+        bill.water_electricity_labor_fee = labor_fee;
+        bill.garbage_disposal_fee = garbage_fee;
+        if let Some(i) = discount_i {
+            bill.discount = optional_discount(cell_discount(row.get(i)), &mut parse_errors, row_idx + 2, &headers[i]);
+        }
+        bill.update_totals();
+
+        if let Some(i) = received_i {
+            bill.received_amount = optional_amount(cell_amount(row.get(i)), &mut parse_errors, row_idx + 2, &headers[i]).map(round_cents);
+        }
+
+        bill.period_start = period_start_i.and_then(|i| row.get(i)).and_then(period_date_from_cell);
+        bill.period_end = period_end_i.and_then(|i| row.get(i)).and_then(period_date_from_cell);
+
+        // 账单月份：该列缺失或为空时沿用当月
+        if let Some(cell) = month_i.and_then(|i| row.get(i)).filter(|c| !c.is_empty()) {
+            match billing_month_from_cell(cell) {
+                Some(month) => bill.month = month,
+                None => parse_errors.push(format!("第{}行「账单月份」无法识别: {}", row_idx + 2, cell)),
+            }
+        }
+
+        price_issues.extend(negative_price_issues(&bill, row_idx + 2, prefix));
+        bills.push(bill);
+    }
+    if let Some((row, col)) = building_name_cell_ref(options)? {
+        let name = range.get_value((row as u32, col as u32)).map(|c| c.to_string());
+        apply_building_name(&mut bills, name);
+    }
+    for e in &parse_errors {
+        log_line!("警告：{}", e);
+    }
+    report_price_issues(&price_issues, options)?;
+    check_swapped_readings(&bills, options)?;
+    check_zero_total(&bills, options)?;
+    Ok(bills)
+}
+
+pub fn read_csv_file(file_path: &str, headers_map: &HeadersMap, options: &ReadOptions) -> Result<Vec<MerchantBill>> {
+    let file = File::open(file_path)
+        .with_context(|| format!("无法打开CSV文件: {}", file_path))?;
+    read_csv_reader(BufReader::new(file), headers_map, options)
+}
+
+// 从内存中的CSV内容读取（如通过URL下载的文件）
+pub fn read_csv_bytes(bytes: &[u8], headers_map: &HeadersMap, options: &ReadOptions) -> Result<Vec<MerchantBill>> {
+    read_csv_reader(bytes, headers_map, options)
+}
+
+// 按CSV规则切分记录（csv crate），返回 (起始行号, 字段)；双引号内的逗号、换行属于字段内容，"" 表示一个引号。
+// 行尾的 \r\n 同样识别；Google表格导出的CSV（每个字段都带引号、CRLF换行，如 "123","3.5"）去掉引号后再解析数字，
+// 千分位（"1,234"）在引号内不会被当作分隔符。空行不产生记录
+fn csv_records<R: Read>(mut reader: R) -> Result<Vec<(usize, Vec<String>)>> {
+    let mut text = String::new();
+    reader.read_to_string(&mut text).context("读取CSV内容失败")?;
+    let mut csv_reader = csv::ReaderBuilder::new().has_headers(false).flexible(true).from_reader(text.as_bytes());
+    let mut records = Vec::new();
+    let mut last_start = None;
+    for record in csv_reader.records() {
+        let record = record.context("CSV格式错误")?;
+        let line = record.position().map(|p| p.line() as usize).unwrap_or(records.len() + 1);
+        last_start = record.position().map(|p| (p.byte() as usize, line));
+        records.push((line, record.iter().map(str::to_string).collect()));
+    }
+    // csv crate 对未闭合的引号不报错，而是一直读到文件末尾并作为最后一条记录返回；
+    // 这里检查最后一条记录在输入结束时是否仍处于引号内
+    if let Some((start, line)) = last_start {
+        if ends_inside_quotes(&text[start..]) {
+            anyhow::bail!("CSV第{}行开始的引号未闭合", line);
+        }
+    }
+    Ok(records)
+}
+
+// 按CSV规则扫描一段文本，返回结束时是否仍在引号字段内。
+// 只有字段开头的引号才开启引用，未加引号字段中间的引号（如 A"B）是普通字符
+fn ends_inside_quotes(text: &str) -> bool {
+    let mut in_quotes = false;
+    let mut field_start = true;
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if in_quotes {
+            if c == '"' {
+                // 引号字段中的 "" 为转义的引号
+                if chars.peek() == Some(&'"') {
+                    chars.next();
+                } else {
+                    in_quotes = false;
+                }
+            }
+        } else if c == ',' || c == '\n' || c == '\r' {
+            field_start = true;
+            continue;
+        } else if c == '"' && field_start {
+            in_quotes = true;
+        }
+        field_start = false;
+    }
+    in_quotes
+}
+
+// CSV文件编码：Windows 版 Excel 另存的CSV常为带BOM的UTF-8或GBK（GB18030）
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CsvEncoding {
+    #[default]
+    Auto,
+    Utf8,
+    Gbk,
+}
+
+// 解码CSV内容：去掉开头的UTF-8 BOM（否则会粘在第一个表头上，导致"铺面编号"等列匹配失败）；
+// 按 csv_encoding 以UTF-8或GBK解码，自动识别时不是有效UTF-8的内容按GBK尝试。
+// UTF-8含无效字节时默认报错，lenient_utf8 时替换为 U+FFFD 并提示所在行
+pub fn decode_csv_text(bytes: &[u8], options: &ReadOptions) -> Result<String> {
+    if let Some(rest) = bytes.strip_prefix(b"\xEF\xBB\xBF") {
+        return decode_utf8_csv(rest, options);
+    }
+    match options.csv_encoding {
+        CsvEncoding::Gbk => decode_gbk_csv(bytes),
+        // 个别无效字节的UTF-8文件也可能"成功"按GBK解码成乱码，只有解码后表头能识别时才认定为GBK
+        CsvEncoding::Auto if std::str::from_utf8(bytes).is_err() => match decode_gbk_csv(bytes) {
+            Ok(text) if has_known_header(text.lines().next().unwrap_or("")) => {
+                log_line!("提示：CSV不是UTF-8编码，已按GBK编码读取");
+                Ok(text)
+            }
+            _ => decode_utf8_csv(bytes, options),
+        },
+        _ => decode_utf8_csv(bytes, options),
+    }
+}
+
+// 表头行是否包含可识别的列名（见 COLUMN_SCHEMA）
+fn has_known_header(header_line: &str) -> bool {
+    COLUMN_SCHEMA.iter().flat_map(|spec| spec.headers.iter()).any(|h| header_line.contains(h))
+}
+
+// 按GBK（GB18030）解码；含无法解码的字节时报错，不生成乱码表头
+fn decode_gbk_csv(bytes: &[u8]) -> Result<String> {
+    let (text, had_errors) = encoding_rs::GB18030.decode_without_bom_handling(bytes);
+    if had_errors {
+        anyhow::bail!("CSV内容无法按GBK编码解码，请确认文件编码（建议另存为UTF-8格式的CSV）");
+    }
+    Ok(text.into_owned())
+}
+
+fn decode_utf8_csv(bytes: &[u8], options: &ReadOptions) -> Result<String> {
+    let mut bad_lines = Vec::new();
+    let mut line = 1;
+    for chunk in bytes.utf8_chunks() {
+        line += chunk.valid().matches('\n').count();
+        if !chunk.invalid().is_empty() && bad_lines.last() != Some(&line) {
+            bad_lines.push(line);
+        }
+    }
+    if bad_lines.is_empty() {
+        return Ok(String::from_utf8_lossy(bytes).into_owned());
+    }
+    let lines = bad_lines.iter().map(|l| l.to_string()).collect::<Vec<_>>().join("、");
+    if !options.lenient_utf8 {
+        anyhow::bail!("CSV第{}行包含无效的UTF-8字节，请确认文件编码为UTF-8或GBK（或启用宽松模式读取）", lines);
+    }
+    log_line!("警告：CSV第{}行包含无效的UTF-8字节，已替换为�读取，相关数据可能有误，请核对", lines);
+    Ok(String::from_utf8_lossy(bytes).into_owned())
+}
+
+fn read_csv_reader<R: BufRead>(mut reader: R, headers_map: &HeadersMap, options: &ReadOptions) -> Result<Vec<MerchantBill>> {
+    let mut bytes = Vec::new();
+    reader.read_to_end(&mut bytes).context("读取CSV内容失败")?;
+    let text = decode_csv_text(&bytes, options)?;
+    let records = csv_records(text.as_bytes())?;
+    let building_name = building_name_cell_ref(options)?
+        .and_then(|(row, col)| records.iter().find(|(line, _)| *line == row + 1).and_then(|(_, fields)| fields.get(col)).cloned());
+    let mut records = records.into_iter();
+    let (_, header_fields) = records.next().context("CSV中缺少表头行")?;
+    let headers: Vec<String> = header_fields.iter().map(|s| s.trim().to_string()).collect();
+
+    log_line!("调试：找到的表头: {:?}", headers);
+
+    // 直接查找列索引，不使用find_indices
+    let code_i = headers.iter().position(|h| h.contains("铺面编号")).context("找不到铺面编号列")?;
+    let m_i = headers.iter().position(|h| h.contains("店铺名称")).context("找不到店铺名称列")?;
+    let prefix = headers_map.electricity_prefix;
+    let templates = meter_header_templates(prefix, options);
+    let e1p_i = find_meter_column(&headers, &templates, 1, "上期读数").with_context(|| format!("找不到{}1上期读数列", prefix))?;
+    let e1c_i = find_meter_column(&headers, &templates, 1, "本期读数").with_context(|| format!("找不到{}1本期读数列", prefix))?;
+    let wp_i = headers.iter().position(|h| h.contains("上期水表读数")).context("找不到上期水表读数列")?;
+    let wc_i = headers.iter().position(|h| h.contains("本期水表读数")).context("找不到本期水表读数列")?;
+    let price_columns = find_price_columns(&headers)?;
+    
+    // 水电人工费和垃圾处理费列（可选，缺失时按0处理，可由 GenerateOptions 的标准收费补齐）
+    let labor_fee_i = headers.iter().position(|h| h.contains("水电人工费"));
+    let garbage_fee_i = headers.iter().position(|h| h.contains("垃圾处理费"));
+    // 可选：户主姓名/联系人列
+    let contact_i = headers.iter().position(|h| h.contains("户主姓名") || h.contains("联系人"));
+    // 可选：纳税人识别号列（开票用）
+    let tax_id_i = headers.iter().position(|h| h.contains("纳税人识别号") || h.contains("税号"));
+    // 可选：账单月份列（补开往月账单时使用）
+    let month_i = headers.iter().position(|h| h.contains("账单月份"));
+    // 可选：计费周期起止日期列
+    let (period_start_i, period_end_i) = find_period_columns(&headers);
+    // 可选：小区名称列
+    let building_i = headers.iter().position(|h| h.contains("小区名称"));
+    // 可选：实收金额列（对账用）
+    let received_i = headers.iter().position(|h| h.contains("实收金额"));
+    // 可选：优惠/折扣列（固定金额或 "-10%" 形式的比例）
+    let discount_i = headers.iter().position(|h| h.contains("优惠") || h.contains("折扣"));
+    // 可选：免抄/估抄标记列
+    let estimated_i = headers.iter().position(|h| h.contains("免抄") || h.contains("估抄"));
+    // 可选：其他费用列（"其他费用：停车费"等，可有多列）
+    let extra_fee_columns = find_extra_fee_columns(&headers);
+
+    let mut electricity_columns = find_electricity_columns(&headers, &templates)?;
+    if !electricity_columns.iter().any(|(p,c)| *p==e1p_i && *c==e1c_i) {
+        electricity_columns.insert(0, (e1p_i, e1c_i));
+    }
+
+    let meter_price_columns = find_meter_price_columns(&headers, &templates, electricity_columns.len());
+    let meter_serial_columns = find_meter_serial_columns(&headers, &templates, electricity_columns.len());
+    let meter_digits_columns = find_meter_digits_columns(&headers, &templates, electricity_columns.len());
+    for collision in meter_id_collisions(&headers, &templates, electricity_columns.len()) {
+        log_line!("警告：{}", collision);
+    }
+
+    log_line!("调试：基础列索引 - 商家:{}, 水表上期:{}, 水表本期:{}, 单价列(水,电):{:?}, 水电人工费:{:?}, 垃圾处理费:{:?}", 
+             m_i, wp_i, wc_i, price_columns, labor_fee_i, garbage_fee_i);
+    log_line!("调试：电表列: {:?}", electricity_columns);
+
+    // 读数、单价列：某行字段数不足以覆盖这些列时（多为少了分隔符），与空白读数区分开单独提示
+    let mut reading_columns: Vec<usize> = electricity_columns.iter().flat_map(|(p, c)| [*p, *c]).collect();
+    reading_columns.extend([wp_i, wc_i]);
+    reading_columns.extend(price_columns.map(|(w, e)| [w, e]).into_iter().flatten());
+    reading_columns.sort_unstable();
+
+    let mut bills = Vec::new();
+    let mut parse_errors: Vec<String> = Vec::new();
+    let mut price_issues: Vec<String> = Vec::new();
+    for (line_no, parts) in records {
+        if parts.iter().all(|p| p.trim().is_empty()) { continue; }
+        // 字段数不足以包含基础列的行（多为误换行或少了分隔符）跳过，并提示行号以免静默丢失
+        if parts.len() < 5 {
+            log_line!("警告：第{}行只有{}列，不足以包含铺面编号、店铺名称、读数等基础列，已跳过该行", line_no, parts.len());
+            continue;
+        }
+        
+        let get = |i: usize| -> &str { parts.get(i).map(String::as_str).unwrap_or("") };
+        let missing: Vec<&str> = reading_columns.iter().filter(|i| **i >= parts.len()).map(|i| headers[*i].as_str()).collect();
+        if !missing.is_empty() {
+            parse_errors.push(format!("第{}行只有{}列，缺少「{}」列（并非空白读数，请检查该行是否少了分隔符），已按0处理", line_no, parts.len(), missing.join("」「")));
+        }
+        
+        let (mut merchant_name, mut shop_code) = (get(m_i).trim().to_string(), get(code_i).trim().to_string());
+        if options.normalize_full_width {
+            merchant_name = normalize_width(&merchant_name);
+            shop_code = normalize_width(&shop_code);
+        }
+        let Some(merchant_name) = resolve_merchant_name(merchant_name, &shop_code, line_no, options) else { continue };
+
+        let meter_prices: Vec<Option<f64>> = meter_price_columns
+            .iter()
+            .map(|col| col.and_then(|i| optional_amount(parse_amount(get(i)), &mut parse_errors, line_no, &headers[i])))
+            .collect();
+        let meter_digits: Vec<Option<u32>> = meter_digits_columns
+            .iter()
+            .map(|col| col.and_then(|i| rollover_digits(parse_amount(get(i)), &mut parse_errors, line_no, &headers[i])))
+            .collect();
+
+        let mut num = |i: usize| -> f64 {
+            let column = headers.get(i).map(String::as_str).unwrap_or("");
+            amount_or_zero(parse_amount(get(i)), &mut parse_errors, line_no, column)
+        };
+        
+        let (water_price, electricity_price) = price_columns.map(|(w, e)| (num(w), num(e))).unwrap_or((0.0, 0.0));
+        let prev_water = num(wp_i);
+        let curr_water = num(wc_i);
+
+        let mut bill = MerchantBill::new(merchant_name, water_price, electricity_price);
+        bill.unpriced = price_columns.is_none();
+        bill.electricity_tiers = options.electricity_tiers.clone();
+        bill.set_water_readings(prev_water, curr_water);
+        bill.set_shop_code(shop_code);
+        bill.contact_name = contact_i
+            .map(|i| get(i).trim().to_string())
+            .filter(|n| !n.is_empty());
+        bill.tax_id = tax_id_i
+            .map(|i| get(i).trim().to_string())
+            .filter(|n| !n.is_empty());
+        bill.building_name = building_i
+            .map(|i| get(i).trim().to_string())
+            .filter(|n| !n.is_empty());
+
+        // 处理每个电表
+        for (meter_id, (prev_col, curr_col)) in electricity_columns.iter().enumerate() {
+            let prev_reading = num(*prev_col);
+            let curr_reading = num(*curr_col);
+            if options.list_all_meters || prev_reading > 0.0 || curr_reading > 0.0 {
+                match meter_prices[meter_id] {
+                    Some(price) => bill.add_priced_electricity_meter(format!("{}", meter_id + 1), prev_reading, curr_reading, price),
+                    None => bill.add_electricity_meter(format!("{}", meter_id + 1), prev_reading, curr_reading),
+                }
+                if let Some(meter) = bill.electricity_meters.last_mut() {
+                    meter.serial = meter_serial_columns[meter_id]
+                        .map(|i| get(i).trim().to_string())
+                        .filter(|s| !s.is_empty());
+                }
+                if meter_digits[meter_id].is_some() {
+                    bill.set_meter_rollover_digits(bill.electricity_meters.len() - 1, meter_digits[meter_id]);
+                }
+            }
+        }
+        if estimated_i.is_some_and(|i| is_flag_set(get(i))) {
+            apply_estimation(&mut bill, line_no, options);
+        }
+
+        // 从CSV读取水电人工费和垃圾处理费
+        let labor_fee = labor_fee_i.map(&mut num).unwrap_or(0.0);
+        let garbage_fee = garbage_fee_i.map(&mut num).unwrap_or(0.0);
+        // 其他费用：空白或为0视为该户没有此项
+        bill.extra_fees = extra_fee_columns
+            .iter()
+            .map(|(i, name)| ExtraFee { name: name.clone(), amount: num(*i) })
+            .filter(|fee| fee.amount != 0.0)
+            .collect();
+        bill.water_electricity_labor_fee = labor_fee;
+        bill.garbage_disposal_fee = garbage_fee;
+        if let Some(i) = discount_i {
+            bill.discount = optional_discount(Discount::parse(get(i)), &mut parse_errors, line_no, &headers[i]);
+        }
+        bill.update_totals();
+
+        if let Some(i) = received_i {
+            bill.received_amount = optional_amount(parse_amount(get(i)), &mut parse_errors, line_no, &headers[i]).map(round_cents);
+        }
+
+        bill.period_start = period_start_i.map(|i| get(i).trim().to_string()).filter(|d| !d.is_empty());
+        bill.period_end = period_end_i.map(|i| get(i).trim().to_string()).filter(|d| !d.is_empty());
+
+        // 账单月份：该列缺失或为空时沿用当月
+        if let Some(raw) = month_i.map(|i| get(i).trim()).filter(|m| !m.is_empty()) {
+            match normalize_billing_month(raw) {
+                Some(month) => bill.month = month,
+                None => parse_errors.push(format!("第{}行「账单月份」无法识别: {}", line_no, raw)),
+            }
+        }
+
+        price_issues.extend(negative_price_issues(&bill, line_no, prefix));
+        bills.push(bill);
+    }
+    apply_building_name(&mut bills, building_name);
+    for e in &parse_errors {
+        log_line!("警告：{}", e);
+    }
+    report_price_issues(&price_issues, options)?;
+    check_swapped_readings(&bills, options)?;
+    check_zero_total(&bills, options)?;
+    Ok(bills)
+}
+
+pub fn read_data_file(file_path: &str, headers_map: &HeadersMap) -> Result<Vec<MerchantBill>> {
+    read_data_file_with_options(file_path, headers_map, &ReadOptions::default())
+}
+
+pub fn read_data_file_with_options(file_path: &str, headers_map: &HeadersMap, options: &ReadOptions) -> Result<Vec<MerchantBill>> {
+    if is_url(file_path) {
+        #[cfg(feature = "url")]
+        return read_data_url(file_path, headers_map, options);
+        #[cfg(not(feature = "url"))]
+        anyhow::bail!("未启用 url 功能，无法读取网络地址: {}", file_path);
+    }
+    let path = Path::new(file_path);
+    let extension = path.extension().and_then(|e| e.to_str()).unwrap_or("").to_lowercase();
+    let bills = match extension.as_str() {
+        "xlsx" => read_excel_file(file_path, headers_map, options),
+        "csv" => read_csv_file(file_path, headers_map, options),
+        _ => {
+            if file_path.ends_with(".xlsx") { read_excel_file(file_path, headers_map, options) }
+            else if file_path.ends_with(".csv") { read_csv_file(file_path, headers_map, options) }
+            else { anyhow::bail!("不支持的文件格式: {}", extension) }
+        }
+    }?;
+    warn_after_read(&bills);
+    Ok(bills)
+}
+
+// 读取完成后的整批检查（文件、内存内容两条读取路径共用）
+fn warn_after_read(bills: &[MerchantBill]) {
+    warn_price_conflicts(bills);
+    warn_rounding_drifts(bills);
+}
+
+fn warn_price_conflicts(bills: &[MerchantBill]) {
+    for conflict in find_price_conflicts(bills) {
+        log_line!("警告：{}", conflict);
+    }
+}
+
+// 整批账单的电费取整差额合并为一条提示
+fn warn_rounding_drifts(bills: &[MerchantBill]) {
+    let drifts = find_rounding_drifts(bills);
+    if !drifts.is_empty() {
+        log_line!("提示：以下商户电费按总用量取整，与分表取整之和不一致：{}", drifts.join("；"));
+    }
+}
+
+// 按格式（"xlsx"/"csv"）解析内存中的文件内容
+pub fn read_data_bytes(bytes: &[u8], format: &str, headers_map: &HeadersMap, options: &ReadOptions) -> Result<Vec<MerchantBill>> {
+    let bills = match format {
+        "xlsx" => read_excel_bytes(bytes, headers_map, options),
+        "csv" => read_csv_bytes(bytes, headers_map, options),
+        _ => anyhow::bail!("不支持的文件格式: {}", format),
+    }?;
+    warn_after_read(&bills);
+    Ok(bills)
+}
+
+// 数据检查：某个数值列在首个数据行中的原始内容与实际读取的数值
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColumnCoercion {
+    pub column: String,
+    pub raw: String,
+    pub value: f64,             // 读取时实际使用的数值（空值或无法解析时为0）
+    pub error: Option<String>,  // 无法解析时的原因
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct InspectReport {
+    pub headers: Vec<String>,
+    pub data_rows: usize,
+    pub coercions: Vec<ColumnCoercion>,
+}
+
+// 读取时按数值解析的列：水表读数、单价、电表读数与分表单价、各项费用
+fn numeric_columns(headers: &[String], electricity_prefix: &str) -> Vec<usize> {
+    let mut columns: Vec<usize> = ["上期水表读数", "本期水表读数", "水费单价", "电费单价", "水电人工费", "垃圾处理费", "实收金额"]
+        .iter()
+        .filter_map(|name| headers.iter().position(|h| h.contains(name)))
+        .collect();
+    let templates = meter_header_templates(electricity_prefix, &ReadOptions::default());
+    let meters = find_electricity_columns(headers, &templates).unwrap_or_default();
+    columns.extend(meters.iter().flat_map(|(p, c)| [*p, *c]));
+    columns.extend(find_meter_price_columns(headers, &templates, meters.len()).into_iter().flatten());
+    columns.sort_unstable();
+    columns.dedup();
+    columns
+}
+
+// 原始单元格：(原始文本, 数值解析结果)
+type RawCell = (String, Result<f64, ParseNumError>);
+// 原始数据行：(行号, 单元格)
+type RawRow = (usize, Vec<RawCell>);
+
+// 按格式读取原始表格：表头与各数据行（行号, 单元格），不做任何列识别
+fn raw_table(bytes: &[u8], format: &str) -> Result<(Vec<String>, Vec<RawRow>)> {
+    match format {
+        "xlsx" => {
+            let mut workbook = Xlsx::new(Cursor::new(bytes.to_vec())).context("无法解析Excel内容")?;
+            let (_, range) = first_data_sheet(&mut workbook)?;
+            let mut rows = range.rows();
+            let headers = rows.next().context("Excel中缺少表头行")?.iter().map(|c| c.to_string().trim().to_string()).collect();
+            let rows = rows.enumerate().map(|(idx, row)| (idx + 2, row.iter().map(|c| (c.to_string(), cell_amount(Some(c)))).collect())).collect();
+            Ok((headers, rows))
+        }
+        "csv" => {
+            let text = decode_csv_text(bytes, &ReadOptions::default())?;
+            let mut records = csv_records(text.as_bytes())?.into_iter();
+            let (_, header_fields) = records.next().context("CSV中缺少表头行")?;
+            let headers = header_fields.iter().map(|h| h.trim().to_string()).collect();
+            let rows = records
+                .map(|(line, fields)| (line, fields.into_iter().map(|f| { let v = parse_amount(&f); (f, v) }).collect()))
+                .collect();
+            Ok((headers, rows))
+        }
+        _ => anyhow::bail!("不支持的文件格式: {}", format),
+    }
+}
+
+// 检查上传数据的读取方式：表头、数据行数，以及首个数据行各数值列的解析结果
+pub fn inspect_data_bytes(bytes: &[u8], format: &str, electricity_prefix: &str) -> Result<InspectReport> {
+    let (headers, rows) = raw_table(bytes, format)?;
+    let data_rows: Vec<&Vec<RawCell>> = rows.iter().map(|(_, row)| row).filter(|row| row.iter().any(|(raw, _)| !raw.trim().is_empty())).collect();
+    let coercions = match data_rows.first() {
+        Some(row) => numeric_columns(&headers, electricity_prefix)
+            .into_iter()
+            .map(|i| coerce_cell(&headers[i], row.get(i)))
+            .collect(),
+        None => Vec::new(),
+    };
+
+    Ok(InspectReport { data_rows: data_rows.len(), headers, coercions })
+}
+
+// 数值列单元格的读取结果：空值按0，无法解析时按0并附原因（与读取账单时一致）
+fn coerce_cell(column: &str, cell: Option<&RawCell>) -> ColumnCoercion {
+    let (raw, parsed) = cell.cloned().unwrap_or((String::new(), Err(ParseNumError::Empty)));
+    let (value, error) = match parsed {
+        Ok(v) => (v, None),
+        Err(ParseNumError::Empty) => (0.0, None),
+        Err(e) => (0.0, Some(e.to_string())),
+    };
+    ColumnCoercion { column: column.to_string(), raw, value, error }
+}
+
+// 调试输出（读取审计）：逐行列出每个单元格的原始内容、数值列实际读取的数值，以及对应账单的计算结果，
+// 便于不看日志排查某户金额为何不对。bills 为由同一文件读取的账单，按铺面编号与各数据行对应
+pub fn debug_dump(bytes: &[u8], format: &str, electricity_prefix: &str, bills: &[MerchantBill]) -> Result<String> {
+    let (headers, rows) = raw_table(bytes, format)?;
+    let numeric = numeric_columns(&headers, electricity_prefix);
+    let code_i = headers.iter().position(|h| h.contains("铺面编号"));
+    let name_i = headers.iter().position(|h| h.contains("店铺名称"));
+    let num = |v: f64| format_price(v, 0, 4);
+    let mut used = vec![false; bills.len()];
+    let mut out = format!("读取审计：共{}个数据行，生成{}份账单\n", rows.len(), bills.len());
+    for (line, row) in &rows {
+        if row.iter().all(|(raw, _)| raw.trim().is_empty()) {
+            continue;
+        }
+        out.push_str(&format!("\n== 第{}行 ==\n", line));
+        out.push_str("[原始内容 → 读取值]\n");
+        for (i, header) in headers.iter().enumerate() {
+            let raw = row.get(i).map(|(raw, _)| raw.as_str()).unwrap_or("");
+            if !numeric.contains(&i) {
+                out.push_str(&format!("  {}：「{}」\n", header, raw));
+                continue;
+            }
+            let coerced = coerce_cell(header, row.get(i));
+            let note = coerced.error.map(|e| format!("（{}，按0计算）", e)).unwrap_or_default();
+            out.push_str(&format!("  {}：「{}」 → {}{}\n", header, raw, num(coerced.value), note));
+        }
+
+        // 按铺面编号对应账单（未读取铺面编号的账单按店铺名称），同一编号有多行时按出现顺序依次对应
+        let cell_text = |i: Option<usize>| i.and_then(|i| row.get(i)).map(|(raw, _)| raw.trim().to_string()).unwrap_or_default();
+        let (code, name) = (normalize_width(&cell_text(code_i)), cell_text(name_i));
+        let matches = |bill: &MerchantBill| {
+            if bill.shop_code.is_empty() { !name.is_empty() && bill.merchant_name == name } else { bill.shop_code_key() == code }
+        };
+        let Some(idx) = (0..bills.len()).find(|&i| !used[i] && matches(&bills[i])) else {
+            out.push_str("[计算结果] 该行未生成账单（如店铺名称为空被跳过）\n");
+            continue;
+        };
+        used[idx] = true;
+        let bill = &bills[idx];
+        let code_text = if bill.shop_code.is_empty() { String::new() } else { format!("（{}）", bill.shop_code) };
+        out.push_str(&format!("[计算结果] {}{}\n", bill.merchant_name, code_text));
+        out.push_str(&format!(
+            "  水费：{} → {}，用水量{} × 单价{} = {}\n",
+            num(bill.prev_water_reading), num(bill.curr_water_reading), num(bill.water_usage), num(bill.water_unit_price), num(bill.water_amount)
+        ));
+        for (i, meter) in bill.electricity_meters.iter().enumerate() {
+            let price = meter.unit_price.unwrap_or(bill.electricity_unit_price);
+            let estimated = if meter.estimated { "（估）" } else { "" };
+            out.push_str(&format!(
+                "  电表{}{}：{} → {}，用电量{} × 单价{} = {}\n",
+                i + 1, estimated, num(meter.prev_reading), num(meter.curr_reading), num(meter.usage), num(price), num(meter.amount)
+            ));
+        }
+        out.push_str(&format!("  电费合计：{}\n", num(bill.electricity_amount)));
+        out.push_str(&format!("  水电人工费：{}，垃圾处理费：{}\n", num(bill.water_electricity_labor_fee), num(bill.garbage_disposal_fee)));
+        for fee in &bill.extra_fees {
+            out.push_str(&format!("  {}：{}\n", fee.name, num(fee.amount)));
+        }
+        if bill.discount_amount != 0.0 {
+            out.push_str(&format!("  优惠：{}\n", num(bill.discount_amount)));
+        }
+        out.push_str(&format!("  总价：{}\n", num(bill.total_fee)));
+    }
+    Ok(out)
+}
+
+pub fn is_url(input: &str) -> bool {
+    let lower = input.trim().to_lowercase();
+    lower.starts_with("http://") || lower.starts_with("https://")
+}
+
+// 根据Content-Type判断格式，无法判断时使用URL路径的扩展名
+#[cfg(feature = "url")]
+fn detect_url_format(url: &str, content_type: Option<&str>) -> Option<&'static str> {
+    let content_type = content_type.unwrap_or("").to_lowercase();
+    if content_type.contains("spreadsheetml") || content_type.contains("ms-excel") {
+        return Some("xlsx");
+    }
+    if content_type.contains("text/csv") {
+        return Some("csv");
+    }
+    let path = url.split(['?', '#']).next().unwrap_or("").to_lowercase();
+    if path.ends_with(".xlsx") {
+        Some("xlsx")
+    } else if path.ends_with(".csv") || content_type.starts_with("text/") {
+        Some("csv")
+    } else {
+        None
+    }
+}
+
+// 通过 http(s) 下载文件到内存，返回内容及格式（"xlsx"/"csv"）
+#[cfg(feature = "url")]
+pub fn fetch_data_url(url: &str) -> Result<(Vec<u8>, &'static str)> {
+    let response = reqwest::blocking::get(url)
+        .with_context(|| format!("无法下载文件: {}", url))?
+        .error_for_status()
+        .with_context(|| format!("下载文件失败: {}", url))?;
+    let content_type = response
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.to_string());
+    let format = detect_url_format(url, content_type.as_deref())
+        .with_context(|| format!("无法判断文件格式（Content-Type: {}）: {}", content_type.as_deref().unwrap_or("未知"), url))?;
+    let bytes = response.bytes().with_context(|| format!("读取下载内容失败: {}", url))?;
+    Ok((bytes.to_vec(), format))
+}
+
+#[cfg(feature = "url")]
+pub fn read_data_url(url: &str, headers_map: &HeadersMap, options: &ReadOptions) -> Result<Vec<MerchantBill>> {
+    let (bytes, format) = fetch_data_url(url)?;
+    read_data_bytes(&bytes, format, headers_map, options)
+}
+
+// 账单月份统一为 "YYYY年MM月"；支持 2025-07、2025/7、2025年7月、202507 等写法
+pub fn normalize_billing_month(raw: &str) -> Option<String> {
+    let groups: Vec<&str> = raw.split(|c: char| !c.is_ascii_digit()).filter(|g| !g.is_empty()).collect();
+    let (year, month) = match groups.as_slice() {
+        [ym] if ym.len() == 6 => (ym[..4].parse::<i32>().ok()?, ym[4..].parse::<u32>().ok()?),
+        [y, m, ..] if y.len() == 4 => (y.parse::<i32>().ok()?, m.parse::<u32>().ok()?),
+        _ => return None,
+    };
+    if !(1..=12).contains(&month) {
+        return None;
+    }
+    Some(format!("{}年{:02}月", year, month))
+}
+
+// 计费周期起止日期列（"计费起始日期"/"计费开始日期"、"计费截止日期"/"计费结束日期"）
+fn find_period_columns(headers: &[String]) -> (Option<usize>, Option<usize>) {
+    let start = headers.iter().position(|h| h.contains("计费起始日期") || h.contains("计费开始日期"));
+    let end = headers.iter().position(|h| h.contains("计费截止日期") || h.contains("计费结束日期"));
+    (start, end)
+}
+
+// 计费周期日期单元格：Excel日期显示为 "2025/07/01"，文本按原样保留，空白为None
+fn period_date_from_cell(cell: &DataType) -> Option<String> {
+    match cell {
+        DataType::DateTime(serial) => {
+            let base = chrono::NaiveDate::from_ymd_opt(1899, 12, 30)?;
+            let date = base + chrono::Duration::days(*serial as i64);
+            Some(date.format("%Y/%m/%d").to_string())
+        }
+        other => Some(other.to_string().trim().to_string()).filter(|d| !d.is_empty()),
+    }
+}
+
+// Excel 中的月份可能是日期单元格（序列号），需先换算为日期
+fn billing_month_from_cell(cell: &DataType) -> Option<String> {
+    match cell {
+        DataType::DateTime(serial) => {
+            let base = chrono::NaiveDate::from_ymd_opt(1899, 12, 30)?;
+            let date = base + chrono::Duration::days(*serial as i64);
+            Some(date.format("%Y年%m月").to_string())
+        }
+        other => normalize_billing_month(&other.to_string()),
+    }
+}
+
+// 自然排序的分段：连续数字为一段，其余字符为一段
+#[derive(Debug, PartialEq, Eq)]
+enum NaturalChunk<'a> {
+    Text(String),
+    Number(&'a str),
+}
+
+// 分隔符 - _ / . 空格 视为同一种，字母不区分大小写
+fn natural_chunks(s: &str) -> Vec<NaturalChunk<'_>> {
+    let mut chunks = Vec::new();
+    let mut rest = s.trim();
+    while let Some(first) = rest.chars().next() {
+        let is_digit = first.is_ascii_digit();
+        let end = rest.find(|c: char| c.is_ascii_digit() != is_digit).unwrap_or(rest.len());
+        let (chunk, tail) = rest.split_at(end);
+        if is_digit {
+            chunks.push(NaturalChunk::Number(chunk));
+        } else {
+            let text: String = chunk
+                .chars()
+                .map(|c| if matches!(c, '-' | '_' | '/' | '.' | ' ') { '-' } else { c })
+                .flat_map(char::to_lowercase)
+                .collect();
+            chunks.push(NaturalChunk::Text(text));
+        }
+        rest = tail;
+    }
+    chunks
+}
+
+// 比较数字段：去掉前导0后先比长度再逐位比较，避免超长编号溢出
+fn cmp_number_chunk(a: &str, b: &str) -> Ordering {
+    let a_trimmed = a.trim_start_matches('0');
+    let b_trimmed = b.trim_start_matches('0');
+    a_trimmed
+        .len()
+        .cmp(&b_trimmed.len())
+        .then_with(|| a_trimmed.cmp(b_trimmed))
+}
+
+// 自然排序比较铺面编号等字符串："A-2" < "A-10"
+// 数值相同的数字段（如 "01" 与 "1"）视为相等，最后再按原字符串比较以保证顺序稳定
+pub fn natural_cmp(a: &str, b: &str) -> Ordering {
+    // 全角编号（如 "Ａ－１０"）按半角比较
+    let (a_norm, b_norm) = (normalize_width(a), normalize_width(b));
+    let a_chunks = natural_chunks(&a_norm);
+    let b_chunks = natural_chunks(&b_norm);
+    for (x, y) in a_chunks.iter().zip(b_chunks.iter()) {
+        let ord = match (x, y) {
+            (NaturalChunk::Number(x), NaturalChunk::Number(y)) => cmp_number_chunk(x, y),
+            (NaturalChunk::Text(x), NaturalChunk::Text(y)) => x.cmp(y),
+            // 数字段排在文字段之前
+            (NaturalChunk::Number(_), NaturalChunk::Text(_)) => Ordering::Less,
+            (NaturalChunk::Text(_), NaturalChunk::Number(_)) => Ordering::Greater,
+        };
+        if ord != Ordering::Equal {
+            return ord;
+        }
+    }
+    a_chunks.len().cmp(&b_chunks.len()).then_with(|| a.cmp(b))
+}
+
+// 单价比较容差：吸收浮点误差（如 0.80 与 0.7999999）
+pub const PRICE_EPSILON: f64 = 1e-6;
+
+pub fn prices_equal(a: f64, b: f64) -> bool {
+    (a - b).abs() < PRICE_EPSILON
+}
+
+// 同一铺面编号出现在多行且单价不一致时返回提示（容差内视为相同）
+pub fn find_price_conflicts(bills: &[MerchantBill]) -> Vec<String> {
+    let mut conflicts = Vec::new();
+    for (i, a) in bills.iter().enumerate() {
+        let key = a.shop_code_key();
+        if key.is_empty() { continue; }
+        // 只与首次出现的同编号行比较，避免重复提示
+        let Some(first) = bills[..i].iter().find(|b| b.shop_code_key() == key) else { continue };
+        if !prices_equal(first.water_unit_price, a.water_unit_price) {
+            conflicts.push(format!("铺面{}的水费单价不一致: {} / {}", a.shop_code, first.water_unit_price, a.water_unit_price));
+        }
+        if !prices_equal(first.electricity_unit_price, a.electricity_unit_price) {
+            conflicts.push(format!("铺面{}的电费单价不一致: {} / {}", a.shop_code, first.electricity_unit_price, a.electricity_unit_price));
+        }
+    }
+    conflicts
+}
+
+// 多电表商户中，电费（按总用量取整）与各电表分别取整之和不一致的，返回 "商户 电费X元，相差Y元"
+pub fn find_rounding_drifts(bills: &[MerchantBill]) -> Vec<String> {
+    bills
+        .iter()
+        .filter(|b| b.electricity_meters.len() > 1 && b.electricity_rounding_drift() != 0.0)
+        .map(|b| format!("{} 电费{}元，相差{}元", b.merchant_name, format_price(b.electricity_amount, 0, 2), format_price(b.electricity_rounding_drift(), 0, 2)))
+        .collect()
+}
+
+// 两批账单某个计算结果的差异；某一侧没有该商家时对应值为None
+#[derive(Debug, Clone, PartialEq)]
+pub struct FieldDiff {
+    pub shop_code: String,
+    pub merchant_name: String,
+    pub field: &'static str,
+    pub before: Option<f64>,
+    pub after: Option<f64>,
+}
+
+// 参与比较的计算结果：字段名与取值
+type DiffField = (&'static str, fn(&MerchantBill) -> f64);
+
+const DIFF_FIELDS: &[DiffField] = &[
+    ("water_usage", |b| b.water_usage),
+    ("water_amount", |b| b.water_amount),
+    ("electricity_usage", |b| b.electricity_usage),
+    ("electricity_amount", |b| b.electricity_amount),
+    ("water_electricity_labor_fee", |b| b.water_electricity_labor_fee),
+    ("garbage_disposal_fee", |b| b.garbage_disposal_fee),
+    ("discount_amount", |b| b.discount_amount),
+    ("total_fee", |b| b.total_fee),
+];
+
+// 比较两批账单（如调整取整规则或模板前后）的计算结果：按铺面编号逐户匹配（无编号时按店铺名称），
+// 返回数值发生变化的字段；只出现在一侧的商家以 total_fee 一项报告
+pub fn diff_documents(a: &[MerchantBill], b: &[MerchantBill]) -> Vec<FieldDiff> {
+    let key = |bill: &MerchantBill| {
+        let code = bill.shop_code_key();
+        if code.is_empty() { bill.merchant_name.trim().to_string() } else { code }
+    };
+    let diff = |bill: &MerchantBill, field: &'static str, before: Option<f64>, after: Option<f64>| FieldDiff {
+        shop_code: bill.shop_code.clone(),
+        merchant_name: bill.merchant_name.clone(),
+        field,
+        before,
+        after,
+    };
+
+    let mut diffs = Vec::new();
+    for before in a {
+        match b.iter().find(|after| key(after) == key(before)) {
+            Some(after) => {
+                for (field, value) in DIFF_FIELDS {
+                    let (x, y) = (value(before), value(after));
+                    if round_cents(x) != round_cents(y) {
+                        diffs.push(diff(before, field, Some(x), Some(y)));
+                    }
+                }
+            }
+            None => diffs.push(diff(before, "total_fee", Some(before.total_fee), None)),
+        }
+    }
+    for after in b.iter().filter(|after| !a.iter().any(|before| key(before) == key(after))) {
+        diffs.push(diff(after, "total_fee", None, Some(after.total_fee)));
+    }
+    diffs
+}
+
+// 大写金额支持的上限（元，不含）：最高到"兆"（万亿）位；超出时分数已超出f64能精确表示的范围
+pub const RMB_UPPER_MAX: f64 = 1e13;
+
+// 超出大写范围时显示的文字，避免输出丢位的错误大写
+pub const RMB_UPPER_OVERFLOW: &str = "金额超出大写范围";
+
+// 将数值金额转换为中文大写人民币（元到分）
+fn rmb_upper(amount: f64) -> String {
+    if round_cents(amount).abs() >= RMB_UPPER_MAX {
+        log_line!("警告：金额 {} 超出大写金额支持的范围（{}元以下），大写处显示「{}」", amount, RMB_UPPER_MAX, RMB_UPPER_OVERFLOW);
+        return RMB_UPPER_OVERFLOW.to_string();
+    }
+    let text = rmb_upper_text(amount);
+    // 调试构建下校验大写能还原为同一金额，及早发现大写转换的错误
+    debug_assert_eq!(rmb_upper_to_amount(&text), Some(round_cents(amount)), "大写金额与小写不一致：{} => {}", amount, text);
+    text
+}
+
+fn rmb_upper_text(amount: f64) -> String {
+    // 四舍五入到分（厘及以下不进入大写），与小写金额的显示取整一致
+    let cents = (round_cents(amount) * 100.0).round() as i64;
+    if cents == 0 {
+        return "零元整".to_string();
+    }
+    if cents < 0 {
+        return format!("负{}", rmb_upper_text(-amount));
+    }
+
+    const DIGITS: [&str; 10] = ["零", "壹", "贰", "叁", "肆", "伍", "陆", "柒", "捌", "玖"];
+    // 整数部分按四位一节，节内为仟佰拾，各节依次为（元）、万、亿、兆
+    const SECTION_UNITS: [&str; 4] = ["", "万", "亿", "兆"];
+    const PLACE_UNITS: [&str; 4] = ["仟", "佰", "拾", ""];
+
+    let (yuan, jiao, fen) = (cents / 100, (cents / 10 % 10) as usize, (cents % 10) as usize);
+    let mut sections = Vec::new();
+    let mut rest = yuan;
+    while rest > 0 {
+        sections.push((rest % 10_000) as usize);
+        rest /= 10_000;
+    }
+
+    let mut s = String::new();
+    // 已输出高位后遇到为0的节或位，需要补一个"零"
+    let mut pending_zero = false;
+    for (idx, &section) in sections.iter().enumerate().rev() {
+        if section == 0 {
+            pending_zero = !s.is_empty();
+            continue;
+        }
+        let places = [section / 1000, section / 100 % 10, section / 10 % 10, section % 10];
+        for (place, &d) in places.iter().enumerate() {
+            if d == 0 {
+                pending_zero = !s.is_empty();
+                continue;
+            }
+            if pending_zero {
+                s.push('零');
+                pending_zero = false;
+            }
+            s.push_str(DIGITS[d]);
+            s.push_str(PLACE_UNITS[place]);
+        }
+        s.push_str(SECTION_UNITS[idx]);
+        // 节末尾的0不补"零"（如"壹仟万壹仟元"），下一节不足千位时在其首位补
+        pending_zero = false;
+    }
+    if yuan > 0 {
+        s.push('元');
+    }
+
+    if jiao == 0 && fen == 0 {
+        s.push('整');
+        return s;
+    }
+    if jiao > 0 {
+        s.push_str(DIGITS[jiao]);
+        s.push('角');
+    } else if yuan > 0 {
+        s.push('零');
+    }
+    if fen > 0 {
+        s.push_str(DIGITS[fen]);
+        s.push('分');
+    }
+    s
+}
+
+// 中文大写金额还原为数值（rmb_upper 的逆运算，仅用于校验），无法识别时返回None
+pub fn rmb_upper_to_amount(text: &str) -> Option<f64> {
+    let text = text.trim();
+    let (negative, body) = match text.strip_prefix('负') {
+        Some(rest) => (true, rest),
+        None => (false, text),
+    };
+    let body = body.strip_suffix('整').unwrap_or(body);
+    if body.is_empty() {
+        return None;
+    }
+
+    // high：兆、亿、万以上已结算部分（元）；section：当前万以内部分（元）；digit：尚未带单位的数字
+    let mut high: i64 = 0;
+    let mut section: i64 = 0;
+    let mut yuan: i64 = 0;
+    let mut cents: i64 = 0;
+    let mut digit: Option<i64> = None;
+    for c in body.chars() {
+        match c {
+            '零' => digit = None,
+            '壹' | '贰' | '叁' | '肆' | '伍' | '陆' | '柒' | '捌' | '玖' => {
+                if digit.is_some() {
+                    return None;
+                }
+                digit = Some("零壹贰叁肆伍陆柒捌玖".chars().position(|d| d == c)? as i64);
+            }
+            '拾' | '佰' | '仟' => {
+                let scale = match c { '拾' => 10, '佰' => 100, _ => 1000 };
+                section += digit.take()? * scale;
+            }
+            '万' | '亿' | '兆' => {
+                let scale = match c { '万' => 10_000, '亿' => 100_000_000, _ => 1_000_000_000_000 };
+                high += (section + digit.take().unwrap_or(0)) * scale;
+                section = 0;
+            }
+            '元' => {
+                yuan = high + section + digit.take().unwrap_or(0);
+                high = 0;
+                section = 0;
+            }
+            '角' => cents += digit.take()? * 10,
+            '分' => cents += digit.take()?,
+            _ => return None,
+        }
+    }
+    // 末尾不允许有未带单位的数字或未以"元"结束的整数部分
+    if digit.is_some() || high != 0 || section != 0 {
+        return None;
+    }
+    let amount = (yuan * 100 + cents) as f64 / 100.0;
+    Some(if negative { -amount } else { amount })
+}
+
+// 汇总表的列布局，docx 与 Markdown 汇总表共用
+struct SummaryLayout<'a> {
+    columns: &'a SummaryColumns,
+    // 其他费用列：各商户其他费用名称的并集，某户没有该项时该格为0
+    extra_fees: Vec<String>,
+    money_format: MoneyFormat,
+    show_discount: bool,
+    show_received: bool,
+}
+
+impl SummaryLayout<'_> {
+    fn header(&self) -> Vec<String> {
+        let mut titles = vec!["店铺名称".to_string()];
+        if !self.columns.combined.is_empty() {
+            titles.push(self.columns.combined_title());
+        }
+        titles.extend(self.columns.separate.iter().map(|c| c.label().to_string()));
+        titles.extend(self.extra_fees.iter().cloned());
+        if self.show_discount {
+            titles.push("优惠".to_string());
+        }
+        titles.push("总价".to_string());
+        if self.show_received {
+            titles.extend(["实收".to_string(), "差额".to_string()]);
+        }
+        titles
+    }
+
+    // 名称列之后的金额列；amount 按类别、extra 按其他费用名称取单户或合计金额
+    fn money_cells(&self, amount: impl Fn(FeeCategory) -> f64, extra: impl Fn(&str) -> f64, discount: f64, total: f64, received: Option<f64>, outstanding: Option<f64>) -> Vec<String> {
+        let money = |v: f64| self.money_format.format(v, 2);
+        let mut cells = Vec::new();
+        if !self.columns.combined.is_empty() {
+            cells.push(money(self.columns.combined.iter().fold(0.0, |sum, c| round_cents(sum + amount(*c)))));
+        }
+        cells.extend(self.columns.separate.iter().map(|c| money(amount(*c))));
+        cells.extend(self.extra_fees.iter().map(|name| money(extra(name))));
+        if self.show_discount {
+            cells.push(money(discount));
+        }
+        cells.push(money(total));
+        if self.show_received {
+            cells.push(received.map(money).unwrap_or_default());
+            cells.push(outstanding.map(money).unwrap_or_default());
+        }
+        cells
+    }
+
+    fn bill_cells(&self, bill: &MerchantBill) -> Vec<String> {
+        let mut cells = vec![bill.merchant_name.clone()];
+        cells.extend(self.money_cells(|c| c.amount(bill), |name| bill.extra_fee(name), bill.discount_amount, bill.total_fee, bill.received_amount, bill.outstanding_amount()));
+        cells
+    }
+
+    fn totals_cells(&self, label: String, totals: &Totals) -> Vec<String> {
+        let mut cells = vec![label];
+        cells.extend(self.money_cells(|c| c.total(totals), |name| totals.extra_fee(name), totals.discount_amount, totals.grand_total, Some(totals.received_amount), Some(totals.outstanding_amount)));
+        cells
+    }
+
+    // "总价"所在列
+    fn total_column(&self) -> usize {
+        self.header().iter().position(|t| t == "总价").unwrap_or(0)
+    }
+}
+
+fn add_summary_table(mut doc: docx_rs::Docx, merchants: &[MerchantBill], group_by: Option<SummaryGroup>, layout: &SummaryLayout) -> Result<docx_rs::Docx, anyhow::Error> {
+    use docx_rs::*;
+
+    // 添加汇总表格标题
+    doc = doc.add_paragraph(
+        Paragraph::new()
+            .add_run(Run::new().add_text("费用汇总表").size(36).bold())
+            .align(AlignmentType::Center)
+    );
+
+    // 空行
+    doc = doc.add_paragraph(Paragraph::new());
+
+    // 创建表格，设置较大的字体，保持原有宽度
+    let mut table = Table::new(vec![
+        TableRow::new(layout.header().into_iter().map(|title| {
+            TableCell::new()
+                .add_paragraph(Paragraph::new().add_run(Run::new().add_text(title).bold().size(24)).align(AlignmentType::Center))
+        }).collect())
+        .row_height(600.0)
+    ]);
+
+    let bill_row = |bill: &MerchantBill| {
+        TableRow::new(layout.bill_cells(bill).into_iter().map(|text| {
+            TableCell::new()
+                .add_paragraph(Paragraph::new().add_run(Run::new().add_text(text).size(20)).align(AlignmentType::Center))
+        }).collect())
+        .row_height(500.0)
+    };
+
+    // 合计行与分组小计行
+    let totals_row = |label: String, totals: &Totals| {
+        TableRow::new(layout.totals_cells(label, totals).into_iter().map(|text| {
+            TableCell::new()
+                .add_paragraph(Paragraph::new().add_run(Run::new().add_text(text).bold().size(24)).align(AlignmentType::Center))
+        }).collect())
+        .row_height(600.0)
+    };
+
+    // 添加数据行
+    match group_by {
+        Some(SummaryGroup::ElectricityPrice) => {
+            for (price, group) in group_by_electricity_price(merchants) {
+                for bill in &group {
+                    table = table.add_row(bill_row(bill));
+                }
+                table = table.add_row(totals_row(electricity_price_subtotal_label(price), &Totals::from_merchants(&group)));
+            }
+        }
+        None => {
+            for bill in merchants {
+                table = table.add_row(bill_row(bill));
+            }
+        }
+    }
+
+    // 添加合计行
+    table = table.add_row(totals_row("合计".to_string(), &Totals::from_merchants(merchants)));
 
-    // 添加合计行
-    let total_water_electricity: f64 = merchants.iter().map(|b| b.water_amount + b.electricity_amount).sum();
-    let total_labor_fee: f64 = merchants.iter().map(|b| b.water_electricity_labor_fee).sum();
-    let total_garbage_fee: f64 = merchants.iter().map(|b| b.garbage_disposal_fee).sum();
-    let grand_total: f64 = merchants.iter().map(|b| b.total_fee).sum();
-
-    table = table.add_row(TableRow::new(vec![
-        TableCell::new()
-            .add_paragraph(Paragraph::new().add_run(Run::new().add_text("合计").bold().size(24)).align(AlignmentType::Center)),
-        TableCell::new()
-            .add_paragraph(Paragraph::new().add_run(Run::new().add_text(format!("{:.2}", total_water_electricity)).bold().size(24)).align(AlignmentType::Center)),
-        TableCell::new()
-            .add_paragraph(Paragraph::new().add_run(Run::new().add_text(format!("{:.2}", total_labor_fee)).bold().size(24)).align(AlignmentType::Center)),
-        TableCell::new()
-            .add_paragraph(Paragraph::new().add_run(Run::new().add_text(format!("{:.2}", total_garbage_fee)).bold().size(24)).align(AlignmentType::Center)),
-        TableCell::new()
-            .add_paragraph(Paragraph::new().add_run(Run::new().add_text(format!("{:.2}", grand_total)).bold().size(24)).align(AlignmentType::Center)),
-    ])
-    .row_height(600.0));
-
     doc = doc.add_table(table);
     Ok(doc)
 }
+
+// 分组小计行标签，如 "小计（电价 0.80 元/度）"
+fn electricity_price_subtotal_label(price: f64) -> String {
+    format!("小计（电价 {} 元/度）", format_price(price, 2, PRICE_MAX_DECIMALS))
+}
+
+// Markdown 单元格中的竖线与换行需转义，否则会破坏表格结构
+fn markdown_cell(text: &str) -> String {
+    text.replace('|', "\\|").replace(['\r', '\n'], " ")
+}
+
+fn markdown_row(cells: &[String]) -> String {
+    let cells: Vec<String> = cells.iter().map(|c| markdown_cell(c)).collect();
+    format!("| {} |\n", cells.join(" | "))
+}
+
+// 生成Markdown格式的通知单（每个商户一张表格，最后附汇总表），便于粘贴到Wiki或邮件
+pub fn render_bills_markdown(merchants: &[MerchantBill], options: Option<GenerateOptions>) -> Result<String> {
+    let date_format = resolve_date_format(options.as_ref().and_then(|o| o.date_format.as_deref()))?;
+    let name_source = options.as_ref().map(|o| o.name_source).unwrap_or_default();
+    let draft = options.as_ref().map(|o| o.draft).unwrap_or(false);
+    let meter_info_layout = options.as_ref().map(|o| o.meter_info_layout).unwrap_or_default();
+    let empty_info_field = options.as_ref().map(|o| o.empty_info_field).unwrap_or_default();
+    let show_effective_rate = options.as_ref().map(|o| o.show_effective_rate).unwrap_or(false);
+    let money_format = options.as_ref().map(|o| o.money_format).unwrap_or_default();
+    let start_sequence = options.as_ref().and_then(|o| o.start_sequence);
+    let summary_group_by = options.as_ref().and_then(|o| o.summary_group_by);
+    let table_options = options.clone().unwrap_or_default();
+
+    check_merchant_limit(merchants.len(), options.as_ref())?;
+    let merchants = &*with_default_fees(merchants, options.as_ref());
+    let mut md = String::new();
+    if let Some(name) = resolve_building_name(merchants, options.as_ref()) {
+        md.push_str(&format!("# {}\n\n", markdown_cell(&name)));
+    }
+    if draft {
+        md.push_str(&format!("> **{}**\n\n", DRAFT_BANNER));
+    }
+
+    for (index, bill) in merchants.iter().enumerate() {
+        let title = options
+            .as_ref()
+            .and_then(|o| o.custom_title.clone())
+            .unwrap_or_else(|| format!("{}抄表计费通知单", bill.month));
+        md.push_str(&format!("## {}\n\n", title));
+        if let Some(start) = start_sequence {
+            md.push_str(&format!("{}\n\n", sequence_number_text(start, index)));
+        }
+
+        let meter_reader = bill.meter_reader.clone().unwrap_or_default();
+        let meter_date = bill.meter_date.clone().unwrap_or_else(|| Local::now().format(&date_format).to_string());
+        let mut info_fields = vec![("编号", bill.shop_code.as_str()), ("姓名", name_source.display_name(bill))];
+        info_fields.extend(bill.tax_id.as_deref().map(|tax_id| ("纳税人识别号", tax_id)));
+        if meter_info_layout.in_info_line() {
+            info_fields.extend([("抄表人", meter_reader.as_str()), ("抄表日期", meter_date.as_str())]);
+        }
+        let period = billing_period_text(bill, options.as_ref());
+        info_fields.extend(period.as_deref().map(|period| ("计费周期", period)));
+        let info_line: Vec<String> = info_fields
+            .into_iter()
+            .filter_map(|(label, value)| empty_info_field.render(value).map(|v| format!("{}：{}", label, markdown_cell(&v))))
+            .collect();
+        md.push_str(&info_line.join("　"));
+        md.push_str("\n\n");
+
+        // 费用表与Word共用 fee_table 的内容；"公共分摊"列、滞纳金/广告费占位行不输出
+        let fee_table = bill.fee_table(&table_options, &meter_reader, &meter_date);
+        let without_shared = |cells: Vec<String>| -> Vec<String> {
+            cells.into_iter().enumerate().filter(|(i, _)| Some(*i) != fee_table.shared_column).map(|(_, c)| c).collect()
+        };
+        let header = without_shared(fee_table.header.clone());
+        md.push_str(&markdown_row(&header));
+        md.push_str(&format!("|{}\n", " --- |".repeat(header.len())));
+        let blank_row = |first: String, last: String| {
+            let mut cells = vec![String::new(); header.len()];
+            cells[0] = first;
+            cells[header.len() - 1] = last;
+            cells
+        };
+        for row in &fee_table.rows {
+            let cells = match row.kind {
+                BillRowKind::Placeholder => continue,
+                BillRowKind::Total => blank_row(
+                    format!("**{}**", row.cells[0].text),
+                    format!("**{}**（{}）", row.cells[1].text, row.cells[2].text),
+                ),
+                BillRowKind::MeterInfo => blank_row(row.cells[0].text.clone(), row.cells[1].text.clone()),
+                _ => without_shared(row.cells.iter().map(|c| c.text.clone()).collect()),
+            };
+            md.push_str(&markdown_row(&cells));
+        }
+        if show_effective_rate && bill.has_variable_pricing() {
+            md.push_str(&format!("\n{}\n", bill.effective_rate_text()));
+        }
+        md.push('\n');
+    }
+
+    // 汇总表
+    let totals = Totals::from_merchants(merchants);
+    let summary_columns = options.as_ref().map(|o| o.summary_columns.clone()).unwrap_or_default();
+    let layout = SummaryLayout {
+        columns: &summary_columns,
+        extra_fees: extra_fee_names(merchants),
+        money_format,
+        show_discount: merchants.iter().any(|b| b.discount_amount != 0.0),
+        show_received: false,
+    };
+    md.push_str("## 费用汇总表\n\n");
+    let header = layout.header();
+    md.push_str(&markdown_row(&header));
+    md.push_str(&format!("|{}\n", " --- |".repeat(header.len())));
+    let bill_row = |bill: &MerchantBill| markdown_row(&layout.bill_cells(bill));
+    let total_column = layout.total_column();
+    let totals_row = |label: String, totals: &Totals| {
+        let mut total_cells = layout.totals_cells(format!("**{}**", label), totals);
+        total_cells[total_column] = format!("**{}**", total_cells[total_column]);
+        markdown_row(&total_cells)
+    };
+    match summary_group_by {
+        Some(SummaryGroup::ElectricityPrice) => {
+            for (price, group) in group_by_electricity_price(merchants) {
+                for bill in &group {
+                    md.push_str(&bill_row(bill));
+                }
+                md.push_str(&totals_row(electricity_price_subtotal_label(price), &Totals::from_merchants(&group)));
+            }
+        }
+        None => {
+            for bill in merchants {
+                md.push_str(&bill_row(bill));
+            }
+        }
+    }
+    md.push_str(&totals_row("合计".to_string(), &totals));
+
+    Ok(md)
+}
+
+// 为每个商家单独生成一份通知单文档（文件名, DOCX内容），便于逐户转换或发送
+pub fn generate_per_merchant_documents(merchants: &[MerchantBill], options: Option<GenerateOptions>) -> Result<Vec<(String, Vec<u8>)>> {
+    check_merchant_limit(merchants.len(), options.as_ref())?;
+    merchants
+        .iter()
+        .enumerate()
+        .map(|(idx, bill)| {
+            // 逐户生成时单据编号按商家顺序递增
+            let mut options = options.clone();
+            if let Some(o) = options.as_mut() {
+                o.start_sequence = o.start_sequence.map(|start| start + idx as u32);
+            }
+            let bytes = generate_word_document_with_template(std::slice::from_ref(bill), options)?;
+            let stem = if bill.shop_code.trim().is_empty() { &bill.merchant_name } else { &bill.shop_code };
+            Ok((format!("{:03}_{}.docx", idx + 1, normalize_width(stem)), bytes))
+        })
+        .collect()
+}
+
+// 将多个PDF按顺序合并为一个（逐户转换后合并打印）
+#[cfg(feature = "pdf")]
+pub fn merge_pdfs(pdfs: &[Vec<u8>]) -> Result<Vec<u8>> {
+    use lopdf::{Document, Object, ObjectId};
+
+    if pdfs.is_empty() {
+        anyhow::bail!("没有可合并的PDF");
+    }
+
+    let mut max_id = 1;
+    let mut pages: Vec<(ObjectId, lopdf::Dictionary)> = Vec::new();
+    let mut objects = std::collections::BTreeMap::new();
+    for (idx, bytes) in pdfs.iter().enumerate() {
+        let mut doc = Document::load_mem(bytes).with_context(|| format!("无法解析第{}个PDF", idx + 1))?;
+        doc.renumber_objects_with(max_id);
+        max_id = doc.max_id + 1;
+        // get_pages 按页码排序，保证合并后页序与原文档一致
+        for (_, page_id) in doc.get_pages() {
+            let mut page = doc.get_dictionary(page_id)?.clone();
+            inherit_page_attributes(&doc, &mut page);
+            pages.push((page_id, page));
+        }
+        objects.extend(doc.objects);
+    }
+
+    // 保留第一个 Catalog/Pages 对象，其余页面统一挂到该 Pages 下
+    let mut merged = Document::with_version("1.5");
+    let mut catalog: Option<(ObjectId, Object)> = None;
+    let mut pages_root: Option<ObjectId> = None;
+    for (id, object) in objects {
+        match object.type_name().unwrap_or("") {
+            "Catalog" => { catalog.get_or_insert((id, object)); }
+            "Pages" => { pages_root.get_or_insert(id); }
+            "Page" | "Outlines" | "Outline" => {}
+            _ => { merged.objects.insert(id, object); }
+        }
+    }
+    let (catalog_id, catalog) = catalog.context("PDF中缺少Catalog对象")?;
+    let pages_root = pages_root.context("PDF中缺少Pages对象")?;
+
+    let mut kids = Vec::with_capacity(pages.len());
+    for (id, mut page) in pages {
+        page.set("Parent", pages_root);
+        merged.objects.insert(id, Object::Dictionary(page));
+        kids.push(Object::Reference(id));
+    }
+    let mut pages_dict = lopdf::Dictionary::new();
+    pages_dict.set("Type", "Pages");
+    pages_dict.set("Count", kids.len() as i64);
+    pages_dict.set("Kids", kids);
+    merged.objects.insert(pages_root, Object::Dictionary(pages_dict));
+
+    let mut catalog = catalog.as_dict()?.clone();
+    catalog.set("Pages", pages_root);
+    catalog.remove(b"Outlines");
+    merged.objects.insert(catalog_id, Object::Dictionary(catalog));
+    merged.trailer.set("Root", catalog_id);
+    merged.max_id = merged.objects.keys().map(|(id, _)| *id).max().unwrap_or(0);
+    merged.renumber_objects();
+    merged.compress();
+
+    let mut out = Vec::new();
+    merged.save_to(&mut out)?;
+    Ok(out)
+}
+
+// 页面可从上级 Pages 节点继承的属性；合并时上级节点会被替换，需先复制到页面本身
+#[cfg(feature = "pdf")]
+fn inherit_page_attributes(doc: &lopdf::Document, page: &mut lopdf::Dictionary) {
+    let mut parent = page.get(b"Parent").and_then(|p| p.as_reference()).ok();
+    while let Some(dict) = parent.and_then(|id| doc.get_dictionary(id).ok()) {
+        for key in [&b"Resources"[..], b"MediaBox", b"CropBox", b"Rotate"] {
+            if !page.has(key) {
+                if let Ok(value) = dict.get(key) {
+                    page.set(key, value.clone());
+                }
+            }
+        }
+        parent = dict.get(b"Parent").and_then(|p| p.as_reference()).ok();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers() -> HeadersMap<'static> {
+        HeadersMap {
+            merchant: "店铺名称",
+            prev_e: "",
+            curr_e: "",
+            prev_w: "",
+            curr_w: "",
+            w_price: "",
+            e_price: "",
+            electricity_price: "",
+            electricity_prefix: "电表",
+            water_electricity_labor_fee: "水电人工费",
+            garbage_disposal_fee: "垃圾处理费",
+        }
+    }
+
+    const CSV_HEADER: &str = "铺面编号,店铺名称,电表1上期读数,电表1本期读数,上期水表读数,本期水表读数,水费单价,电费单价\n";
+
+    fn read_csv(body: &str) -> Result<Vec<MerchantBill>> {
+        read_csv_bytes(format!("{}{}", CSV_HEADER, body).as_bytes(), &headers(), &ReadOptions::default())
+    }
+
+    // 水 10→12（单价3.5），电 100→150（单价1）
+    fn sample_bill() -> MerchantBill {
+        MerchantBill::builder("商户甲".to_string(), 3.5, 1.0)
+            .shop_code("A01".to_string())
+            .water_readings(10.0, 12.0)
+            .electricity_meter("1".to_string(), 100.0, 150.0)
+            .build()
+    }
+
+    // 生成的 Word 文档中的文字（用于检查渲染结果）
+    fn docx_text(bills: &[MerchantBill], options: GenerateOptions) -> String {
+        let bytes = generate_word_document_with_template(bills, Some(options)).unwrap();
+        docx_rs::read_docx(&bytes).unwrap().json()
+    }
+
+    #[test]
+    fn rmb_upper_round_trips_through_rmb_upper_to_amount() {
+        let amounts = [
+            0.0, 0.01, 0.1, 0.5, 1.0, 10.0, 10.05, 100.2, 101.0, 1001.0, 1010.1, 10_000.0, 10_001.0, 100_010.0,
+            1_000_100.5, 20_000_000.0, 100_000_000.0, 100_000_001.01, 123_456_789.99, 1_000_000_000_001.0, -35.6,
+        ];
+        for amount in amounts {
+            let text = rmb_upper(amount);
+            assert_eq!(rmb_upper_to_amount(&text), Some(amount), "{} => {}", amount, text);
+        }
+    }
+
+    #[test]
+    fn rmb_upper_handles_amounts_up_to_zhao() {
+        assert_eq!(rmb_upper(123_456_789_012.34), "壹仟贰佰叁拾肆亿伍仟陆佰柒拾捌万玖仟零壹拾贰元叁角肆分");
+        assert_eq!(rmb_upper(1_000_000_000_000.0), "壹兆元整");
+        assert_eq!(rmb_upper(RMB_UPPER_MAX), RMB_UPPER_OVERFLOW);
+    }
+
+    #[test]
+    fn csv_quoted_field_keeps_embedded_comma() {
+        let bills = read_csv("A01,\"Store A, Ltd\",\"100\",\"1,150\",10,12,3.5,1\n").unwrap();
+        assert_eq!(bills.len(), 1);
+        assert_eq!(bills[0].merchant_name, "Store A, Ltd");
+        assert_eq!(bills[0].electricity_meters[0].curr_reading, 1150.0);
+        assert_eq!(bills[0].electricity_usage, 1050.0);
+        assert_eq!(bills[0].water_usage, 2.0);
+    }
+
+    #[test]
+    fn csv_quote_inside_unquoted_field_is_literal() {
+        let bills = read_csv("A01,A\"B,100,150,10,12,3.5,1\n").unwrap();
+        assert_eq!(bills[0].merchant_name, "A\"B");
+    }
+
+    #[test]
+    fn csv_unterminated_quoted_field_is_an_error() {
+        let err = read_csv("A01,商户甲,100,150,10,12,3.5,1\nA02,\"商户乙,100,150,10,12,3.5,1\n").unwrap_err();
+        assert!(err.to_string().contains("第3行开始的引号未闭合"), "{}", err);
+    }
+
+    #[test]
+    fn estimate_missing_readings_uses_history_average() {
+        let mut bill = MerchantBill::new("商户甲".to_string(), 3.5, 1.0);
+        bill.set_shop_code("A01".to_string());
+        bill.add_electricity_meter("1".to_string(), 1000.0, 0.0);
+        let history = HashMap::from([("A01".to_string(), vec![vec![100.0, 120.0, 140.0]])]);
+        let mut bills = vec![bill];
+        assert_eq!(estimate_missing_readings(&mut bills, &history), 1);
+        let meter = &bills[0].electricity_meters[0];
+        assert!(meter.estimated);
+        assert_eq!(meter.curr_reading, 1120.0);
+        assert_eq!(meter.usage, 120.0);
+        assert_eq!(bills[0].electricity_amount, 120.0);
+    }
+
+    #[test]
+    fn estimate_missing_readings_wraps_at_rollover_digits() {
+        let mut bill = MerchantBill::new("商户甲".to_string(), 3.5, 1.0);
+        bill.set_shop_code("A01".to_string());
+        bill.add_rollover_electricity_meter("1".to_string(), 99_950.0, 0.0, 5);
+        let history = HashMap::from([("A01".to_string(), vec![vec![80.0, 100.0, 120.0]])]);
+        let mut bills = vec![bill];
+        estimate_missing_readings(&mut bills, &history);
+        let meter = &bills[0].electricity_meters[0];
+        assert_eq!(meter.curr_reading, 50.0);
+        assert_eq!(meter.usage, 100.0);
+        assert_eq!(meter.reading_usage(), 100.0);
+    }
+
+    #[test]
+    fn meter_usage_handles_rollover() {
+        // 5位表 99980 → 00030
+        assert_eq!(meter_usage(99_980.0, 30.0, Some(5)), 50.0);
+        // 6位表 999900 → 000100
+        assert_eq!(meter_usage(999_900.0, 100.0, Some(6)), 200.0);
+        // 未翻表时与位数无关
+        assert_eq!(meter_usage(100.0, 150.0, Some(5)), 50.0);
+        assert_eq!(meter_usage(100.0, 150.0, None), 50.0);
+        // 未设置位数时本期小于上期仍按0计算
+        assert_eq!(meter_usage(99_980.0, 30.0, None), 0.0);
+
+        let mut bill = MerchantBill::new("商户甲".to_string(), 0.0, 1.0);
+        bill.add_rollover_electricity_meter("1".to_string(), 99_980.0, 30.0, 5);
+        assert_eq!(bill.electricity_usage, 50.0);
+        assert_eq!(bill.electricity_amount, 50.0);
+    }
+
+    #[test]
+    fn tiered_price_walks_tiers_over_aggregate_usage() {
+        let tiers = TieredPrice::new(vec![200.0, 400.0], vec![0.5, 0.6, 0.8]).unwrap();
+        assert_eq!(tiers.amount(0.0), 0.0);
+        assert_eq!(tiers.amount(200.0), 100.0);
+        assert_eq!(round_cents(tiers.amount(400.0)), 220.0);
+        assert_eq!(round_cents(tiers.amount(450.0)), 260.0);
+
+        // 两块表合计恰好200度，全部落在第一档
+        let mut bill = MerchantBill::new("商户甲".to_string(), 0.0, 9.9);
+        bill.set_electricity_tiers(Some(tiers.clone()));
+        bill.add_electricity_meter("1".to_string(), 0.0, 120.0);
+        bill.add_electricity_meter("2".to_string(), 0.0, 80.0);
+        assert_eq!(bill.electricity_usage, 200.0);
+        assert_eq!(bill.electricity_amount, 100.0);
+
+        let mut idle = MerchantBill::new("商户乙".to_string(), 0.0, 9.9);
+        idle.set_electricity_tiers(Some(tiers));
+        idle.add_electricity_meter("1".to_string(), 300.0, 300.0);
+        assert_eq!(idle.electricity_usage, 0.0);
+        assert_eq!(idle.electricity_amount, 0.0);
+        assert_eq!(idle.total_fee, 0.0);
+    }
+
+    #[test]
+    fn parse_amount_cases() {
+        let ok = [
+            ("123", 123.0),
+            ("  12.5 ", 12.5),
+            ("１２３．５", 123.5),
+            ("－８", -8.0),
+            ("￥1,234.50", 1234.5),
+            ("¥5", 5.0),
+            ("$7.25", 7.25),
+            ("-￥5", -5.0),
+            ("+3", 3.0),
+            ("RMB 100", 100.0),
+            ("12元", 12.0),
+            ("0.8元/度", 0.8),
+            ("1234度", 1234.0),
+            ("56吨", 56.0),
+            ("7.5立方米", 7.5),
+            ("88kwh", 88.0),
+            ("1,234,567", 1_234_567.0),
+            ("1.5e3", 1500.0),
+            ("2E-2", 0.02),
+            ("10%", 0.1),
+            ("１０％", 0.1),
+            (".5", 0.5),
+        ];
+        for (raw, expected) in ok {
+            assert_eq!(parse_amount(raw), Ok(expected), "{:?}", raw);
+        }
+
+        assert_eq!(parse_amount(""), Err(ParseNumError::Empty));
+        assert_eq!(parse_amount("　 "), Err(ParseNumError::Empty));
+        for raw in ["abc", "1,5", "12,34", ",123", "1,234,5", "1.2.3", "inf", "NaN", "-", "￥", "12abc", "1e400", "--5"] {
+            assert_eq!(parse_amount(raw), Err(ParseNumError::Invalid(raw.trim().to_string())), "{:?}", raw);
+        }
+
+        // 空值按0且不记错；格式错误同样按0但会记录下来
+        let mut errors = Vec::new();
+        assert_eq!(amount_or_zero(parse_amount(""), &mut errors, 2, "电表1本期读数"), 0.0);
+        assert!(errors.is_empty());
+        assert_eq!(amount_or_zero(parse_amount("12,34"), &mut errors, 3, "电表1本期读数"), 0.0);
+        assert_eq!(errors, vec!["第3行「电表1本期读数」无法解析数值: 12,34".to_string()]);
+    }
+
+    #[test]
+    fn natural_cmp_orders_shop_codes() {
+        let mut codes = vec!["A-1", "A-10", "A-2", "B-1"];
+        codes.sort_by(|a, b| natural_cmp(a, b));
+        assert_eq!(codes, ["A-1", "A-2", "A-10", "B-1"]);
+
+        let cases = [
+            ("A-2", "A-10", Ordering::Less),
+            // 分隔符视为同一种，字母不区分大小写
+            ("A_2", "A-10", Ordering::Less),
+            ("a/3", "A.2", Ordering::Greater),
+            ("A02", "A10", Ordering::Less),
+            ("A-9", "A-010", Ordering::Less),
+            ("Ａ－１０", "A-2", Ordering::Greater),
+            ("2", "10", Ordering::Less),
+            ("10", "A", Ordering::Less),
+            ("A-1", "A-1-1", Ordering::Less),
+            ("12345678901234567890", "99", Ordering::Greater),
+        ];
+        for (a, b, expected) in cases {
+            assert_eq!(natural_cmp(a, b), expected, "{} vs {}", a, b);
+        }
+        // 数值相同的前导0编号按原字符串区分，保证排序稳定
+        assert_ne!(natural_cmp("A-01", "A-1"), Ordering::Equal);
+        assert_eq!(natural_cmp("A-1", "A-1"), Ordering::Equal);
+    }
+
+    #[test]
+    fn rounding_drift_is_detected_for_three_meters() {
+        // 各表 0.4 度分别取整为0元，合计 1.2 度取整为1元
+        let mut bill = MerchantBill::new("商户甲".to_string(), 0.0, 1.0);
+        for id in ["1", "2", "3"] {
+            bill.add_electricity_meter(id.to_string(), 100.0, 100.4);
+        }
+        assert_eq!(bill.electricity_meters.iter().map(|m| m.amount).sum::<f64>(), 0.0);
+        assert_eq!(bill.electricity_amount, 1.0);
+        assert_eq!(bill.electricity_rounding_drift(), 1.0);
+        assert_eq!(find_rounding_drifts(&[bill]), vec!["商户甲 电费1元，相差1元".to_string()]);
+
+        let mut even = MerchantBill::new("商户乙".to_string(), 0.0, 1.0);
+        even.add_electricity_meter("1".to_string(), 0.0, 10.0);
+        even.add_electricity_meter("2".to_string(), 0.0, 20.0);
+        assert!(find_rounding_drifts(&[even]).is_empty());
+    }
+
+    #[test]
+    fn read_data_bytes_reports_rounding_drift() {
+        let csv = CSV_HEADER.replace("电表1本期读数,", "电表1本期读数,电表2上期读数,电表2本期读数,电表3上期读数,电表3本期读数,")
+            + "A01,商户甲,100,100.4,100,100.4,100,100.4,0,0,10,1\n";
+        let (bills, logs) = captured_logs(|| read_data_bytes(csv.as_bytes(), "csv", &headers(), &ReadOptions::default()).unwrap());
+        assert_eq!(bills[0].electricity_rounding_drift(), 1.0);
+        assert!(logs.iter().any(|l| l.contains("提示：以下商户电费按总用量取整") && l.contains("商户甲")), "{:?}", logs);
+    }
+
+    // 执行 f 并收集期间库输出的警告与提示
+    fn captured_logs<T>(f: impl FnOnce() -> T) -> (T, Vec<String>) {
+        let lines = std::rc::Rc::new(std::cell::RefCell::new(Vec::new()));
+        let sink = lines.clone();
+        let result = with_log_sink(move |line| sink.borrow_mut().push(line.to_string()), f);
+        let lines = lines.borrow().clone();
+        (result, lines)
+    }
+
+    #[test]
+    fn csv_short_rows_are_reported() {
+        let (bills, logs) = captured_logs(|| read_csv("A01,商户甲,100,150,10,12,3.5,1\nA02,商户乙,100\nA03,商户丙,100,150,10\n").unwrap());
+        assert_eq!(bills.len(), 2);
+        assert!(logs.iter().any(|l| l == "警告：第3行只有3列，不足以包含铺面编号、店铺名称、读数等基础列，已跳过该行"), "{:?}", logs);
+        // 含基础列但缺少后面的读数、单价列的行保留，并与空白读数区分提示
+        assert!(logs.iter().any(|l| l.contains("第4行只有5列") && l.contains("本期水表读数")), "{:?}", logs);
+    }
+
+    #[test]
+    fn custom_table_headers_replace_default_labels() {
+        let options = GenerateOptions {
+            table_headers: TableHeaders { prev_reading: "起度".to_string(), curr_reading: "止度".to_string(), ..TableHeaders::default() },
+            ..GenerateOptions::default()
+        };
+        let table = sample_bill().fee_table(&options, "", "");
+        assert_eq!(table.header, ["项目", "起度", "止度", "实用度数", "公共分摊", "单价（元）", "金额"]);
+
+        let text = docx_text(&[sample_bill()], options);
+        assert!(text.contains("起度") && text.contains("止度"), "{}", text);
+        assert!(!text.contains("上月表底") && !text.contains("本月抄表数"), "{}", text);
+    }
+
+    #[test]
+    fn zero_usage_meter_renders_zero_amounts_without_nan() {
+        assert_eq!(safe_rate(50.0, 0.0), 0.0);
+        assert_eq!(safe_rate(f64::NAN, 10.0), 0.0);
+        assert_eq!(safe_rate(50.0, f64::INFINITY), 0.0);
+
+        let mut bill = MerchantBill::builder("空置铺".to_string(), 3.5, f64::NAN)
+            .water_readings(10.0, 10.0)
+            .electricity_meter("1".to_string(), 100.0, 100.0)
+            .build();
+        bill.garbage_disposal_fee = f64::INFINITY;
+        bill.update_totals();
+        assert_eq!(bill.effective_electricity_rate(), 0.0);
+        let options = GenerateOptions { show_effective_rate: true, ..GenerateOptions::default() };
+        let table = bill.fee_table(&options, "", "");
+        for kind in [BillRowKind::Meter, BillRowKind::Water] {
+            let row = table.rows.iter().find(|r| r.kind == kind).unwrap();
+            assert_eq!(row.cells[6].text, "0", "{:?}", row);
+        }
+        assert_eq!(table.total_text(), Some("0.00"));
+
+        let text = docx_text(&[bill], options);
+        assert!(!text.contains("NaN") && !text.contains("inf"), "{}", text);
+    }
+
+    #[test]
+    fn date_format_option_changes_rendered_date() {
+        assert_eq!(resolve_date_format(None).unwrap(), "%Y年%m月%d日");
+        assert_eq!(resolve_date_format(Some("iso")).unwrap(), "%Y-%m-%d");
+        assert!(resolve_date_format(Some("%Y-%Q")).is_err());
+
+        let today = Local::now();
+        let options = GenerateOptions { date_format: Some("%Y/%m/%d".to_string()), ..GenerateOptions::default() };
+        let text = docx_text(&[sample_bill()], options);
+        assert!(text.contains(&today.format("%Y/%m/%d").to_string()), "{}", text);
+        assert!(!text.contains(&today.format("%Y年%m月%d日").to_string()), "{}", text);
+
+        assert_eq!(month_format("%Y年%m月%d日"), "%Y年%m月");
+        assert_eq!(month_format("%Y-%m-%d"), "%Y-%m");
+        assert_eq!(month_format("%d/%m/%Y"), "%m/%Y");
+
+        // 模板配置的生成时间同样使用配置的日期格式
+        let mut config = template_simple::TemplateConfig::minimal();
+        config.date_format = Some("slash".to_string());
+        config.merchant_template.sections.push(serde_json::from_value(serde_json::json!({
+            "name": "timestamp", "type": "timestamp", "content": "生成时间：{datetime}"
+        })).unwrap());
+        let bytes = template_simple::DocumentGenerator::new(config).generate_complete_document(&[sample_bill()]).unwrap();
+        let text = docx_rs::read_docx(&bytes).unwrap().json();
+        assert!(text.contains(&format!("生成时间：{} ", today.format("%Y/%m/%d"))), "{}", text);
+    }
+
+    #[test]
+    fn prices_within_epsilon_are_the_same_price() {
+        let priced = |code: &str, price: f64| MerchantBill::builder(code.to_string(), 3.5, price).shop_code(code.to_string()).build();
+        let bills = [priced("A01", 0.80), priced("A01", 0.7999999), priced("B01", 0.8000001)];
+        assert!(prices_equal(0.80, 0.7999999));
+        assert!(find_price_conflicts(&bills).is_empty());
+        let groups = group_by_electricity_price(&bills);
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0].1.len(), 3);
+
+        let conflicts = find_price_conflicts(&[priced("A01", 0.80), priced("A01", 0.81)]);
+        assert_eq!(conflicts, ["铺面A01的电费单价不一致: 0.8 / 0.81"]);
+    }
+
+    #[test]
+    fn contact_name_fills_the_info_line_name() {
+        let csv = CSV_HEADER.replace("店铺名称,", "店铺名称,户主姓名,") + "A01,商户甲,王大妈,100,150,10,12,3.5,1\n";
+        let bills = read_csv_bytes(csv.as_bytes(), &headers(), &ReadOptions::default()).unwrap();
+        assert_eq!(bills[0].merchant_name, "商户甲");
+        assert_eq!(bills[0].contact_name.as_deref(), Some("王大妈"));
+
+        let markdown = render_bills_markdown(&bills, None).unwrap();
+        assert!(markdown.contains("姓名：王大妈"), "{}", markdown);
+        let text = docx_text(&bills, GenerateOptions::default());
+        // 信息行显示户主姓名，汇总表仍按店铺名称
+        assert!(text.contains("王大妈") && text.contains("商户甲"), "{}", text);
+
+        let options = GenerateOptions { name_source: NameSource::ShopName, ..GenerateOptions::default() };
+        let markdown = render_bills_markdown(&bills, Some(options)).unwrap();
+        assert!(markdown.contains("姓名：商户甲"), "{}", markdown);
+    }
+
+    #[test]
+    fn draft_banner_only_appears_for_drafts() {
+        let draft = docx_text(&[sample_bill()], GenerateOptions { draft: true, ..GenerateOptions::default() });
+        assert!(draft.contains(DRAFT_BANNER), "{}", draft);
+        let final_copy = docx_text(&[sample_bill()], GenerateOptions::default());
+        assert!(!final_copy.contains(DRAFT_BANNER), "{}", final_copy);
+    }
+
+    #[test]
+    fn billing_month_column_sets_each_merchants_period() {
+        assert_eq!(normalize_billing_month("2025/7").as_deref(), Some("2025年07月"));
+        assert_eq!(normalize_billing_month("202507").as_deref(), Some("2025年07月"));
+        assert_eq!(normalize_billing_month("2025-13"), None);
+
+        let csv = CSV_HEADER.replace("店铺名称,", "店铺名称,账单月份,")
+            + "A01,商户甲,2025-06,100,150,10,12,3.5,1\n"
+            + "A02,商户乙,2025年7月,200,260,20,25,3.5,1\n";
+        let bills = read_csv_bytes(csv.as_bytes(), &headers(), &ReadOptions::default()).unwrap();
+        assert_eq!(bills[0].month, "2025年06月");
+        assert_eq!(bills[1].month, "2025年07月");
+        let text = docx_text(&bills, GenerateOptions::default());
+        assert!(text.contains("2025年06月抄表计费通知单") && text.contains("2025年07月抄表计费通知单"), "{}", text);
+
+        // 无该列时沿用当月
+        let current = read_csv("A01,商户甲,100,150,10,12,3.5,1\n").unwrap();
+        assert_eq!(current[0].month, Local::now().format("%Y年%m月").to_string());
+    }
+
+    #[test]
+    fn ceil_water_usage_bills_whole_tons() {
+        let mut bill = MerchantBill::new("商户甲".to_string(), 3.0, 1.0);
+        bill.set_water_readings(100.0, 110.2);
+        assert!((bill.water_usage - 10.2).abs() < 1e-9);
+        bill.set_water_usage_rounding(UsageRounding::CeilToUnit);
+        assert_eq!(bill.water_usage, 11.0);
+        assert_eq!(bill.water_amount, 33.0);
+        bill.set_water_usage_rounding(UsageRounding::RoundToUnit);
+        assert_eq!(bill.water_usage, 10.0);
+    }
+
+    #[test]
+    fn capitalized_amounts_show_both_forms_on_fee_rows() {
+        let water_amount = |options: &GenerateOptions| {
+            let table = sample_bill().fee_table(options, "", "");
+            table.rows.iter().find(|r| r.kind == BillRowKind::Water).unwrap().cells[6].text.clone()
+        };
+        assert_eq!(water_amount(&GenerateOptions::default()), "7");
+        let options = GenerateOptions { capitalize_all_amounts: true, ..GenerateOptions::default() };
+        assert_eq!(water_amount(&options), "7（柒元整）");
+        let text = docx_text(&[sample_bill()], options);
+        assert!(text.contains("7（柒元整）") && text.contains("50（伍拾元整）"), "{}", text);
+    }
+
+    #[test]
+    fn partial_payment_shows_outstanding_difference() {
+        let csv = CSV_HEADER.trim_end().to_string() + ",实收金额\n"
+            + "A01,商户甲,100,150,10,12,3.5,1,50\n"
+            + "A02,商户乙,200,210,20,22,3.5,1,\n";
+        let bills = read_csv_bytes(csv.as_bytes(), &headers(), &ReadOptions::default()).unwrap();
+        assert_eq!(bills[0].total_fee, 57.0);
+        assert_eq!(bills[0].outstanding_amount(), Some(7.0));
+        assert_eq!(bills[1].outstanding_amount(), None);
+
+        let summary = summary_json(&bills);
+        assert_eq!(summary["received_amount"], 50.0);
+        assert_eq!(summary["outstanding_amount"], 7.0);
+        let text = docx_text(&bills, GenerateOptions::default());
+        assert!(text.contains("实收") && text.contains("差额"), "{}", text);
+    }
+
+    #[test]
+    fn list_all_meters_keeps_zero_reading_meters() {
+        let csv = CSV_HEADER.replace("电表1本期读数,", "电表1本期读数,电表2上期读数,电表2本期读数,") + "A01,商户甲,100,150,0,0,10,12,3.5,1\n";
+        let read = |list_all_meters: bool| {
+            let options = ReadOptions { list_all_meters, ..ReadOptions::default() };
+            read_csv_bytes(csv.as_bytes(), &headers(), &options).unwrap().remove(0)
+        };
+        assert_eq!(read(false).electricity_meters.len(), 1);
+
+        let bill = read(true);
+        assert_eq!(bill.electricity_meters.len(), 2);
+        assert_eq!(bill.electricity_usage, 50.0);
+        let table = bill.fee_table(&GenerateOptions::default(), "", "");
+        let meter_rows: Vec<&BillRow> = table.rows.iter().filter(|r| r.kind == BillRowKind::Meter).collect();
+        assert_eq!(meter_rows.len(), 2);
+        assert_eq!(meter_rows[1].cells[1..4].iter().map(|c| c.text.as_str()).collect::<Vec<_>>(), ["0", "0", "0"]);
+    }
+
+    #[test]
+    fn summary_paths_share_the_same_grand_total() {
+        let mut second = sample_bill();
+        second.shop_code = "A02".to_string();
+        second.water_electricity_labor_fee = 12.5;
+        second.garbage_disposal_fee = 8.0;
+        second.update_totals();
+        let bills = [sample_bill(), second];
+        let totals = Totals::from_merchants(&bills);
+        assert_eq!(totals.grand_total, 134.5);
+
+        let mut template = BillTemplate::new("10".to_string(), "2026".to_string());
+        bills.iter().cloned().for_each(|b| template.add_merchant(b));
+        assert_eq!(template.grand_total, totals.grand_total);
+        assert_eq!(summary_json(&bills)["grand_total"], totals.grand_total);
+        let markdown = render_bills_markdown(&bills, None).unwrap();
+        assert!(markdown.contains("134.50"), "{}", markdown);
+        let options = GenerateOptions { include_footer_total: true, ..GenerateOptions::default() };
+        let text = docx_text(&bills, options);
+        assert!(text.contains("本批合计：¥134.50"), "{}", text);
+    }
+
+    #[test]
+    fn water_price_is_shown_at_stored_precision() {
+        assert_eq!(format_price(2.8567, 3, PRICE_MAX_DECIMALS), "2.8567");
+        assert_eq!(format_price(3.5, 3, PRICE_MAX_DECIMALS), "3.500");
+        assert_eq!(format_price(0.8, 2, PRICE_MAX_DECIMALS), "0.80");
+        assert_eq!(format_price(2.8567, 3, 3), "2.857");
+
+        let bill = MerchantBill::builder("商户甲".to_string(), 2.8567, 1.0).water_readings(0.0, 10.0).build();
+        let table = bill.fee_table(&GenerateOptions::default(), "", "");
+        let water = table.rows.iter().find(|r| r.kind == BillRowKind::Water).unwrap();
+        assert_eq!(water.cells[5].text, "2.8567");
+        assert_eq!(water.cells[6].text, "29");
+    }
+
+    #[test]
+    fn markdown_lists_each_merchants_total() {
+        let mut second = sample_bill();
+        second.merchant_name = "商户|乙".to_string();
+        second.garbage_disposal_fee = 8.0;
+        second.update_totals();
+        let markdown = render_bills_markdown(&[sample_bill(), second], None).unwrap();
+        assert!(markdown.contains("| **合计** |  |  |  |  | **57.00**（伍拾柒元整） |"), "{}", markdown);
+        assert!(markdown.contains("| **合计** |  |  |  |  | **65.00**（陆拾伍元整） |"), "{}", markdown);
+        assert!(markdown.contains("| 商户甲 | 57.00 | 0.00 | 0.00 | 57.00 |"), "{}", markdown);
+        // 单元格中的竖线需转义，避免拆出多余的列
+        assert!(markdown.contains("| 商户\\|乙 | 57.00 | 0.00 | 8.00 | 65.00 |"), "{}", markdown);
+        assert!(markdown.contains("| **合计** | 114.00 | 0.00 | 8.00 | **122.00** |"), "{}", markdown);
+    }
+
+    #[test]
+    fn meter_merge_modes_for_two_meters() {
+        let bill = MerchantBill::builder("商户甲".to_string(), 3.5, 1.0)
+            .electricity_meter("1".to_string(), 100.0, 150.0)
+            .electricity_meter("2".to_string(), 0.0, 30.0)
+            .build();
+        let price_and_amount = |mode: MeterMergeMode| {
+            let options = GenerateOptions { meter_merge_mode: mode, ..GenerateOptions::default() };
+            let table = bill.fee_table(&options, "", "");
+            table
+                .rows
+                .iter()
+                .filter(|r| r.kind == BillRowKind::Meter)
+                .map(|r| ((r.cells[5].merge, r.cells[5].text.clone()), (r.cells[6].merge, r.cells[6].text.clone())))
+                .collect::<Vec<_>>()
+        };
+        let cell = |merge, text: &str| (merge, text.to_string());
+        assert_eq!(
+            price_and_amount(MeterMergeMode::MergePriceAndAmount),
+            [
+                (cell(CellMerge::Start, "1.00"), cell(CellMerge::Start, "80")),
+                (cell(CellMerge::Continue, ""), cell(CellMerge::Continue, "")),
+            ]
+        );
+        assert_eq!(
+            price_and_amount(MeterMergeMode::MergeAmountOnly),
+            [
+                (cell(CellMerge::None, "1.00"), cell(CellMerge::Start, "80")),
+                (cell(CellMerge::None, "1.00"), cell(CellMerge::Continue, "")),
+            ]
+        );
+        assert_eq!(
+            price_and_amount(MeterMergeMode::NoMerge),
+            [
+                (cell(CellMerge::None, "1.00"), cell(CellMerge::None, "80")),
+                (cell(CellMerge::None, "1.00"), cell(CellMerge::None, "80")),
+            ]
+        );
+        assert_eq!(GenerateOptions::default().meter_merge_mode, MeterMergeMode::MergePriceAndAmount);
+    }
+
+    // 本地模拟 HTTP 服务：只响应一次请求，返回服务地址
+    #[cfg(feature = "url")]
+    fn serve_once(content_type: &'static str, body: String) -> String {
+        use std::io::{Read, Write};
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = [0u8; 1024];
+            let _ = stream.read(&mut request);
+            let response = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                content_type,
+                body.len(),
+                body
+            );
+            stream.write_all(response.as_bytes()).unwrap();
+        });
+        format!("http://{}", addr)
+    }
+
+    #[cfg(feature = "url")]
+    #[test]
+    fn csv_is_read_from_a_url() {
+        let csv = format!("{}A01,商户甲,100,150,10,12,3.5,1\n", CSV_HEADER);
+        // 地址没有扩展名时按 Content-Type 判断格式
+        let base = serve_once("text/csv; charset=utf-8", csv.clone());
+        let bills = read_data_file_with_options(&format!("{}/export?id=1", base), &headers(), &ReadOptions::default()).unwrap();
+        assert_eq!(bills.len(), 1);
+        assert_eq!(bills[0].total_fee, 57.0);
+
+        let base = serve_once("application/octet-stream", csv);
+        let (_, format) = fetch_data_url(&format!("{}/bills.csv", base)).unwrap();
+        assert_eq!(format, "csv");
+        assert_eq!(detect_url_format("http://host/data", Some("application/vnd.openxmlformats-officedocument.spreadsheetml.sheet")), Some("xlsx"));
+        assert_eq!(detect_url_format("http://host/data", Some("application/octet-stream")), None);
+    }
+
+    #[test]
+    fn quoted_field_spanning_lines_is_one_record() {
+        let csv = CSV_HEADER.trim_end().to_string() + ",备注\n"
+            + "A01,商户甲,100,150,10,12,3.5,1,\"月底退租\n押金待退\"\n"
+            + "A02,商户乙,200,210,20,22,3.5,1,\r\n";
+        let bills = read_csv_bytes(csv.as_bytes(), &headers(), &ReadOptions::default()).unwrap();
+        assert_eq!(bills.iter().map(|b| b.shop_code.as_str()).collect::<Vec<_>>(), ["A01", "A02"]);
+        assert_eq!(bills[0].total_fee, 57.0);
+        assert_eq!(bills[1].total_fee, 17.0);
+    }
+
+    #[test]
+    fn per_meter_prices_show_own_price_and_amount() {
+        let bill = MerchantBill::builder("商户甲".to_string(), 3.5, 1.0)
+            .priced_electricity_meter("1".to_string(), 0.0, 100.0, 0.8)
+            .priced_electricity_meter("2".to_string(), 0.0, 50.0, 1.0)
+            .build();
+        assert_eq!(bill.electricity_amount, 130.0);
+        let table = bill.fee_table(&GenerateOptions::default(), "", "");
+        let meters: Vec<(&str, &str, CellMerge)> = table
+            .rows
+            .iter()
+            .filter(|r| r.kind == BillRowKind::Meter)
+            .map(|r| (r.cells[5].text.as_str(), r.cells[6].text.as_str(), r.cells[6].merge))
+            .collect();
+        assert_eq!(meters, [("0.80", "80", CellMerge::None), ("1.00", "50", CellMerge::None)]);
+        assert_eq!(table.total_text(), Some("130.00"));
+    }
+
+    #[test]
+    fn default_options_match_passing_none() {
+        let bills = [sample_bill()];
+        let with_none = generate_word_document_with_template(&bills, None).unwrap();
+        let with_default = generate_word_document_with_template(&bills, Some(GenerateOptions::default())).unwrap();
+        assert_eq!(docx_rs::read_docx(&with_none).unwrap().json(), docx_rs::read_docx(&with_default).unwrap().json());
+
+        // 最小模板配置可直接用于生成
+        let generator = template_simple::DocumentGenerator::new(template_simple::TemplateConfig::minimal());
+        let bytes = generator.generate_complete_document(&bills).unwrap();
+        assert!(docx_rs::read_docx(&bytes).unwrap().json().contains("商家水费电费账单"));
+    }
+
+    #[test]
+    fn building_name_column_becomes_rendered_name() {
+        let csv = CSV_HEADER.replace("店铺名称,", "店铺名称,小区名称,") + "A01,商户甲,阳光花园,100,150,10,12,3.5,1\n";
+        let bills = read_csv_bytes(csv.as_bytes(), &headers(), &ReadOptions::default()).unwrap();
+        let options = GenerateOptions { building_name: Some("默认小区".to_string()), ..GenerateOptions::default() };
+        assert_eq!(resolve_building_name(&bills, Some(&options)).as_deref(), Some("阳光花园"));
+        let text = docx_text(&bills, options.clone());
+        assert!(text.contains("阳光花园") && !text.contains("默认小区"), "{}", text);
+
+        // 表格中没有小区名称时使用选项中的配置
+        let plain = read_csv("A01,商户甲,100,150,10,12,3.5,1\n").unwrap();
+        assert_eq!(resolve_building_name(&plain, Some(&options)).as_deref(), Some("默认小区"));
+    }
+
+    #[test]
+    fn tiered_bill_shows_blended_effective_rate() {
+        let mut bill = MerchantBill::builder("商户甲".to_string(), 3.5, 1.0).electricity_meter("1".to_string(), 0.0, 300.0).build();
+        bill.set_electricity_tiers(Some(TieredPrice::new(vec![200.0], vec![0.5, 0.8]).unwrap()));
+        assert!(bill.has_variable_pricing());
+        assert_eq!(bill.electricity_amount, 180.0);
+        assert_eq!(bill.effective_electricity_rate(), 0.6);
+        assert_eq!(bill.effective_rate_text(), "实际单价：电费 0.60 元/度（180 元 ÷ 300 度）");
+
+        let shown = docx_text(std::slice::from_ref(&bill), GenerateOptions { show_effective_rate: true, ..GenerateOptions::default() });
+        assert!(shown.contains("实际单价：电费 0.60 元/度"), "{}", shown);
+        let hidden = docx_text(&[bill], GenerateOptions::default());
+        assert!(!hidden.contains("实际单价"), "{}", hidden);
+    }
+
+    #[test]
+    fn deduction_rows_render_negative_amounts() {
+        let mut bill = sample_bill();
+        bill.extra_fees.push(ExtraFee { name: "预存抵扣".to_string(), amount: -50.0 });
+        bill.update_totals();
+        assert_eq!(bill.total_fee, 7.0);
+        let deduction = |options: &GenerateOptions, bill: &MerchantBill| {
+            let table = bill.fee_table(options, "", "");
+            let row = table.rows.iter().find(|r| r.cells[0].text == "预存抵扣").unwrap().cells[6].clone();
+            (row.text, row.negative, table.rows.iter().find(|r| r.kind == BillRowKind::Total).unwrap().cells[2].text.clone())
+        };
+        assert_eq!(deduction(&GenerateOptions::default(), &bill), ("-50.00".to_string(), true, "柒元整".to_string()));
+        let parentheses = GenerateOptions { negative_style: NegativeStyle::Parentheses, ..GenerateOptions::default() };
+        assert_eq!(deduction(&parentheses, &bill).0, "(50.00)");
+
+        // 抵扣超过应收时合计为负，大写带"负"
+        bill.extra_fees[0].amount = -100.0;
+        bill.update_totals();
+        assert_eq!(bill.total_fee, -43.0);
+        assert_eq!(deduction(&parentheses, &bill).2, "负肆拾叁元整");
+        assert_eq!(NegativeStyle::Parentheses.format(-0.001), "0.00");
+    }
+
+    #[test]
+    fn water_first_row_order_puts_water_before_meters() {
+        let kinds = |row_order: RowOrder| {
+            let table = sample_bill().fee_table(&GenerateOptions { row_order, ..GenerateOptions::default() }, "", "");
+            table.rows.iter().take(2).map(|r| r.kind).collect::<Vec<_>>()
+        };
+        assert_eq!(GenerateOptions::default().row_order, RowOrder::ElectricityFirst);
+        assert_eq!(kinds(RowOrder::ElectricityFirst), [BillRowKind::Meter, BillRowKind::Water]);
+        assert_eq!(kinds(RowOrder::WaterFirst), [BillRowKind::Water, BillRowKind::Meter]);
+    }
+
+    #[test]
+    fn configured_default_fees_fill_missing_fee_columns() {
+        let csv = CSV_HEADER.trim_end().to_string() + ",垃圾处理费\n"
+            + "A01,商户甲,100,150,10,12,3.5,1,\n"
+            + "A02,商户乙,200,210,20,22,3.5,1,20\n";
+        let mut bills = read_csv_bytes(csv.as_bytes(), &headers(), &ReadOptions::default()).unwrap();
+        let options = GenerateOptions { default_labor_fee: Some(50.0), default_garbage_fee: Some(30.0), ..GenerateOptions::default() };
+        apply_default_fees(&mut bills, &options);
+        let fees: Vec<(f64, f64, f64)> = bills.iter().map(|b| (b.water_electricity_labor_fee, b.garbage_disposal_fee, b.total_fee)).collect();
+        // 表格中填写了的费用保留原值
+        assert_eq!(fees, [(50.0, 30.0, 137.0), (50.0, 20.0, 87.0)]);
+
+        // 生成文档时按选项补齐，不修改传入的账单
+        let plain = read_csv("A01,商户甲,100,150,10,12,3.5,1\n").unwrap();
+        let text = docx_text(&plain, options);
+        assert!(text.contains("壹佰叁拾柒元整"), "{}", text);
+        assert_eq!(plain[0].total_fee, 57.0);
+    }
+
+    // pages 页的PDF；页面大小（MediaBox）放在 Pages 节点上，由页面继承
+    #[cfg(feature = "pdf")]
+    fn blank_pdf(pages: usize) -> Vec<u8> {
+        use lopdf::{dictionary, Document, Object};
+        let mut doc = Document::with_version("1.5");
+        let pages_id = doc.new_object_id();
+        let kids: Vec<Object> = (0..pages)
+            .map(|_| doc.add_object(dictionary! { "Type" => "Page", "Parent" => pages_id }).into())
+            .collect();
+        let media_box: Vec<Object> = vec![0.into(), 0.into(), 595.into(), 842.into()];
+        doc.objects.insert(pages_id, Object::Dictionary(dictionary! {
+            "Type" => "Pages", "Kids" => kids, "Count" => pages as i64, "MediaBox" => media_box,
+        }));
+        let catalog_id = doc.add_object(dictionary! { "Type" => "Catalog", "Pages" => pages_id });
+        doc.trailer.set("Root", catalog_id);
+        let mut out = Vec::new();
+        doc.save_to(&mut out).unwrap();
+        out
+    }
+
+    #[cfg(feature = "pdf")]
+    #[test]
+    fn combined_pdf_has_a_page_per_merchant() {
+        let mut bills = vec![sample_bill(), sample_bill(), sample_bill()];
+        bills[1].shop_code = "A02".to_string();
+        bills[2].shop_code = "A03".to_string();
+        let docs = generate_per_merchant_documents(&bills, None).unwrap();
+        assert_eq!(docs.iter().map(|(name, _)| name.as_str()).collect::<Vec<_>>(), ["001_A01.docx", "002_A02.docx", "003_A03.docx"]);
+
+        // 逐户转换得到的PDF（此处用单页PDF代替）合并后页数与商户数一致
+        let merged = merge_pdfs(&docs.iter().map(|_| blank_pdf(1)).collect::<Vec<_>>()).unwrap();
+        assert!(merged.starts_with(b"%PDF"));
+        let doc = lopdf::Document::load_mem(&merged).unwrap();
+        let pages = doc.get_pages();
+        assert_eq!(pages.len(), 3);
+        // 页面继承的 MediaBox 在合并后仍然保留
+        for page_id in pages.values() {
+            assert!(doc.get_dictionary(*page_id).unwrap().get(b"MediaBox").is_ok());
+        }
+        assert_eq!(lopdf::Document::load_mem(&merge_pdfs(&[blank_pdf(2), blank_pdf(1)]).unwrap()).unwrap().get_pages().len(), 3);
+        assert!(merge_pdfs(&[]).is_err());
+    }
+
+    #[test]
+    fn broad_prefix_does_not_match_water_columns() {
+        assert!(meter_header_matches("表1上期读数（度）", "表1上期读数"));
+        assert!(!meter_header_matches("水表1上期读数", "表1上期读数"));
+        assert!(!meter_header_matches("表10上期读数", "表1上期读数"));
+
+        let csv = "铺面编号,店铺名称,水表1上期读数,水表1本期读数,表1上期读数,表1本期读数,上期水表读数,本期水表读数,水费单价,电费单价\n\
+                   A01,商户甲,10,12,100,150,10,12,3.5,1\n";
+        let headers = HeadersMap { electricity_prefix: "表", ..headers() };
+        let bills = read_csv_bytes(csv.as_bytes(), &headers, &ReadOptions::default()).unwrap();
+        assert_eq!(bills[0].electricity_meters.len(), 1);
+        assert_eq!(bills[0].electricity_usage, 50.0);
+        assert_eq!(bills[0].water_usage, 2.0);
+    }
+
+    #[test]
+    fn percentage_discount_deducts_from_subtotal() {
+        assert_eq!(Discount::parse("-10%").unwrap(), Some(Discount::Percent(0.1)));
+        assert_eq!(Discount::parse("15").unwrap(), Some(Discount::Amount(15.0)));
+        assert_eq!(Discount::parse("0").unwrap(), None);
+
+        let csv = CSV_HEADER.trim_end().to_string() + ",优惠\nA01,商户甲,0,200,10,10,3.5,1,-10%\n";
+        let bills = read_csv_bytes(csv.as_bytes(), &headers(), &ReadOptions::default()).unwrap();
+        assert_eq!(bills[0].discount_amount, -20.0);
+        assert_eq!(bills[0].total_fee, 180.0);
+        let table = bills[0].fee_table(&GenerateOptions::default(), "", "");
+        let discount = table.rows.iter().find(|r| r.kind == BillRowKind::Discount).unwrap();
+        assert_eq!((discount.cells[0].text.as_str(), discount.cells[6].text.as_str()), ("优惠", "-20.00"));
+        let total = table.rows.iter().find(|r| r.kind == BillRowKind::Total).unwrap();
+        assert_eq!((total.cells[1].text.as_str(), total.cells[2].text.as_str()), ("180.00", "壹佰捌拾元整"));
+    }
+
+    #[test]
+    fn readings_only_file_is_unpriced_until_priced() {
+        let csv = "铺面编号,店铺名称,电表1上期读数,电表1本期读数,上期水表读数,本期水表读数\nA01,商户甲,100,150,10,12\n";
+        let (bills, logs) = captured_logs(|| read_csv_bytes(csv.as_bytes(), &headers(), &ReadOptions::default()).unwrap());
+        let mut bill = bills.into_iter().next().unwrap();
+        assert!(bill.unpriced);
+        assert_eq!((bill.water_usage, bill.electricity_usage), (2.0, 50.0));
+        assert_eq!((bill.water_amount, bill.electricity_amount, bill.total_fee), (0.0, 0.0, 0.0));
+        // 仅读数输入不提示合计为0
+        assert!(!logs.iter().any(|l| l.contains("合计金额均为0")), "{:?}", logs);
+
+        bill.apply_prices(3.5, 1.0);
+        assert!(!bill.unpriced);
+        assert_eq!(bill.total_fee, 57.0);
+    }
+
+    #[test]
+    fn merchant_limit_errors_above_the_cap() {
+        let bills = vec![sample_bill(); 3];
+        let options = |max_merchants| Some(GenerateOptions { max_merchants, ..GenerateOptions::default() });
+        assert!(generate_word_document_with_template(&bills, options(3)).is_ok());
+        let error = generate_word_document_with_template(&bills, options(2)).unwrap_err().to_string();
+        assert_eq!(error, "商家数量超过上限 2，请拆分文件");
+        assert_eq!(GenerateOptions::default().max_merchants, DEFAULT_MAX_MERCHANTS);
+    }
+
+    #[test]
+    fn negative_price_warns_and_aborts_in_strict_mode() {
+        let body = "A01,商户甲,100,150,10,12,3.5,-0.8\n";
+        let (bills, logs) = captured_logs(|| read_csv(body).unwrap());
+        assert_eq!(bills.len(), 1);
+        assert!(logs.iter().any(|l| l == "警告：第2行 商户「商户甲」「电费单价」为负数: -0.8"), "{:?}", logs);
+
+        let strict = ReadOptions { strict: true, ..ReadOptions::default() };
+        let csv = format!("{}{}", CSV_HEADER, body);
+        let error = read_csv_bytes(csv.as_bytes(), &headers(), &strict).unwrap_err().to_string();
+        assert!(error.contains("单价校验失败") && error.contains("商户「商户甲」「电费单价」为负数"), "{}", error);
+    }
+
+    #[test]
+    fn money_values_have_no_float_noise() {
+        assert_eq!(round_cents(299.99999999), 300.0);
+        assert_eq!(round_cents(0.1 + 0.2), 0.3);
+
+        let mut bill = MerchantBill::new("商户甲".to_string(), 0.0, 0.0);
+        bill.water_electricity_labor_fee = 0.1 + 0.2;
+        bill.garbage_disposal_fee = 1.0 / 3.0;
+        bill.extra_fees = vec![ExtraFee { name: "卫生费".to_string(), amount: 0.1 }; 3];
+        bill.update_totals();
+        assert_eq!(bill.water_electricity_labor_fee, 0.3);
+        assert_eq!(bill.garbage_disposal_fee, 0.33);
+        assert_eq!(bill.total_fee, 0.93);
+
+        let bills = vec![bill; 10];
+        let totals = Totals::from_merchants(&bills);
+        assert_eq!(totals.grand_total, 9.3);
+        assert_eq!(totals.labor_fee, 3.0);
+        assert_eq!(summary_json(&bills)["grand_total"].to_string(), "9.3");
+    }
+
+    #[test]
+    fn combined_meters_show_one_row_with_summed_usage() {
+        let bill = MerchantBill::builder("商户甲".to_string(), 3.5, 1.0)
+            .electricity_meter("1".to_string(), 100.0, 120.0)
+            .electricity_meter("2".to_string(), 200.0, 230.0)
+            .electricity_meter("3".to_string(), 300.0, 350.0)
+            .build();
+        let options = GenerateOptions { combine_meters: true, ..GenerateOptions::default() };
+        let table = bill.fee_table(&options, "", "");
+        let meters: Vec<Vec<&str>> = table
+            .rows
+            .iter()
+            .filter(|r| r.kind == BillRowKind::Meter)
+            .map(|r| r.cells.iter().map(|c| c.text.as_str()).collect())
+            .collect();
+        assert_eq!(meters.len(), 1);
+        assert_eq!(meters[0][1..4], ["100", "350", "100"]);
+        assert_eq!(meters[0][6], "100");
+        // 账单中的分表数据保持不变
+        assert_eq!(bill.electricity_meters.len(), 3);
+        assert_eq!(bill.fee_table(&GenerateOptions::default(), "", "").rows.iter().filter(|r| r.kind == BillRowKind::Meter).count(), 3);
+    }
+
+    #[test]
+    fn meter_info_table_rows_layout() {
+        let cells = |layout: MeterInfoLayout| {
+            let options = GenerateOptions { meter_info_layout: layout, ..GenerateOptions::default() };
+            let table = sample_bill().fee_table(&options, "张三", "2025年08月16日");
+            table
+                .rows
+                .iter()
+                .filter(|r| r.kind == BillRowKind::MeterInfo)
+                .map(|r| (r.cells[0].text.clone(), r.cells[1].text.clone()))
+                .collect::<Vec<_>>()
+        };
+        let expected = [("抄表人".to_string(), "张三".to_string()), ("抄表日期".to_string(), "2025年08月16日".to_string())];
+        assert!(cells(MeterInfoLayout::InfoLine).is_empty());
+        assert_eq!(cells(MeterInfoLayout::TableRows), expected);
+        assert_eq!(cells(MeterInfoLayout::Both), expected);
+
+        let mut bill = sample_bill();
+        bill.set_meter_info(Some("张三".to_string()), Some("2025年08月16日".to_string()));
+        let options = GenerateOptions { meter_info_layout: MeterInfoLayout::TableRows, ..GenerateOptions::default() };
+        let markdown = render_bills_markdown(std::slice::from_ref(&bill), Some(options)).unwrap();
+        assert!(markdown.contains("| 抄表人 |  |  |  |  | 张三 |"), "{}", markdown);
+        assert!(!markdown.contains("抄表人：张三"), "{}", markdown);
+    }
+
+    #[test]
+    fn summary_groups_by_electricity_price_band() {
+        let priced = |code: &str, price: f64| MerchantBill::builder(code.to_string(), 3.5, price)
+            .shop_code(code.to_string())
+            .water_readings(10.0, 12.0)
+            .electricity_meter("1".to_string(), 0.0, 100.0)
+            .build();
+        let bills = [priced("A01", 1.0), priced("A02", 0.8), priced("A03", 0.8)];
+        let groups = group_by_electricity_price(&bills);
+        let bands: Vec<(f64, Vec<&str>, f64)> = groups
+            .iter()
+            .map(|(price, group)| (*price, group.iter().map(|b| b.shop_code.as_str()).collect(), Totals::from_merchants(group).grand_total))
+            .collect();
+        assert_eq!(bands, [(0.8, vec!["A02", "A03"], 174.0), (1.0, vec!["A01"], 107.0)]);
+
+        let options = GenerateOptions { summary_group_by: Some(SummaryGroup::ElectricityPrice), ..GenerateOptions::default() };
+        let markdown = render_bills_markdown(&bills, Some(options.clone())).unwrap();
+        assert!(markdown.contains("| **小计（电价 0.80 元/度）** | 174.00 | 0.00 | 0.00 | **174.00** |"), "{}", markdown);
+        assert!(markdown.contains("| **小计（电价 1.00 元/度）** | 107.00 | 0.00 | 0.00 | **107.00** |"), "{}", markdown);
+        let text = docx_text(&bills, options);
+        assert!(text.contains("小计（电价 0.80 元/度）") && text.contains("小计（电价 1.00 元/度）"), "{}", text);
+    }
+
+    #[test]
+    fn half_even_rounds_ties_to_even_amounts() {
+        assert_eq!(RoundingMode::HalfUp.round(2.5), 3.0);
+        assert_eq!(RoundingMode::HalfEven.round(2.5), 2.0);
+        assert_eq!(RoundingMode::HalfEven.round(3.5), 4.0);
+
+        // 水 5吨 × 0.5 = 2.5；电 25度 × 0.1 = 2.5000000000000004（乘法误差）
+        let bill = |rounding| MerchantBill::builder("商户甲".to_string(), 0.5, 0.1)
+            .water_readings(0.0, 5.0)
+            .electricity_meter("1".to_string(), 0.0, 25.0)
+            .amount_rounding(rounding)
+            .build();
+        let half_up = bill(RoundingMode::HalfUp);
+        assert_eq!((half_up.water_amount, half_up.electricity_amount, half_up.total_fee), (3.0, 3.0, 6.0));
+        let half_even = bill(RoundingMode::HalfEven);
+        assert_eq!((half_even.water_amount, half_even.electricity_amount, half_even.total_fee), (2.0, 2.0, 4.0));
+        assert_eq!(MerchantBill::new(String::new(), 0.0, 0.0).amount_rounding, RoundingMode::HalfUp);
+    }
+
+    #[test]
+    fn mixed_meter_header_styles_are_all_detected() {
+        let csv = "铺面编号,店铺名称,电表1上期读数,电表1本期读数,2号电表上期,2号电表本期,电表（3）上期读数,电表（3）本期读数,上期水表读数,本期水表读数,水费单价,电费单价\n\
+                   A01,商户甲,0,10,0,20,0,30,10,12,3.5,1\n";
+        let options = ReadOptions {
+            meter_header_templates: COMMON_METER_HEADER_TEMPLATES.iter().map(|t| t.to_string()).collect(),
+            ..ReadOptions::default()
+        };
+        let bills = read_csv_bytes(csv.as_bytes(), &headers(), &options).unwrap();
+        let usages: Vec<f64> = bills[0].electricity_meters.iter().map(|m| m.usage).collect();
+        assert_eq!(usages, [10.0, 20.0, 30.0]);
+
+        // 只按默认的 "电表{n}" 时，编号2不匹配，后续电表不再查找
+        let bills = read_csv_bytes(csv.as_bytes(), &headers(), &ReadOptions::default()).unwrap();
+        assert_eq!(bills[0].electricity_meters.len(), 1);
+    }
+
+    #[test]
+    fn comma_decimal_with_dot_grouping() {
+        let money = MoneyFormat { decimal_separator: ',', grouping_separator: Some('.') };
+        assert_eq!(money.format(1234.56, 2), "1.234,56");
+        assert_eq!(NegativeStyle::Minus.format_money(-1234.56, &money), "-1.234,56");
+
+        // 7 + 50 + 1177.56 = 1234.56
+        let bill = MerchantBill::builder("商户甲".to_string(), 3.5, 1.0)
+            .water_readings(10.0, 12.0)
+            .electricity_meter("1".to_string(), 100.0, 150.0)
+            .labor_fee(1177.56)
+            .build();
+        let options = GenerateOptions { money_format: money, ..GenerateOptions::default() };
+        let table = bill.fee_table(&options, "", "");
+        assert_eq!(table.total_text(), Some("1.234,56"));
+        // 滞纳金、广告费占位行同样按配置显示
+        let placeholders: Vec<&str> = table.rows.iter().filter(|r| r.kind == BillRowKind::Placeholder).map(|r| r.cells[6].text.as_str()).collect();
+        assert_eq!(placeholders, ["0,00", "0,00"]);
+    }
+
+    // 第一个工作表为空（如封面），之后的工作表才是数据
+    fn xlsx_with_empty_first_sheet(data: bool) -> Vec<u8> {
+        let mut workbook = rust_xlsxwriter::Workbook::new();
+        workbook.add_worksheet().set_name("封面").unwrap();
+        let sheet = workbook.add_worksheet().set_name("账单").unwrap();
+        if data {
+            for (col, header) in CSV_HEADER.trim().split(',').enumerate() {
+                sheet.write(0, col as u16, header).unwrap();
+            }
+            sheet.write(1, 0, "A01").unwrap();
+            sheet.write(1, 1, "商户甲").unwrap();
+            for (col, value) in [100.0, 150.0, 10.0, 12.0, 3.5, 1.0].into_iter().enumerate() {
+                sheet.write(1, col as u16 + 2, value).unwrap();
+            }
+        }
+        workbook.save_to_buffer().unwrap()
+    }
+
+    #[test]
+    fn empty_first_sheet_falls_through_to_the_next_sheet() {
+        let (bills, logs) = captured_logs(|| read_excel_bytes(&xlsx_with_empty_first_sheet(true), &headers(), &ReadOptions::default()));
+        let bills = bills.unwrap();
+        assert_eq!(bills.len(), 1);
+        assert_eq!(bills[0].merchant_name, "商户甲");
+        assert_eq!(bills[0].total_fee, 57.0);
+        assert!(logs.iter().any(|l| l.contains("工作表为空或无法读取：封面")), "{:?}", logs);
+
+        let err = read_excel_bytes(&xlsx_with_empty_first_sheet(false), &headers(), &ReadOptions::default()).unwrap_err();
+        assert!(format!("{:#}", err).contains("工作表为空或无法读取：封面、账单，请检查是否选错工作表"), "{:#}", err);
+    }
+
+    #[test]
+    fn meter_serial_column_is_shown_in_the_label() {
+        let csv = "铺面编号,店铺名称,电表1编号,电表1上期读数,电表1本期读数,上期水表读数,本期水表读数,水费单价,电费单价\n\
+                   A01,商户甲,EM-0098,100,150,10,12,3.5,1\n";
+        let bills = read_csv_bytes(csv.as_bytes(), &headers(), &ReadOptions::default()).unwrap();
+        assert_eq!(bills[0].electricity_meters[0].serial.as_deref(), Some("EM-0098"));
+
+        let table = bills[0].fee_table(&GenerateOptions::default(), "", "");
+        let meter_row = table.rows.iter().find(|r| r.kind == BillRowKind::Meter).unwrap();
+        assert_eq!(meter_row.cells[0].text, "电表（EM-0098）");
+        assert!(docx_text(&bills, GenerateOptions::default()).contains("电表（EM-0098）"));
+    }
+
+    #[test]
+    fn custom_summary_columns_roll_labor_into_the_combined_total() {
+        let bill = MerchantBill::builder("商户甲".to_string(), 3.5, 1.0)
+            .water_readings(10.0, 12.0)
+            .electricity_meter("1".to_string(), 100.0, 150.0)
+            .labor_fee(5.0)
+            .garbage_fee(3.0)
+            .build();
+        let options = GenerateOptions {
+            summary_columns: SummaryColumns {
+                combined: vec![FeeCategory::Water, FeeCategory::Electricity, FeeCategory::LaborFee],
+                separate: vec![FeeCategory::GarbageFee],
+            },
+            ..GenerateOptions::default()
+        };
+        let md = render_bills_markdown(&[bill], Some(options)).unwrap();
+        assert!(md.contains("| 店铺名称 | 水费+电费+水电人工费合计（元） | 垃圾处理费 | 总价 |\n"), "{}", md);
+        assert!(md.contains("| 商户甲 | 62.00 | 3.00 | 65.00 |\n"), "{}", md);
+    }
+
+    #[test]
+    fn three_merchants_get_sequential_numbers_from_one() {
+        let bills: Vec<MerchantBill> = ["商户甲", "商户乙", "商户丙"].iter()
+            .map(|name| MerchantBill::builder(name.to_string(), 3.5, 1.0).water_readings(10.0, 12.0).build())
+            .collect();
+        let options = GenerateOptions { start_sequence: Some(1), ..GenerateOptions::default() };
+        let text = docx_text(&bills, options.clone());
+        for number in ["单据编号：No.0001", "单据编号：No.0002", "单据编号：No.0003"] {
+            assert!(text.contains(number), "{}", number);
+        }
+        assert!(!text.contains("No.0004"));
+
+        let md = render_bills_markdown(&bills, Some(options)).unwrap();
+        let positions: Vec<usize> = ["No.0001", "No.0002", "No.0003"].iter().map(|n| md.find(n).unwrap()).collect();
+        assert!(positions.windows(2).all(|w| w[0] < w[1]));
+        assert!(!docx_text(&bills, GenerateOptions::default()).contains("单据编号"));
+    }
+
+    #[test]
+    fn full_width_shop_code_matches_half_width() {
+        assert_eq!(normalize_width("Ａ－１０１　"), "A-101");
+
+        let body = "Ａ－１０１,商户甲,100,150,10,12,3.5,1\nA-101,商户甲,150,160,12,13,3.5,1.2\n";
+        let bills = read_csv(body).unwrap();
+        // 默认显示保留原文，匹配时按半角比较
+        assert_eq!(bills[0].shop_code, "Ａ－１０１");
+        assert_eq!(bills[0].shop_code_key(), bills[1].shop_code_key());
+        assert_eq!(find_price_conflicts(&bills), ["铺面A-101的电费单价不一致: 1 / 1.2"]);
+
+        let options = ReadOptions { normalize_full_width: true, ..ReadOptions::default() };
+        let bills = read_csv_bytes(format!("{}{}", CSV_HEADER, body).as_bytes(), &headers(), &options).unwrap();
+        assert_eq!(bills[0].shop_code, "A-101");
+    }
+
+    #[test]
+    fn nameless_row_with_shop_code_is_kept_when_enabled() {
+        let csv = format!("{}A01,,100,150,10,12,3.5,1\n", CSV_HEADER);
+        assert!(read_csv_bytes(csv.as_bytes(), &headers(), &ReadOptions::default()).unwrap().is_empty());
+
+        let options = ReadOptions { keep_nameless_rows: true, ..ReadOptions::default() };
+        let (bills, logs) = captured_logs(|| read_csv_bytes(csv.as_bytes(), &headers(), &options));
+        let bills = bills.unwrap();
+        assert_eq!(bills.len(), 1);
+        assert_eq!(bills[0].merchant_name, "A01");
+        assert_eq!(bills[0].total_fee, 57.0);
+        assert!(logs.iter().any(|l| l.contains("警告：第2行店铺名称为空，以铺面编号「A01」作为名称")), "{:?}", logs);
+    }
+
+    #[test]
+    fn price_change_yields_per_merchant_diffs() {
+        let before = read_csv("A01,商户甲,100,150,10,12,3.5,1\nA02,商户乙,0,10,0,1,3.5,1\n").unwrap();
+        let after = read_csv("A01,商户甲,100,150,10,12,3.5,1.2\nA02,商户乙,0,10,0,1,3.5,1\n").unwrap();
+        assert!(diff_documents(&before, &before).is_empty());
+
+        let diff = |field, before, after| FieldDiff {
+            shop_code: "A01".to_string(),
+            merchant_name: "商户甲".to_string(),
+            field,
+            before: Some(before),
+            after: Some(after),
+        };
+        assert_eq!(diff_documents(&before, &after), [diff("electricity_amount", 50.0, 60.0), diff("total_fee", 57.0, 67.0)]);
+    }
+
+    #[test]
+    fn separator_line_can_be_disabled() {
+        let bills = read_csv("A01,商户甲,100,150,10,12,3.5,1\nA02,商户乙,0,10,0,1,3.5,1\n").unwrap();
+        let separator = "=".repeat(40);
+        let options = GenerateOptions { per_page: 2, ..GenerateOptions::default() };
+        assert!(docx_text(&bills, options.clone()).contains(&separator));
+
+        let text = docx_text(&bills, GenerateOptions { show_separator: false, ..options });
+        assert!(!text.contains("===="));
+        assert!(text.contains("商户甲") && text.contains("商户乙"));
+    }
+
+    #[test]
+    fn readings_with_unit_suffix_parse_and_feed_usage() {
+        assert_eq!(parse_amount("1234度"), Ok(1234.0));
+        assert_eq!(parse_amount("56吨"), Ok(56.0));
+        assert_eq!(parse_amount("12 立方米"), Ok(12.0));
+        assert_eq!(parse_amount("30kWh"), Ok(30.0));
+
+        let bills = read_csv("A01,商户甲,1234度,1284度,10吨,12吨,3.5,1\n").unwrap();
+        assert_eq!(bills[0].electricity_usage, 50.0);
+        assert_eq!(bills[0].water_usage, 2.0);
+        assert_eq!(bills[0].total_fee, 57.0);
+    }
+
+    #[test]
+    fn footer_shows_grand_total_only_when_enabled() {
+        let bills = read_csv("A01,商户甲,100,150,10,12,3.5,1\nA02,商户乙,0,10,0,1,3.5,1\n").unwrap();
+        assert!(!docx_text(&bills, GenerateOptions::default()).contains("本批合计"));
+
+        // 57 + 14（水费3.5元取整）
+        let text = docx_text(&bills, GenerateOptions { include_footer_total: true, ..GenerateOptions::default() });
+        assert!(text.contains("本批合计：¥71.00    第 "), "{}", text);
+        assert!(text.contains(" 页"));
+    }
+
+    // 生成的 Word 文档正文 XML（word/document.xml）
+    fn docx_document_xml(bills: &[MerchantBill], options: GenerateOptions) -> String {
+        let bytes = generate_word_document_with_template(bills, Some(options)).unwrap();
+        let mut archive = zip::ZipArchive::new(Cursor::new(bytes)).unwrap();
+        let mut xml = String::new();
+        archive.by_name("word/document.xml").unwrap().read_to_string(&mut xml).unwrap();
+        xml
+    }
+
+    #[test]
+    fn configured_row_height_appears_in_table_xml() {
+        let bills = [sample_bill()];
+        let xml = docx_document_xml(&bills, GenerateOptions::default());
+        assert!(!xml.contains(r#"<w:trHeight w:val="612""#));
+
+        let xml = docx_document_xml(&bills, GenerateOptions { row_height: Some(612.0), body_font_size: Some(20), ..GenerateOptions::default() });
+        assert!(xml.contains(r#"<w:trHeight w:val="612""#), "{}", xml);
+    }
+
+    #[test]
+    fn one_bad_utf8_byte_parses_in_lenient_mode_with_warning() {
+        let mut csv = CSV_HEADER.as_bytes().to_vec();
+        csv.extend_from_slice(b"A01,\xE5\x95\x86\xFF,100,150,10,12,3.5,1\n");
+
+        let err = read_csv_bytes(&csv, &headers(), &ReadOptions::default()).unwrap_err();
+        assert!(format!("{:#}", err).contains("CSV第2行包含无效的UTF-8字节"), "{:#}", err);
+
+        let options = ReadOptions { lenient_utf8: true, ..ReadOptions::default() };
+        let (bills, logs) = captured_logs(|| read_csv_bytes(&csv, &headers(), &options));
+        let bills = bills.unwrap();
+        assert_eq!(bills[0].merchant_name, "商\u{FFFD}");
+        assert_eq!(bills[0].total_fee, 57.0);
+        assert!(logs.iter().any(|l| l.starts_with("警告：CSV第2行包含无效的UTF-8字节")), "{:?}", logs);
+    }
+
+    #[test]
+    fn summary_json_grand_total_is_sum_of_merchant_totals() {
+        let bills: Vec<MerchantBill> = [("A01", 0.1), ("A02", 0.2), ("A03", 12.35)].iter()
+            .map(|(code, fee)| MerchantBill::builder(format!("商户{}", code), 3.5, 0.85)
+                .shop_code(code.to_string())
+                .water_readings(10.0, 12.0)
+                .electricity_meter("1".to_string(), 100.0, 133.0)
+                .labor_fee(*fee)
+                .build())
+            .collect();
+        let summary = summary_json(&bills);
+        let sum = |f: fn(&MerchantBill) -> f64| round_cents(bills.iter().map(f).sum());
+        assert_eq!(summary["merchant_count"], 3);
+        assert_eq!(summary["grand_total"], sum(|b| b.total_fee));
+        assert_eq!(summary["water_amount"], sum(|b| b.water_amount));
+        assert_eq!(summary["electricity_amount"], sum(|b| b.electricity_amount));
+        assert_eq!(summary["labor_fee"], 12.65);
+    }
+
+    #[test]
+    fn formula_error_in_price_cell_is_reported() {
+        use std::io::Write;
+        let mut workbook = rust_xlsxwriter::Workbook::new();
+        let sheet = workbook.add_worksheet();
+        for (col, header) in CSV_HEADER.trim().split(',').enumerate() {
+            sheet.write(0, col as u16, header).unwrap();
+        }
+        sheet.write(1, 0, "A01").unwrap();
+        sheet.write(1, 1, "商户甲").unwrap();
+        for (col, value) in [100.0, 150.0, 10.0, 12.0, 3.5].into_iter().enumerate() {
+            sheet.write(1, col as u16 + 2, value).unwrap();
+        }
+        sheet.write_formula(1, 7, rust_xlsxwriter::Formula::new("=1/0").set_result("#DIV/0!")).unwrap();
+        let bytes = workbook.save_to_buffer().unwrap();
+
+        // rust_xlsxwriter 将文本结果写为 t="str"，改为 Excel 保存公式错误时的 t="e"
+        let mut archive = zip::ZipArchive::new(Cursor::new(bytes)).unwrap();
+        let mut patched = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        for i in 0..archive.len() {
+            let mut file = archive.by_index(i).unwrap();
+            let mut content = String::new();
+            file.read_to_string(&mut content).unwrap();
+            if file.name() == "xl/worksheets/sheet1.xml" {
+                content = content.replace(r#"t="str""#, r#"t="e""#);
+            }
+            patched.start_file(file.name(), zip::write::FileOptions::default()).unwrap();
+            patched.write_all(content.as_bytes()).unwrap();
+        }
+        let bytes = patched.finish().unwrap().into_inner();
+
+        let (bills, logs) = captured_logs(|| read_excel_bytes(&bytes, &headers(), &ReadOptions::default()));
+        let bills = bills.unwrap();
+        assert_eq!(bills[0].electricity_unit_price, 0.0);
+        assert!(logs.iter().any(|l| l == "警告：第2行「电费单价」公式错误 #DIV/0!，请检查该单元格的公式"), "{:?}", logs);
+    }
+
+    #[test]
+    fn empty_meter_reader_per_info_field_style() {
+        let bill = MerchantBill::builder("商户甲".to_string(), 3.5, 1.0)
+            .shop_code("A01".to_string())
+            .water_readings(10.0, 12.0)
+            .meter_info(None, Some("2025年08月16日".to_string()))
+            .build();
+        let info_line = |style| {
+            let options = GenerateOptions { empty_info_field: style, ..GenerateOptions::default() };
+            // 文档文字为转义后的形式，制表符显示为 \t
+            let text = docx_text(std::slice::from_ref(&bill), options);
+            assert!(text.contains("编号：\\tA01\\t姓名\\t商户甲"), "{}", text);
+            text
+        };
+        assert!(info_line(EmptyFieldStyle::Blank).contains("姓名\\t商户甲\\t抄表人：\\t\\t抄表日期：2025年08月16日"));
+        assert!(info_line(EmptyFieldStyle::Underline).contains("姓名\\t商户甲\\t抄表人：\\t________\\t抄表日期：2025年08月16日"));
+        let omitted = info_line(EmptyFieldStyle::Omit);
+        assert!(omitted.contains("姓名\\t商户甲\\t抄表日期：2025年08月16日"));
+        assert!(!omitted.contains("抄表人"));
+    }
+
+    #[test]
+    fn flagged_meter_uses_history_usage_and_shows_marker() {
+        let csv = "铺面编号,店铺名称,电表1上期读数,电表1本期读数,上期水表读数,本期水表读数,水费单价,电费单价,免抄\n\
+                   A01,商户甲,100,,10,12,3.5,1,是\n\
+                   A02,商户乙,100,150,10,12,3.5,1,否\n";
+        let options = ReadOptions { usage_history: HashMap::from([("A01".to_string(), vec![80.0])]), ..ReadOptions::default() };
+        let bills = read_csv_bytes(csv.as_bytes(), &headers(), &options).unwrap();
+        let meter = &bills[0].electricity_meters[0];
+        assert!(meter.estimated);
+        assert_eq!((meter.curr_reading, meter.usage), (180.0, 80.0));
+        assert_eq!(bills[0].electricity_amount, 80.0);
+        assert!(!bills[1].electricity_meters[0].estimated);
+
+        let label = |bill: &MerchantBill| {
+            let table = bill.fee_table(&GenerateOptions::default(), "", "");
+            table.rows.iter().find(|r| r.kind == BillRowKind::Meter).unwrap().cells[0].text.clone()
+        };
+        assert_eq!(label(&bills[0]), "电表（估）");
+        assert_eq!(label(&bills[1]), "电表");
+    }
+
+    #[test]
+    fn bill_table_totals_match_in_docx_and_markdown() {
+        let bill = MerchantBill::builder("商户甲".to_string(), 2.85, 0.83)
+            .water_readings(10.0, 13.0)
+            .electricity_meter("1".to_string(), 100.0, 151.0)
+            .labor_fee(12.5)
+            .build();
+        let options = GenerateOptions::default();
+        let table = bill.fee_table(&options, "", "");
+        // 水费8.55、电费42.33按元取整后为 9 + 42，加人工费12.5
+        assert_eq!(table.total_text(), Some("63.50"));
+
+        let bills = [bill];
+        assert!(docx_text(&bills, options.clone()).contains("大写：陆拾叁元伍角    小写：63.50"));
+        let md = render_bills_markdown(&bills, Some(options)).unwrap();
+        assert!(md.contains("| **合计** |  |  |  |  | **63.50**（陆拾叁元伍角） |"), "{}", md);
+    }
+
+    #[test]
+    fn hidden_shared_column_gives_six_columns_and_total_span() {
+        let options = GenerateOptions { show_shared_column: false, ..GenerateOptions::default() };
+        let table = sample_bill().fee_table(&options, "", "");
+        assert_eq!(table.header, ["项目", "上月表底", "本月抄表数", "实用度数", "单价（元）", "金额"]);
+        for row in table.rows.iter().filter(|r| !matches!(r.kind, BillRowKind::Total | BillRowKind::MeterInfo | BillRowKind::Placeholder)) {
+            assert_eq!(row.cells.len(), 6, "{:?}", row);
+        }
+
+        // 合计行：标签一格 + 跨其余5列
+        let xml = docx_document_xml(&[sample_bill()], options);
+        assert!(xml.contains(r#"<w:gridSpan w:val="5""#), "{}", xml);
+        assert!(!xml.contains(r#"<w:gridSpan w:val="6""#));
+        assert!(docx_document_xml(&[sample_bill()], GenerateOptions::default()).contains(r#"<w:gridSpan w:val="6""#));
+    }
+
+    #[test]
+    fn tax_id_is_kept_as_text_and_shown_in_info_line() {
+        let csv = format!("{},纳税人识别号\nA01,商户甲,100,150,10,12,3.5,1,91440300MA5F0ABC1X\nA02,商户乙,0,10,0,1,3.5,1,914403000123456789\n", CSV_HEADER.trim());
+        let bills = read_csv_bytes(csv.as_bytes(), &headers(), &ReadOptions::default()).unwrap();
+        assert_eq!(bills[0].tax_id.as_deref(), Some("91440300MA5F0ABC1X"));
+        assert_eq!(bills[1].tax_id.as_deref(), Some("914403000123456789"));
+        assert_eq!(bills[0].total_fee, 57.0);
+
+        let text = docx_text(&bills, GenerateOptions::default());
+        assert!(text.contains("姓名\\t商户甲\\t纳税人识别号：\\t91440300MA5F0ABC1X"), "{}", text);
+        assert!(text.contains("纳税人识别号：\\t914403000123456789"));
+    }
+
+    #[test]
+    fn fractional_usage_is_shown_and_reconciles_with_amount() {
+        let bill = MerchantBill::builder("商户甲".to_string(), 3.5, 2.0)
+            .electricity_meter("1".to_string(), 100.0, 110.5)
+            .build();
+        let table = bill.fee_table(&GenerateOptions::default(), "", "");
+        let meter = table.rows.iter().find(|r| r.kind == BillRowKind::Meter).unwrap();
+        let (usage, price, amount) = (&meter.cells[3].text, &meter.cells[5].text, &meter.cells[6].text);
+        assert_eq!((meter.cells[2].text.as_str(), usage.as_str(), amount.as_str()), ("110.5", "10.5", "21"));
+        let shown: f64 = usage.parse::<f64>().unwrap() * price.parse::<f64>().unwrap();
+        assert_eq!(shown, amount.parse::<f64>().unwrap());
+
+        let options = GenerateOptions { usage_decimals: Some(2), ..GenerateOptions::default() };
+        let meter = bill.fee_table(&options, "", "").rows.into_iter().find(|r| r.kind == BillRowKind::Meter).unwrap();
+        assert_eq!(meter.cells[3].text, "10.50");
+    }
+
+    #[test]
+    fn all_zero_file_warns_and_errors_in_strict_mode() {
+        let csv = format!("{}A01,商户甲,100,100,10,10,3.5,1\nA02,商户乙,50,50,5,5,3.5,1\n", CSV_HEADER);
+        let (bills, logs) = captured_logs(|| read_csv_bytes(csv.as_bytes(), &headers(), &ReadOptions::default()));
+        assert_eq!(bills.unwrap().len(), 2);
+        let message = "全部2户的合计金额均为0，可能是单价或读数列未正确读取，请检查表头与数据";
+        assert!(logs.contains(&format!("警告：{}", message)), "{:?}", logs);
+
+        let options = ReadOptions { strict: true, ..ReadOptions::default() };
+        let err = read_csv_bytes(csv.as_bytes(), &headers(), &options).unwrap_err();
+        assert_eq!(err.to_string(), message);
+
+        let (_, logs) = captured_logs(|| read_csv("A01,商户甲,100,150,10,12,3.5,1\nA02,商户乙,50,50,5,5,3.5,1\n"));
+        assert!(!logs.iter().any(|l| l.contains("合计金额均为0")));
+    }
+
+    #[test]
+    fn old_template_config_reports_incompatible_version() {
+        use crate::template_simple::TemplateConfig;
+        // 旧版配置：没有 individual_bills，且带有已移除的 page_size
+        let mut config: serde_json::Value = serde_json::from_str(include_str!("../config/template_config.json")).unwrap();
+        let fields = config.as_object_mut().unwrap();
+        fields.remove("version");
+        fields.remove("individual_bills");
+        fields.insert("page_size".to_string(), serde_json::json!("A4"));
+
+        let err = TemplateConfig::from_json_str(&config.to_string()).unwrap_err();
+        assert!(err.starts_with("配置版本不兼容（配置版本：未标注，程序支持版本：1）；缺少字段：individual_bills；未知字段："), "{}", err);
+        assert!(err.contains("page_size"), "{}", err);
+        assert!(TemplateConfig::from_json_str(include_str!("../config/template_config.json")).is_ok());
+    }
+
+    // 读取结构体在反序列化时声明的全部字段名
+    struct StructFields<'a>(&'a mut &'static [&'static str]);
+
+    impl<'de> serde::Deserializer<'de> for StructFields<'_> {
+        type Error = serde::de::value::Error;
+
+        fn deserialize_any<V: serde::de::Visitor<'de>>(self, _visitor: V) -> Result<V::Value, Self::Error> {
+            Err(serde::de::Error::custom("只用于读取字段名"))
+        }
+
+        fn deserialize_struct<V: serde::de::Visitor<'de>>(self, _name: &'static str, fields: &'static [&'static str], _visitor: V) -> Result<V::Value, Self::Error> {
+            *self.0 = fields;
+            Err(serde::de::Error::custom("只用于读取字段名"))
+        }
+
+        serde::forward_to_deserialize_any! {
+            bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string bytes byte_buf option unit
+            unit_struct newtype_struct seq tuple tuple_struct map enum identifier ignored_any
+        }
+    }
+
+    #[test]
+    fn template_config_field_lists_match_struct() {
+        use crate::template_simple::{TemplateConfig, OPTIONAL_FIELDS, REQUIRED_FIELDS};
+        let mut fields: &'static [&'static str] = &[];
+        assert!(TemplateConfig::deserialize(StructFields(&mut fields)).is_err());
+        let mut declared: Vec<&str> = REQUIRED_FIELDS.iter().chain(OPTIONAL_FIELDS).copied().collect();
+        let mut actual = fields.to_vec();
+        declared.sort_unstable();
+        actual.sort_unstable();
+        assert_eq!(declared, actual);
+
+        // 只含必填字段的配置可以解析，即可选字段确实可省略
+        let config: serde_json::Value = serde_json::from_str(include_str!("../config/template_config.json")).unwrap();
+        let required: serde_json::Map<String, serde_json::Value> = config
+            .as_object()
+            .unwrap()
+            .iter()
+            .filter(|(k, _)| REQUIRED_FIELDS.contains(&k.as_str()))
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect();
+        assert_eq!(required.len(), REQUIRED_FIELDS.len());
+        assert!(TemplateConfig::from_json_str(&serde_json::Value::Object(required).to_string()).is_ok());
+    }
+
+    #[test]
+    fn price_cells_show_unit_labels_when_enabled() {
+        let price = |options: &GenerateOptions, kind| {
+            let table = sample_bill().fee_table(options, "", "");
+            table.rows.iter().find(|r| r.kind == kind).unwrap().cells[5].text.clone()
+        };
+        let default = GenerateOptions::default();
+        assert_eq!((price(&default, BillRowKind::Meter), price(&default, BillRowKind::Water)), ("1.00".to_string(), "3.500".to_string()));
+
+        let labeled = GenerateOptions { show_price_units: true, ..GenerateOptions::default() };
+        assert_eq!(price(&labeled, BillRowKind::Meter), "1.00 元/度");
+        assert_eq!(price(&labeled, BillRowKind::Water), "3.500 元/吨");
+        assert!(docx_text(&[sample_bill()], labeled).contains("1.00 元/度"));
+    }
+
+    #[test]
+    fn disjoint_extra_fees_produce_a_union_of_summary_columns() {
+        let with_fee = |name: &str, fee: &str, amount: f64| {
+            let mut bill = MerchantBill::builder(name.to_string(), 3.5, 1.0).water_readings(10.0, 12.0).build();
+            bill.extra_fees.push(ExtraFee { name: fee.to_string(), amount });
+            bill.update_totals();
+            bill
+        };
+        let bills = [with_fee("商户甲", "停车费", 20.0), with_fee("商户乙", "卫生费", 5.5)];
+        assert_eq!(extra_fee_names(&bills), ["停车费", "卫生费"]);
+        let totals = Totals::from_merchants(&bills);
+        assert_eq!((totals.extra_fee("停车费"), totals.extra_fee("卫生费"), totals.grand_total), (20.0, 5.5, 39.5));
+
+        let md = render_bills_markdown(&bills, None).unwrap();
+        assert!(md.contains("| 店铺名称 | 水电费合计（元） | 水电人工费 | 垃圾处理费 | 停车费 | 卫生费 | 总价 |\n"), "{}", md);
+        assert!(md.contains("| 商户甲 | 7.00 | 0.00 | 0.00 | 20.00 | 0.00 | 27.00 |\n"), "{}", md);
+        assert!(md.contains("| 商户乙 | 7.00 | 0.00 | 0.00 | 0.00 | 5.50 | 12.50 |\n"), "{}", md);
+        assert!(md.contains("| **合计** | 14.00 | 0.00 | 0.00 | 20.00 | 5.50 | **39.50** |\n"), "{}", md);
+    }
+
+    #[test]
+    fn mostly_reversed_readings_trigger_swap_warning() {
+        let rows = |reversed: usize| (1..=5)
+            .map(|i| if i <= reversed { format!("A0{},商户{},150,100,10,12,3.5,1\n", i, i) } else { format!("A0{},商户{},100,150,10,12,3.5,1\n", i, i) })
+            .collect::<String>();
+        let (bills, logs) = captured_logs(|| read_csv(&rows(5)));
+        assert!(bills.unwrap().iter().all(|b| b.electricity_usage == 0.0));
+        assert!(logs.iter().any(|l| l.starts_with("警告：电表读数中有5/5个本期读数小于上期读数") && l.contains("可能整列贴反")), "{:?}", logs);
+        assert!(!logs.iter().any(|l| l.contains("水表读数中有")));
+
+        let (_, logs) = captured_logs(|| read_csv(&rows(1)));
+        assert!(!logs.iter().any(|l| l.contains("整列贴反")), "{:?}", logs);
+
+        let options = ReadOptions { strict: true, ..ReadOptions::default() };
+        let csv = format!("{}{}", CSV_HEADER, rows(5));
+        assert!(read_csv_bytes(csv.as_bytes(), &headers(), &options).unwrap_err().to_string().contains("整列贴反"));
+    }
+
+    #[test]
+    fn half_page_layout_puts_two_merchants_per_page() {
+        let bills: Vec<MerchantBill> = (1..=4)
+            .map(|i| MerchantBill::builder(format!("商户{}", i), 3.5, 1.0).water_readings(10.0, 12.0).build())
+            .collect();
+        // per_page 在半页版式下被忽略
+        let options = GenerateOptions { page_layout: PageLayout::HalfPage, per_page: 4, ..GenerateOptions::default() };
+        let xml = docx_document_xml(&bills, options.clone());
+        assert_eq!(xml.matches(r#"<w:br w:type="page""#).count(), 1, "{}", xml);
+
+        let text = docx_text(&bills, options);
+        assert!((1..=4).all(|i| text.contains(&format!("商户{}", i))));
+    }
+
+    #[test]
+    fn third_decimal_rounds_to_fen_in_both_forms() {
+        assert_eq!(rmb_upper(0.125), "壹角叁分");
+        assert_eq!(NegativeStyle::Minus.format(0.125), "0.13");
+
+        // 水费 12.5吨 × 0.01 = 0.125 元（两位小数取整）
+        let bill = MerchantBill::builder("商户甲".to_string(), 0.01, 0.0)
+            .water_readings(0.0, 12.5)
+            .amount_rounding(RoundingMode::TwoDecimals)
+            .build();
+        let table = bill.fee_table(&GenerateOptions::default(), "", "");
+        let total = table.rows.iter().find(|r| r.kind == BillRowKind::Total).unwrap();
+        assert_eq!((total.cells[1].text.as_str(), total.cells[2].text.as_str()), ("0.13", "壹角叁分"));
+        assert_eq!(rmb_upper_to_amount(&total.cells[2].text), Some(0.13));
+    }
+
+    #[test]
+    fn google_sheets_quoted_crlf_export_parses_numbers() {
+        let quote = |line: &str| line.trim().split(',').map(|f| format!("\"{}\"", f)).collect::<Vec<_>>().join(",") + "\r\n";
+        let csv = quote(CSV_HEADER) + &quote("A01,商户甲,100,150,10,12,3.5,1") + &quote("A02,商户乙,0,10.5,0,1,2.85,0.8");
+        let bills = read_csv_bytes(csv.as_bytes(), &headers(), &ReadOptions::default()).unwrap();
+        assert_eq!(bills.len(), 2);
+        assert_eq!((bills[0].shop_code.as_str(), bills[0].merchant_name.as_str()), ("A01", "商户甲"));
+        let meter = &bills[0].electricity_meters[0];
+        assert_eq!((meter.prev_reading, meter.curr_reading), (100.0, 150.0));
+        assert_eq!((bills[0].prev_water_reading, bills[0].curr_water_reading), (10.0, 12.0));
+        assert_eq!((bills[1].water_unit_price, bills[1].electricity_unit_price), (2.85, 0.8));
+        assert_eq!(bills[1].electricity_usage, 10.5);
+        assert_eq!(bills[0].total_fee, 57.0);
+    }
+
+    #[test]
+    fn debug_dump_lists_raw_and_coerced_values() {
+        let csv = format!("{}A01,商户甲,100,\"1,150\",10,abc,¥3.5,1\n", CSV_HEADER);
+        let (bills, _) = captured_logs(|| read_csv_bytes(csv.as_bytes(), &headers(), &ReadOptions::default()));
+        let dump = debug_dump(csv.as_bytes(), "csv", "电表", &bills.unwrap()).unwrap();
+        for line in [
+            "== 第2行 ==",
+            "  店铺名称：「商户甲」",
+            "  电表1本期读数：「1,150」 → 1150",
+            "  本期水表读数：「abc」 → 0（无法解析数值: abc，按0计算）",
+            "  水费单价：「¥3.5」 → 3.5",
+            "[计算结果] 商户甲（A01）",
+            "  电表1：100 → 1150，用电量1050 × 单价1 = 1050",
+        ] {
+            assert!(dump.lines().any(|l| l == line), "{}\n{}", line, dump);
+        }
+    }
+
+    #[test]
+    fn meters_under_two_prefixes_are_both_detected() {
+        let options = ReadOptions { electricity_prefixes: vec!["用电".to_string()], ..ReadOptions::default() };
+        let csv = "铺面编号,店铺名称,电表1上期读数,电表1本期读数,用电2上期读数,用电2本期读数,上期水表读数,本期水表读数,水费单价,电费单价\n\
+                   A01,商户甲,100,150,0,20,10,12,3.5,1\n";
+        let bills = read_csv_bytes(csv.as_bytes(), &headers(), &options).unwrap();
+        let usages: Vec<f64> = bills[0].electricity_meters.iter().map(|m| m.usage).collect();
+        assert_eq!(usages, [50.0, 20.0]);
+        assert_eq!(read_csv_bytes(csv.as_bytes(), &headers(), &ReadOptions::default()).unwrap()[0].electricity_meters.len(), 1);
+
+        // 同一编号出现在两个前缀下：按先匹配的一组读取并提示
+        let csv = "铺面编号,店铺名称,电表1上期读数,电表1本期读数,用电1上期读数,用电1本期读数,上期水表读数,本期水表读数,水费单价,电费单价\n\
+                   A01,商户甲,100,150,0,20,10,12,3.5,1\n";
+        let (bills, logs) = captured_logs(|| read_csv_bytes(csv.as_bytes(), &headers(), &options));
+        assert_eq!(bills.unwrap()[0].electricity_usage, 50.0);
+        assert!(logs.iter().any(|l| l.contains("电表1同时出现在「电表1上期读数」、「用电1上期读数」列")), "{:?}", logs);
+    }
+
+    #[test]
+    fn billing_period_line_renders_both_dates() {
+        let options = GenerateOptions {
+            period_start: Some("2025/07/01".to_string()),
+            period_end: Some("2025/07/31".to_string()),
+            ..GenerateOptions::default()
+        };
+        assert!(docx_text(&[sample_bill()], options.clone()).contains("计费周期：2025/07/01 至 2025/07/31"));
+        assert!(!docx_text(&[sample_bill()], GenerateOptions::default()).contains("计费周期"));
+
+        // 表格中的计费周期列优先于选项
+        let csv = format!("{},计费起始日期,计费截止日期\nA01,商户甲,100,150,10,12,3.5,1,2025/08/01,2025/08/31\n", CSV_HEADER.trim());
+        let bills = read_csv_bytes(csv.as_bytes(), &headers(), &ReadOptions::default()).unwrap();
+        assert_eq!(billing_period_text(&bills[0], Some(&options)).as_deref(), Some("2025/08/01 至 2025/08/31"));
+        let md = render_bills_markdown(&bills, Some(options)).unwrap();
+        assert!(md.contains("计费周期：2025/08/01 至 2025/08/31"), "{}", md);
+    }
+
+    #[test]
+    fn single_bill_is_one_page_without_summary() {
+        let bytes = generate_single_bill(&sample_bill(), None).unwrap();
+        let text = docx_rs::read_docx(&bytes).unwrap().json();
+        assert!(text.contains("大写：伍拾柒元整    小写：57.00"), "{}", text);
+        assert!(!text.contains("费用汇总表"));
+
+        let mut archive = zip::ZipArchive::new(Cursor::new(bytes)).unwrap();
+        let mut xml = String::new();
+        archive.by_name("word/document.xml").unwrap().read_to_string(&mut xml).unwrap();
+        assert!(!xml.contains(r#"<w:br w:type="page""#));
+    }
+
+    #[test]
+    fn half_up_ignores_float_noise_just_below_half() {
+        // 25度 × 0.58 = 14.499999999999998（乘法误差），应按 14.5 进为15元
+        assert_ne!(25.0 * 0.58, 14.5);
+        assert_eq!(RoundingMode::HalfUp.round(25.0 * 0.58), 15.0);
+        let bill = MerchantBill::builder("商户甲".to_string(), 0.58, 0.58)
+            .water_readings(0.0, 25.0)
+            .electricity_meter("1".to_string(), 0.0, 25.0)
+            .build();
+        assert_eq!((bill.water_amount, bill.electricity_amount, bill.total_fee), (15.0, 15.0, 30.0));
+    }
+
+    #[test]
+    fn rounding_modes_agree_on_float_noise() {
+        // 25度 × 0.58 = 14.499999999999998，各模式都按 14.5 处理
+        let noisy = 25.0 * 0.58;
+        let modes = [RoundingMode::HalfUp, RoundingMode::HalfEven, RoundingMode::Down, RoundingMode::Up, RoundingMode::TwoDecimals];
+        assert_eq!(modes.map(|m| m.round(noisy)), [15.0, 14.0, 14.0, 15.0, 14.5]);
+
+        // 5吨 × 0.045 = 0.22499999999999998，保留到分时应为 0.23
+        assert_ne!(5.0 * 0.045, 0.225);
+        assert_eq!(RoundingMode::TwoDecimals.round(5.0 * 0.045), 0.23);
+        let bill = MerchantBill::builder("商户甲".to_string(), 0.045, 0.0)
+            .water_readings(0.0, 5.0)
+            .amount_rounding(RoundingMode::TwoDecimals)
+            .build();
+        assert_eq!(bill.water_amount, 0.23);
+    }
+}
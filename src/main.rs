@@ -1,134 +1,15 @@
 use anyhow::{Context, Result};
 use clap::{Parser, Subcommand};
 use std::path::Path;
-use calamine::{open_workbook, DataType, Reader, Xlsx};
-use chrono::{Datelike, Local};
-use std::fs::File;
-use std::io::{BufRead, BufReader};
+use chrono::Local;
+#[cfg(feature = "pdf")]
 use std::process::Command;
 use std::fs;
-
-// 导入模板模块
-mod template_simple;
-
-#[derive(Debug, Clone)]
-pub struct ElectricityMeter {
-    pub meter_id: String,
-    pub prev_reading: f64,
-    pub curr_reading: f64,
-    pub usage: f64,
-    pub amount: f64,
-}
-
-#[derive(Debug, Clone)]
-pub struct MerchantBill {
-    pub merchant_name: String,
-    pub electricity_meters: Vec<ElectricityMeter>,
-    pub prev_water_reading: f64,
-    pub curr_water_reading: f64,
-    pub water_unit_price: f64,
-    pub electricity_unit_price: f64,
-    pub water_usage: f64,
-    pub water_amount: f64,
-    pub electricity_usage: f64,  // 总用电量
-    pub electricity_amount: f64, // 总电费
-    pub water_electricity_labor_fee: f64, // 水电人工费
-    pub garbage_disposal_fee: f64, // 垃圾处理费
-    pub total_fee: f64,
-    pub month: String,
-}
-
-impl MerchantBill {
-    pub fn new(merchant_name: String, water_unit_price: f64, electricity_unit_price: f64) -> Self {
-        Self {
-            merchant_name,
-            electricity_meters: Vec::new(),
-            prev_water_reading: 0.0,
-            curr_water_reading: 0.0,
-            water_unit_price,
-            electricity_unit_price,
-            water_usage: 0.0,
-            water_amount: 0.0,
-            electricity_usage: 0.0,
-            electricity_amount: 0.0,
-            water_electricity_labor_fee: 0.0,
-            garbage_disposal_fee: 0.0,
-            total_fee: 0.0,
-            month: Local::now().format("%Y年%m月").to_string(),
-        }
-    }
-
-    pub fn add_electricity_meter(&mut self, meter_id: String, prev_reading: f64, curr_reading: f64) {
-        let usage = (curr_reading - prev_reading).max(0.0);
-        let amount = usage * self.electricity_unit_price;
-        
-        let meter = ElectricityMeter {
-            meter_id,
-            prev_reading,
-            curr_reading,
-            usage,
-            amount,
-        };
-        
-        self.electricity_meters.push(meter);
-        self.update_totals();
-    }
-
-    pub fn set_water_readings(&mut self, prev_reading: f64, curr_reading: f64) {
-        self.prev_water_reading = prev_reading;
-        self.curr_water_reading = curr_reading;
-        self.water_usage = (curr_reading - prev_reading).max(0.0);
-        self.water_amount = self.water_usage * self.water_unit_price;
-        self.update_totals();
-    }
-
-    pub fn set_additional_fees(&mut self, water_electricity_labor_fee: f64, garbage_disposal_fee: f64) {
-        self.water_electricity_labor_fee = water_electricity_labor_fee;
-        self.garbage_disposal_fee = garbage_disposal_fee;
-        self.update_totals();
-    }
-
-    fn update_totals(&mut self) {
-        self.electricity_usage = self.electricity_meters.iter().map(|m| m.usage).sum();
-        self.electricity_amount = self.electricity_meters.iter().map(|m| m.amount).sum();
-        self.total_fee = self.electricity_amount + self.water_amount + self.water_electricity_labor_fee + self.garbage_disposal_fee;
-    }
-
-    // 获取所有电表的详细信息（用于模板显示）
-    pub fn get_electricity_details(&self) -> Vec<String> {
-        self.electricity_meters.iter().map(|meter| {
-            format!("电表{}: 上期{}度, 本期{}度, 用量{}度, 费用{:.2}元", 
-                meter.meter_id, 
-                meter.prev_reading, 
-                meter.curr_reading, 
-                meter.usage, 
-                meter.amount)
-        }).collect()
-    }
-}
-
-#[derive(Clone)]
-pub struct HeadersMap<'a> {
-    pub merchant: &'a str,
-    pub water_prev: &'a str,
-    pub water_curr: &'a str,
-    pub water_price: &'a str,
-    pub electricity_price: &'a str,
-    // 电表列的前缀，支持多个电表
-    pub electricity_prefix: &'a str,
-}
-
-impl<'a> HeadersMap<'a> {
-    fn to_pairs(&self) -> Vec<(&str, &str)> {
-        vec![
-            (self.merchant, "店铺名称"),
-            (self.water_prev, "上期水表读数"),
-            (self.water_curr, "本期水表读数"),
-            (self.water_price, "水费单价"),
-            (self.electricity_price, "电费单价"),
-        ]
-    }
-}
+use water_and_electricity_meter::{apply_default_fees, billing_period_text, debug_dump, format_price, is_url, month_format, read_data_file_with_options, render_bills_markdown, resolve_date_format, summary_json, GenerateOptions, HeadersMap, MerchantBill, ReadOptions, Totals, PRICE_MAX_DECIMALS};
+#[cfg(feature = "url")]
+use water_and_electricity_meter::fetch_data_url;
+#[cfg(feature = "pdf")]
+use water_and_electricity_meter::{generate_per_merchant_documents, merge_pdfs};
 
 #[derive(Parser)]
 #[command(name = "excel_to_word")]
@@ -136,13 +17,37 @@ impl<'a> HeadersMap<'a> {
 struct Cli {
     #[command(subcommand)]
     command: Commands,
+    /// 标准水电人工费：表格未提供或为0时使用
+    #[arg(long, global = true)]
+    default_labor_fee: Option<f64>,
+    /// 标准垃圾处理费：表格未提供或为0时使用
+    #[arg(long, global = true)]
+    default_garbage_fee: Option<f64>,
+    /// 按商户数拆分输出：每N户生成一个文件（各自附汇总表），命名为 output_part1.docx 等；0表示不拆分
+    #[arg(long, global = true)]
+    split_every: Option<usize>,
+    /// 调试：将每行的原始单元格、实际读取的数值与计算结果写入该文本文件，用于排查金额不对的原因
+    #[arg(long, global = true, value_name = "FILE")]
+    debug_dump: Option<String>,
+    /// 输出文件已存在时覆盖（默认拒绝覆盖）
+    #[arg(long, global = true)]
+    force: bool,
+    /// 计费周期起始日期，如 2025/07/01；与截止日期一起显示为"计费周期：X 至 Y"
+    #[arg(long, global = true)]
+    period_start: Option<String>,
+    /// 计费周期截止日期，如 2025/07/31
+    #[arg(long, global = true)]
+    period_end: Option<String>,
+    /// 日期格式：cn（默认，2025年08月16日）、iso、slash 或 chrono 格式串如 %Y/%m/%d；用于账单期间与生成时间
+    #[arg(long, global = true)]
+    date_format: Option<String>,
 }
 
 #[derive(Subcommand)]
 enum Commands {
     /// 使用配置文件生成Word文档
     Config {
-        /// 输入文件路径
+        /// 输入文件路径或 http(s) 地址
         #[arg(short, long)]
         input: String,
         /// 输出文件路径
@@ -154,7 +59,7 @@ enum Commands {
     },
     /// 使用默认配置生成Word文档
     Default {
-        /// 输入文件路径
+        /// 输入文件路径或 http(s) 地址
         #[arg(short, long)]
         input: String,
         /// 输出文件路径
@@ -163,7 +68,35 @@ enum Commands {
     },
     /// 使用传统方式生成Word文档
     Legacy {
-        /// 输入文件路径
+        /// 输入文件路径或 http(s) 地址
+        #[arg(short, long)]
+        input: String,
+        /// 输出文件路径
+        #[arg(short, long)]
+        output: String,
+    },
+    /// 每个商家单独生成通知单，逐份转换为PDF后合并为一个PDF（便于一次打印）
+    #[cfg(feature = "pdf")]
+    CombinedPdf {
+        /// 输入文件路径或 http(s) 地址
+        #[arg(short, long)]
+        input: String,
+        /// 输出PDF路径
+        #[arg(short, long)]
+        output: String,
+    },
+    /// 导出为Markdown（便于粘贴到Wiki或邮件）
+    ExportMd {
+        /// 输入文件路径或 http(s) 地址
+        #[arg(short, long)]
+        input: String,
+        /// 输出文件路径
+        #[arg(short, long)]
+        output: String,
+    },
+    /// 仅导出汇总数据JSON（各类合计与总计，供财务系统使用）
+    SummaryJson {
+        /// 输入文件路径或 http(s) 地址
         #[arg(short, long)]
         input: String,
         /// 输出文件路径
@@ -174,42 +107,92 @@ enum Commands {
 
 fn main() -> Result<()> {
     let cli = Cli::parse();
+    let fee_options = GenerateOptions {
+        default_labor_fee: cli.default_labor_fee,
+        default_garbage_fee: cli.default_garbage_fee,
+        date_format: cli.date_format.clone(),
+        ..GenerateOptions::default()
+    };
+    let date_format = resolve_date_format(cli.date_format.as_deref())?;
+    let read_bills = |input: &str| -> Result<Vec<MerchantBill>> {
+        let mut bills = read_data_file_with_options(input, &get_default_headers(), &ReadOptions::default())?;
+        apply_default_fees(&mut bills, &fee_options);
+        for bill in &mut bills {
+            bill.period_start = bill.period_start.take().or_else(|| cli.period_start.clone());
+            bill.period_end = bill.period_end.take().or_else(|| cli.period_end.clone());
+        }
+        if let Some(dump_path) = &cli.debug_dump {
+            write_debug_dump(input, &bills, dump_path, cli.force)?;
+        }
+        Ok(bills)
+    };
 
     match &cli.command {
         Commands::Config { input, output, config } => {
             println!("使用配置文件生成Word文档...");
-            let bills = read_data_file(input, &get_default_headers())?;
-            let docx_content = generate_word_document_with_template(&bills, Some(config))?;
-            write_docx_or_pdf(output, docx_content)?;
+            let bills = read_bills(input)?;
+            write_split(output, &bills, cli.split_every, cli.force, |part| generate_word_document_with_template(part, Some(config), &date_format))?;
         }
         Commands::Default { input, output } => {
             println!("使用默认配置生成Word文档...");
-            let bills = read_data_file(input, &get_default_headers())?;
-            let docx_content = generate_word_document_with_template(&bills, None)?;
-            write_docx_or_pdf(output, docx_content)?;
+            let bills = read_bills(input)?;
+            write_split(output, &bills, cli.split_every, cli.force, |part| generate_word_document_with_template(part, None, &date_format))?;
         }
         Commands::Legacy { input, output } => {
             println!("使用传统方式生成Word文档...");
-            let bills = read_data_file(input, &get_default_headers())?;
-            let docx_content = generate_word_document_with_template(&bills, None)?;
-            write_docx_or_pdf(output, docx_content)?;
+            let bills = read_bills(input)?;
+            write_split(output, &bills, cli.split_every, cli.force, |part| generate_word_document_with_template(part, None, &date_format))?;
+        }
+        #[cfg(feature = "pdf")]
+        Commands::CombinedPdf { input, output } => {
+            println!("逐户生成并合并PDF...");
+            let bills = read_bills(input)?;
+            write_combined_pdf(output, &bills, fee_options.clone(), cli.force)?;
+        }
+        Commands::ExportMd { input, output } => {
+            println!("导出Markdown...");
+            let bills = read_bills(input)?;
+            let markdown = render_bills_markdown(&bills, None)?;
+            check_overwrite(Path::new(output), cli.force)?;
+            fs::write(output, markdown)?;
+            println!("✅ Markdown生成成功: {}", output);
+        }
+        Commands::SummaryJson { input, output } => {
+            println!("导出汇总JSON...");
+            let bills = read_bills(input)?;
+            check_overwrite(Path::new(output), cli.force)?;
+            fs::write(output, serde_json::to_string_pretty(&summary_json(&bills))?)?;
+            println!("✅ 汇总JSON生成成功: {}", output);
         }
     }
 
     Ok(())
 }
 
-fn write_docx_or_pdf(output: &str, docx_bytes: Vec<u8>) -> Result<()> {
+// 输出文件已存在时默认拒绝覆盖，避免重新生成时误覆盖之前的文件
+fn check_overwrite(path: &Path, force: bool) -> Result<()> {
+    if !force && path.exists() {
+        anyhow::bail!("文件已存在，使用 --force 覆盖: {}", path.display());
+    }
+    Ok(())
+}
+
+fn write_docx_or_pdf(output: &str, docx_bytes: Vec<u8>, force: bool) -> Result<()> {
     let out_path = Path::new(output);
+    check_overwrite(out_path, force)?;
     let ext = out_path.extension().and_then(|e| e.to_str()).unwrap_or("").to_lowercase();
     if ext == "pdf" {
-        // 写入临时 DOCX，然后转换为 PDF
-        let tmp_docx_path = out_path.with_extension("docx");
-        fs::write(&tmp_docx_path, &docx_bytes)?;
-        convert_docx_to_pdf(&tmp_docx_path, out_path)?;
-        // 转换完成后删除临时 DOCX（忽略错误）
-        let _ = fs::remove_file(&tmp_docx_path);
-        println!("✅ PDF 生成成功: {}", out_path.display());
+        #[cfg(not(feature = "pdf"))]
+        anyhow::bail!("未启用 pdf 功能，无法输出PDF: {}", out_path.display());
+        // 在临时目录写入同名 DOCX 再转换为 PDF，不会覆盖输出目录中已有的同名 DOCX
+        #[cfg(feature = "pdf")]
+        {
+            let dir = tempfile::tempdir().context("创建临时目录失败")?;
+            let tmp_docx_path = dir.path().join(out_path.file_stem().unwrap_or_default()).with_extension("docx");
+            fs::write(&tmp_docx_path, &docx_bytes)?;
+            convert_docx_to_pdf(&tmp_docx_path, out_path)?;
+            println!("✅ PDF 生成成功: {}", out_path.display());
+        }
     } else {
         fs::write(out_path, &docx_bytes)?;
         println!("✅ Word文档生成成功: {}", out_path.display());
@@ -217,6 +200,68 @@ fn write_docx_or_pdf(output: &str, docx_bytes: Vec<u8>) -> Result<()> {
     Ok(())
 }
 
+// 未指定 split_every 时整批写入 output；否则每N户生成一份文档，依次写入 output_part1.docx、output_part2.docx ...
+fn write_split(output: &str, bills: &[MerchantBill], split_every: Option<usize>, force: bool, generate: impl Fn(&[MerchantBill]) -> Result<Vec<u8>>) -> Result<()> {
+    let Some(per_file) = split_every.filter(|&n| n > 0 && bills.len() > n) else {
+        check_overwrite(Path::new(output), force)?;
+        return write_docx_or_pdf(output, generate(bills)?, force);
+    };
+    let out_path = Path::new(output);
+    let stem = out_path.file_stem().and_then(|s| s.to_str()).unwrap_or("output");
+    let ext = out_path.extension().and_then(|e| e.to_str()).unwrap_or("docx");
+    let parts: Vec<&[MerchantBill]> = bills.chunks(per_file).collect();
+    let part_paths: Vec<_> = (1..=parts.len()).map(|n| out_path.with_file_name(format!("{}_part{}.{}", stem, n, ext))).collect();
+    // 先检查全部分卷，避免写到一半才发现某个文件已存在
+    for part_path in &part_paths {
+        check_overwrite(part_path, force)?;
+    }
+    for (part, part_path) in parts.iter().zip(&part_paths) {
+        write_docx_or_pdf(&part_path.to_string_lossy(), generate(part)?, force)?;
+    }
+    println!("✅ 共{}户，按每{}户拆分为{}个文件", bills.len(), per_file, parts.len());
+    Ok(())
+}
+
+fn write_debug_dump(input: &str, bills: &[MerchantBill], output: &str, force: bool) -> Result<()> {
+    check_overwrite(Path::new(output), force)?;
+    let (bytes, format) = if is_url(input) {
+        #[cfg(not(feature = "url"))]
+        anyhow::bail!("未启用 url 功能，无法读取网络地址: {}", input);
+        #[cfg(feature = "url")]
+        {
+            let (bytes, format) = fetch_data_url(input)?;
+            (bytes, format.to_string())
+        }
+    } else {
+        let format = Path::new(input).extension().and_then(|e| e.to_str()).unwrap_or("").to_lowercase();
+        (fs::read(input).with_context(|| format!("读取输入文件失败: {}", input))?, format)
+    };
+    let dump = debug_dump(&bytes, &format, get_default_headers().electricity_prefix, bills)?;
+    fs::write(output, dump)?;
+    println!("✅ 调试输出已写入: {}", output);
+    Ok(())
+}
+
+#[cfg(feature = "pdf")]
+fn write_combined_pdf(output: &str, bills: &[MerchantBill], options: GenerateOptions, force: bool) -> Result<()> {
+    check_overwrite(Path::new(output), force)?;
+    let docs = generate_per_merchant_documents(bills, Some(options))?;
+    let dir = tempfile::tempdir().context("创建临时目录失败")?;
+    let mut pdfs = Vec::with_capacity(docs.len());
+    for (idx, (_, docx_bytes)) in docs.iter().enumerate() {
+        // 临时文件名只用序号，避免商家名称中的特殊字符影响转换工具
+        let docx_path = dir.path().join(format!("bill_{:03}.docx", idx + 1));
+        let pdf_path = docx_path.with_extension("pdf");
+        fs::write(&docx_path, docx_bytes)?;
+        convert_docx_to_pdf(&docx_path, &pdf_path)?;
+        pdfs.push(fs::read(&pdf_path).with_context(|| format!("读取生成的PDF失败: {}", pdf_path.display()))?);
+    }
+    fs::write(output, merge_pdfs(&pdfs)?)?;
+    println!("✅ 合并PDF生成成功: {}（共{}户）", output, pdfs.len());
+    Ok(())
+}
+
+#[cfg(feature = "pdf")]
 fn convert_docx_to_pdf(docx_path: &Path, pdf_path: &Path) -> Result<()> {
     // 优先尝试 LibreOffice 系列（soffice/libreoffice/lowriter）
     let tools = ["soffice", "libreoffice", "lowriter"];
@@ -266,210 +311,23 @@ fn convert_docx_to_pdf(docx_path: &Path, pdf_path: &Path) -> Result<()> {
 fn get_default_headers() -> HeadersMap<'static> {
     HeadersMap {
         merchant: "店铺名称",
-        water_prev: "上期水表读数",
-        water_curr: "本期水表读数",
-        water_price: "水费单价",
+        prev_e: "",
+        curr_e: "",
+        prev_w: "上期水表读数",
+        curr_w: "本期水表读数",
+        w_price: "水费单价",
+        e_price: "电费单价",
         electricity_price: "电费单价",
         electricity_prefix: "电表",
-    }
-}
-
-// 辅助函数
-fn normalize(s: &str) -> String { s.trim().to_lowercase() }
-
-fn find_indices(headers: &[String], mapping: &[(&str, &str)]) -> Result<Vec<usize>> {
-    let headers_norm: Vec<String> = headers.iter().map(|h| normalize(h)).collect();
-    let mut indices = Vec::with_capacity(mapping.len());
-
-    for (want, fallback) in mapping.iter() {
-        let candidates = vec![normalize(want), normalize(fallback)];
-        let mut found = headers_norm.iter().position(|h| candidates.iter().any(|c| c == h));
-        if found.is_none() {
-            found = headers_norm.iter().position(|h| candidates.iter().any(|c| h.contains(c)));
-        }
-        let idx = found.with_context(|| format!("无法在表头中找到列: {}", want))?;
-        indices.push(idx);
-    }
-    Ok(indices)
-}
-
-fn as_f64(cell: &DataType) -> f64 {
-    match cell {
-        DataType::Float(f) => *f,
-        DataType::Int(i) => *i as f64,
-        DataType::String(s) => s.trim().parse::<f64>().unwrap_or(0.0),
-        _ => 0.0,
-    }
-}
-
-fn read_excel_file(file_path: &str, headers_map: &HeadersMap) -> Result<Vec<MerchantBill>> {
-    let mut workbook: Xlsx<_> = open_workbook(file_path)
-        .with_context(|| format!("无法打开Excel文件: {}", file_path))?;
-    let sheet_name = workbook.sheet_names()[0].clone();
-    let range = workbook
-        .worksheet_range(&sheet_name)
-        .with_context(|| format!("无法读取工作表: {}", sheet_name))??;
-
-    let mut rows = range.rows();
-    let header_row = rows.next().context("Excel中缺少表头行")?;
-    let headers: Vec<String> = header_row.iter().map(|c| c.to_string()).collect();
-    
-    println!("调试：Excel表头: {:?}", headers);
-    
-    // 直接查找列索引，不使用find_indices
-    let m_i = headers.iter().position(|h| h.contains("店铺名称")).context("找不到店铺名称列")?;
-    let wp_i = headers.iter().position(|h| h.contains("上期水表读数")).context("找不到上期水表读数列")?;
-    let wc_i = headers.iter().position(|h| h.contains("本期水表读数")).context("找不到本期水表读数列")?;
-    let wprice_i = headers.iter().position(|h| h.contains("水费单价")).context("找不到水费单价列")?;
-    let eprice_i = headers.iter().position(|h| h.contains("电费单价")).context("找不到电费单价列")?;
-
-    // 找到所有电表相关的列
-    let electricity_columns = find_electricity_columns(&headers, headers_map.electricity_prefix)?;
-
-    println!("调试：Excel基础列索引 - 商家:{}, 水表上期:{}, 水表本期:{}, 水费单价:{}, 电费单价:{}", 
-             m_i, wp_i, wc_i, wprice_i, eprice_i);
-    println!("调试：Excel电表列: {:?}", electricity_columns);
-
-    let mut bills = Vec::new();
-    for row in rows {
-        if row.is_empty() { continue; }
-        let merchant_name = row.get(m_i).map(|c| c.to_string()).unwrap_or_default();
-        if merchant_name.trim().is_empty() { continue; }
-        
-        let water_price = row.get(wprice_i).map(as_f64).unwrap_or(0.0);
-        let electricity_price = row.get(eprice_i).map(as_f64).unwrap_or(0.0);
-        let prev_water = row.get(wp_i).map(as_f64).unwrap_or(0.0);
-        let curr_water = row.get(wc_i).map(as_f64).unwrap_or(0.0);
-
-        let mut bill = MerchantBill::new(merchant_name, water_price, electricity_price);
-        bill.set_water_readings(prev_water, curr_water);
-
-        // 处理每个电表
-        for (meter_id, (prev_col, curr_col)) in electricity_columns.iter().enumerate() {
-            let prev_reading = row.get(*prev_col).map(as_f64).unwrap_or(0.0);
-            let curr_reading = row.get(*curr_col).map(as_f64).unwrap_or(0.0);
-            if prev_reading > 0.0 || curr_reading > 0.0 {
-                bill.add_electricity_meter(format!("{}", meter_id + 1), prev_reading, curr_reading);
-            }
-        }
-
-        // 设置人工费和垃圾处理费（这里使用固定值作为示例，实际应该从数据中读取）
-        let labor_fee = 50.0; // 水电人工费
-        let garbage_fee = 30.0; // 垃圾处理费
-        bill.set_additional_fees(labor_fee, garbage_fee);
-
-        bills.push(bill);
-    }
-    Ok(bills)
-}
-
-fn read_csv_file(file_path: &str, headers_map: &HeadersMap) -> Result<Vec<MerchantBill>> {
-    let file = File::open(file_path)
-        .with_context(|| format!("无法打开CSV文件: {}", file_path))?;
-    let mut lines = BufReader::new(file).lines();
-    let header_line = lines.next().transpose()?.context("CSV中缺少表头行")?;
-    let headers: Vec<String> = header_line.split(',').map(|s| s.trim().to_string()).collect();
-
-    println!("调试：找到的表头: {:?}", headers);
-
-    // 直接查找列索引，不使用find_indices
-    let m_i = headers.iter().position(|h| h.contains("店铺名称")).context("找不到店铺名称列")?;
-    let wp_i = headers.iter().position(|h| h.contains("上期水表读数")).context("找不到上期水表读数列")?;
-    let wc_i = headers.iter().position(|h| h.contains("本期水表读数")).context("找不到本期水表读数列")?;
-    let wprice_i = headers.iter().position(|h| h.contains("水费单价")).context("找不到水费单价列")?;
-    let eprice_i = headers.iter().position(|h| h.contains("电费单价")).context("找不到电费单价列")?;
-
-    // 找到所有电表相关的列
-    let electricity_columns = find_electricity_columns(&headers, headers_map.electricity_prefix)?;
-
-    println!("调试：基础列索引 - 商家:{}, 水表上期:{}, 水表本期:{}, 水费单价:{}, 电费单价:{}", 
-             m_i, wp_i, wc_i, wprice_i, eprice_i);
-    println!("调试：电表列: {:?}", electricity_columns);
-
-    let mut bills = Vec::new();
-    for line in lines {
-        let line = line?;
-        if line.trim().is_empty() { continue; }
-        let parts: Vec<&str> = line.split(',').collect();
-        if parts.len() < 5 { continue; } // 确保至少有基础列
-        
-        let get = |i: usize| -> &str { parts.get(i).copied().unwrap_or("") };
-        
-        let merchant_name = get(m_i).trim().to_string();
-        if merchant_name.is_empty() { continue; }
-        
-        let water_price = get(wprice_i).trim().parse::<f64>().unwrap_or(0.0);
-        let electricity_price = get(eprice_i).trim().parse::<f64>().unwrap_or(0.0);
-        let prev_water = get(wp_i).trim().parse::<f64>().unwrap_or(0.0);
-        let curr_water = get(wc_i).trim().parse::<f64>().unwrap_or(0.0);
-
-        let mut bill = MerchantBill::new(merchant_name, water_price, electricity_price);
-        bill.set_water_readings(prev_water, curr_water);
-
-        // 处理每个电表
-        for (meter_id, (prev_col, curr_col)) in electricity_columns.iter().enumerate() {
-            let prev_reading = get(*prev_col).trim().parse::<f64>().unwrap_or(0.0);
-            let curr_reading = get(*curr_col).trim().parse::<f64>().unwrap_or(0.0);
-            if prev_reading > 0.0 || curr_reading > 0.0 {
-                bill.add_electricity_meter(format!("{}", meter_id + 1), prev_reading, curr_reading);
-            }
-        }
-
-        // 设置人工费和垃圾处理费（这里使用固定值作为示例，实际应该从数据中读取）
-        let labor_fee = 50.0; // 水电人工费
-        let garbage_fee = 30.0; // 垃圾处理费
-        bill.set_additional_fees(labor_fee, garbage_fee);
-
-        bills.push(bill);
-    }
-    Ok(bills)
-}
-
-fn find_electricity_columns(headers: &[String], prefix: &str) -> Result<Vec<(usize, usize)>> {
-    let mut columns = Vec::new();
-    let headers_norm: Vec<String> = headers.iter().map(|h| normalize(h)).collect();
-    
-    // 查找电表列的模式：电表1上期读数、电表1本期读数、电表2上期读数、电表2本期读数...
-    let mut meter_id = 1;
-    loop {
-        let prev_pattern = format!("{}{}上期读数", prefix, meter_id);
-        let curr_pattern = format!("{}{}本期读数", prefix, meter_id);
-        
-        let prev_idx = headers_norm.iter().position(|h| h.contains(&normalize(&prev_pattern)));
-        let curr_idx = headers_norm.iter().position(|h| h.contains(&normalize(&curr_pattern)));
-        
-        if prev_idx.is_some() && curr_idx.is_some() {
-            columns.push((prev_idx.unwrap(), curr_idx.unwrap()));
-            meter_id += 1;
-        } else {
-            break;
-        }
-    }
-    
-    if columns.is_empty() {
-        anyhow::bail!("未找到任何电表列，请确保CSV包含'电表X上期读数'和'电表X本期读数'列");
-    }
-    
-    Ok(columns)
-}
-
-fn read_data_file(file_path: &str, headers_map: &HeadersMap) -> Result<Vec<MerchantBill>> {
-    let path = Path::new(file_path);
-    let extension = path.extension().and_then(|e| e.to_str()).unwrap_or("").to_lowercase();
-    match extension.as_str() {
-        "xlsx" => read_excel_file(file_path, headers_map),
-        "csv" => read_csv_file(file_path, headers_map),
-        _ => {
-            if file_path.ends_with(".xlsx") { read_excel_file(file_path, headers_map) }
-            else if file_path.ends_with(".csv") { read_csv_file(file_path, headers_map) }
-            else { anyhow::bail!("不支持的文件格式: {}", extension) }
-        }
+        water_electricity_labor_fee: "水电人工费",
+        garbage_disposal_fee: "垃圾处理费",
     }
 }
 
 fn generate_word_document_with_template(
     merchants: &[MerchantBill],
-    config_path: Option<&str>,
+    _config_path: Option<&str>,
+    date_format: &str,
 ) -> Result<Vec<u8>, anyhow::Error> {
     // 简单的模板生成，直接使用docx-rs
     use docx_rs::*;
@@ -484,7 +342,7 @@ fn generate_word_document_with_template(
     );
 
     // 为每个商家生成账单
-    for (index, bill) in merchants.iter().enumerate() {
+    for bill in merchants.iter() {
         // 商家名称
         doc = doc.add_paragraph(
             Paragraph::new()
@@ -495,8 +353,14 @@ fn generate_word_document_with_template(
         let now = Local::now();
         doc = doc.add_paragraph(
             Paragraph::new()
-                .add_run(Run::new().add_text(format!("账单期间：{}年{}月", now.year(), now.month())).size(14))
+                .add_run(Run::new().add_text(format!("账单期间：{}", now.format(&month_format(date_format)))).size(14))
         );
+        if let Some(period) = billing_period_text(bill, None) {
+            doc = doc.add_paragraph(
+                Paragraph::new()
+                    .add_run(Run::new().add_text(format!("计费周期：{}", period)).size(14))
+            );
+        }
 
         // 水表读数
         doc = doc.add_paragraph(
@@ -557,11 +421,11 @@ fn generate_word_document_with_template(
         );
         doc = doc.add_paragraph(
             Paragraph::new()
-                .add_run(Run::new().add_text(format!("电费单价：{:.2} 元/度", bill.electricity_unit_price)).size(14))
+                .add_run(Run::new().add_text(format!("电费单价：{} 元/度", format_price(bill.electricity_unit_price, 2, PRICE_MAX_DECIMALS))).size(14))
         );
         doc = doc.add_paragraph(
             Paragraph::new()
-                .add_run(Run::new().add_text(format!("水费单价：{:.2} 元/吨", bill.water_unit_price)).size(14))
+                .add_run(Run::new().add_text(format!("水费单价：{} 元/吨", format_price(bill.water_unit_price, 2, PRICE_MAX_DECIMALS))).size(14))
         );
         doc = doc.add_paragraph(
             Paragraph::new()
@@ -581,7 +445,7 @@ fn generate_word_document_with_template(
         // 生成时间
         doc = doc.add_paragraph(
             Paragraph::new()
-                .add_run(Run::new().add_text(format!("生成时间：{}", Local::now().format("%Y-%m-%d %H:%M:%S"))).size(10))
+                .add_run(Run::new().add_text(format!("生成时间：{}", Local::now().format(&format!("{} %H:%M:%S", date_format)))).size(10))
                 .align(AlignmentType::Right)
         );
 
@@ -631,19 +495,81 @@ fn add_summary_table(mut doc: docx_rs::Docx, merchants: &[MerchantBill]) -> Resu
     }
 
     // 添加合计行
-    let total_water_electricity: f64 = merchants.iter().map(|b| b.water_amount + b.electricity_amount).sum();
-    let total_labor_fee: f64 = merchants.iter().map(|b| b.water_electricity_labor_fee).sum();
-    let total_garbage_fee: f64 = merchants.iter().map(|b| b.garbage_disposal_fee).sum();
-    let grand_total: f64 = merchants.iter().map(|b| b.total_fee).sum();
+    let totals = Totals::from_merchants(merchants);
 
     table = table.add_row(TableRow::new(vec![
         TableCell::new().add_paragraph(Paragraph::new().add_run(Run::new().add_text("合计").bold())),
-        TableCell::new().add_paragraph(Paragraph::new().add_run(Run::new().add_text(format!("{:.2}", total_water_electricity)).bold())),
-        TableCell::new().add_paragraph(Paragraph::new().add_run(Run::new().add_text(format!("{:.2}", total_labor_fee)).bold())),
-        TableCell::new().add_paragraph(Paragraph::new().add_run(Run::new().add_text(format!("{:.2}", total_garbage_fee)).bold())),
-        TableCell::new().add_paragraph(Paragraph::new().add_run(Run::new().add_text(format!("{:.2}", grand_total)).bold())),
+        TableCell::new().add_paragraph(Paragraph::new().add_run(Run::new().add_text(format!("{:.2}", totals.utility_amount())).bold())),
+        TableCell::new().add_paragraph(Paragraph::new().add_run(Run::new().add_text(format!("{:.2}", totals.labor_fee)).bold())),
+        TableCell::new().add_paragraph(Paragraph::new().add_run(Run::new().add_text(format!("{:.2}", totals.garbage_fee)).bold())),
+        TableCell::new().add_paragraph(Paragraph::new().add_run(Run::new().add_text(format!("{:.2}", totals.grand_total)).bold())),
     ]));
 
     doc = doc.add_table(table);
     Ok(doc)
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn merchants(count: usize) -> Vec<MerchantBill> {
+        (1..=count).map(|i| MerchantBill::new(format!("商户{}", i), 3.5, 1.0)).collect()
+    }
+
+    // 以每份的商户数代替文档内容，便于检查拆分结果
+    fn merchant_count(part: &[MerchantBill]) -> Result<Vec<u8>> {
+        Ok(part.len().to_string().into_bytes())
+    }
+
+    #[test]
+    fn split_every_writes_one_file_per_chunk() {
+        let dir = tempfile::tempdir().unwrap();
+        let output = dir.path().join("output.docx");
+        write_split(&output.to_string_lossy(), &merchants(250), Some(100), false, merchant_count).unwrap();
+
+        let counts: Vec<String> = (1..=3)
+            .map(|n| fs::read_to_string(dir.path().join(format!("output_part{}.docx", n))).unwrap())
+            .collect();
+        assert_eq!(counts, ["100", "100", "50"]);
+        assert!(!output.exists());
+        assert!(!dir.path().join("output_part4.docx").exists());
+    }
+
+    #[test]
+    fn existing_output_requires_force() {
+        let dir = tempfile::tempdir().unwrap();
+        let output = dir.path().join("output.docx");
+        fs::write(&output, "旧文件").unwrap();
+        let output_str = output.to_string_lossy();
+
+        let err = write_split(&output_str, &merchants(2), None, false, merchant_count).unwrap_err();
+        assert_eq!(err.to_string(), format!("文件已存在，使用 --force 覆盖: {}", output.display()));
+        assert_eq!(fs::read_to_string(&output).unwrap(), "旧文件");
+
+        write_split(&output_str, &merchants(2), None, true, merchant_count).unwrap();
+        assert_eq!(fs::read_to_string(&output).unwrap(), "2");
+    }
+
+    #[test]
+    fn split_checks_every_part_before_writing() {
+        let dir = tempfile::tempdir().unwrap();
+        let output = dir.path().join("output.docx");
+        fs::write(dir.path().join("output_part2.docx"), "旧文件").unwrap();
+
+        assert!(write_split(&output.to_string_lossy(), &merchants(3), Some(2), false, merchant_count).is_err());
+        assert!(!dir.path().join("output_part1.docx").exists());
+    }
+
+    #[test]
+    fn date_format_applies_to_period_and_timestamp() {
+        let now = Local::now();
+        let bytes = generate_word_document_with_template(&merchants(1), None, "%Y/%m/%d").unwrap();
+        let text = docx_rs::read_docx(&bytes).unwrap().json();
+        assert!(text.contains(&format!("账单期间：{}", now.format("%Y/%m"))), "{}", text);
+        assert!(text.contains(&format!("生成时间：{} ", now.format("%Y/%m/%d"))), "{}", text);
+
+        let bytes = generate_word_document_with_template(&merchants(1), None, &resolve_date_format(None).unwrap()).unwrap();
+        let text = docx_rs::read_docx(&bytes).unwrap().json();
+        assert!(text.contains(&format!("账单期间：{}", now.format("%Y年%m月"))), "{}", text);
+    }
+}
@@ -1,17 +1,96 @@
-use std::{io::Write, fs::{self, File}, path::PathBuf};
+use std::{io::Write, fs::{self, File}, net::{IpAddr, SocketAddr}, path::PathBuf};
+use std::collections::HashMap;
+use std::hash::{DefaultHasher, Hash, Hasher};
 use std::process::Command;
-use axum::{response::{Html, IntoResponse}, routing::{get, post}, Router, extract::Multipart};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use axum::{response::{Html, IntoResponse, Response}, routing::{get, post}, Router, extract::{ConnectInfo, Multipart, Path, Query, Request, State}, http::{HeaderMap, HeaderValue, StatusCode}, middleware::{self, Next}, Json};
 use anyhow::Result;
 use tempfile::tempdir;
+use tokio::sync::{watch, Semaphore};
+use uuid::Uuid;
 
 // 导入库crate（同包名）的导出项
-use water_and_electricity_meter::{HeadersMap, read_data_file, generate_word_document_with_template, GenerateOptions};
+use water_and_electricity_meter::{COLUMN_SCHEMA, HeadersMap, MerchantBill, ReadOptions, fetch_data_url, read_data_bytes, summary_json, read_data_file, generate_word_document_with_template, generate_single_bill, inspect_data_bytes, resolve_building_name, with_log_sink, GenerateOptions, DEFAULT_MAX_MERCHANTS};
+use water_and_electricity_meter::template_simple::TemplateConfig;
+
+tokio::task_local! {
+    // 当前请求的关联ID，由 request_id 中间件设置
+    static REQUEST_ID: String;
+}
+
+// 请求期间的日志统一带上请求ID，便于排查线上上传问题
+macro_rules! log {
+    ($($arg:tt)*) => {
+        write_log(format!("[{}] {}", current_request_id(), format!($($arg)*)))
+    };
+}
+
+fn write_log(line: String) {
+    #[cfg(test)]
+    tests::CAPTURED_LOGS.lock().unwrap().push(line.clone());
+    println!("{}", line);
+}
+
+fn current_request_id() -> String {
+    REQUEST_ID.try_with(|id| id.clone()).unwrap_or_else(|_| "-".to_string())
+}
+
+// 在阻塞线程中执行读取、生成等同步工作：task_local 不会带入 spawn_blocking，这里显式传入请求ID，
+// 库输出的警告、提示也经 log! 带上该ID
+async fn run_blocking<T: Send + 'static>(f: impl FnOnce() -> T + Send + 'static) -> anyhow::Result<T> {
+    let id = current_request_id();
+    tokio::task::spawn_blocking(move || REQUEST_ID.sync_scope(id, || with_log_sink(|line| log!("{}", line), f)))
+        .await
+        .map_err(|e| anyhow::anyhow!("任务执行失败: {}", e))
+}
+
+// 为每个请求分配 uuid，作用于该请求的所有日志，并通过 X-Request-Id 响应头返回
+async fn request_id(req: Request, next: Next) -> Response {
+    let id = Uuid::new_v4().to_string();
+    let method = req.method().clone();
+    let uri = req.uri().clone();
+    let started = Instant::now();
+    REQUEST_ID
+        .scope(id.clone(), async move {
+            log!("{} {}", method, uri);
+            let mut response = next.run(req).await;
+            log!("{} {} -> {} ({}ms)", method, uri, response.status().as_u16(), started.elapsed().as_millis());
+            if let Ok(value) = HeaderValue::from_str(&id) {
+                response.headers_mut().insert("X-Request-Id", value);
+            }
+            response
+        })
+        .await
+}
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    let app = Router::new()
-        .route("/", get(index))
-        .route("/upload", post(upload));
+    // 同时进行的生成任务上限，可通过环境变量 MAX_CONCURRENT_JOBS 调整
+    let max_jobs = std::env::var("MAX_CONCURRENT_JOBS")
+        .ok()
+        .and_then(|v| v.trim().parse::<usize>().ok())
+        .unwrap_or(DEFAULT_MAX_CONCURRENT_JOBS)
+        .max(1);
+    // 每个客户端IP每分钟的请求上限（上传与API接口），可通过环境变量 RATE_LIMIT_PER_MINUTE 调整，0 表示不限制
+    let rate_limit = std::env::var("RATE_LIMIT_PER_MINUTE")
+        .ok()
+        .and_then(|v| v.trim().parse::<u32>().ok())
+        .unwrap_or(DEFAULT_RATE_LIMIT_PER_MINUTE);
+    // 单次生成的商户数上限，可通过环境变量 MAX_MERCHANTS 调整
+    let max_merchants = std::env::var("MAX_MERCHANTS")
+        .ok()
+        .and_then(|v| v.trim().parse::<usize>().ok())
+        .unwrap_or(DEFAULT_MAX_MERCHANTS);
+    // 部署在反向代理之后时设置 TRUST_FORWARDED_FOR=1，按 X-Forwarded-For 中的客户端IP限流
+    let trust_forwarded_for = std::env::var("TRUST_FORWARDED_FOR").is_ok_and(|v| v.trim() == "1");
+    let state = AppState {
+        jobs: JobStore::new(JOB_TTL),
+        idempotency: IdempotencyCache::default(),
+        generation_slots: Arc::new(Semaphore::new(max_jobs)),
+        rate_limiter: RateLimiter::new(rate_limit, trust_forwarded_for),
+        max_merchants,
+    };
 
     let port = std::env::var("PORT").unwrap_or_else(|_| "3002".to_string());
     let addr = format!("0.0.0.0:{}", port);
@@ -23,73 +102,218 @@ async fn main() -> Result<()> {
     let listener = tokio::net::TcpListener::bind(&addr).await.unwrap();
     println!("✅ 服务启动成功！");
     
-    axum::serve(listener, app).await?;
+    axum::serve(listener, app(state).into_make_service_with_connect_info::<SocketAddr>()).await?;
     Ok(())
 }
 
+fn app(state: AppState) -> Router {
+    // 上传与API接口按客户端IP限流；首页与任务状态/结果查询（轮询用）不限流
+    let limited = Router::new()
+        .route("/upload", post(upload))
+        .route("/api/validate-config", post(validate_config))
+        .route("/api/inspect", post(inspect))
+        .route("/api/schema", get(schema))
+        .route("/api/quick", get(quick))
+        .route("/api/summary", get(summary_from_url).post(summary_from_upload))
+        .route("/api/generate-from-json", post(generate_from_json))
+        .route_layer(middleware::from_fn_with_state(state.clone(), rate_limit_by_ip));
+    Router::new()
+        .route("/", get(index))
+        .merge(limited)
+        .route("/api/jobs/:id", get(job_status))
+        .route("/api/jobs/:id/result", get(job_result))
+        .layer(middleware::from_fn(request_id))
+        .with_state(state)
+}
+
 async fn index() -> impl IntoResponse {
-    Html(r#"<!doctype html>
+    Html(render_index(&FormValues::default(), None))
+}
+
+// 表单已填写的值，上传失败时用于回填
+struct FormValues {
+    building_name: String,
+    custom_title: String,
+    per_page: String,
+    meter_reader: String,
+    meter_date: String,
+    as_pdf: bool,
+}
+
+impl Default for FormValues {
+    fn default() -> Self {
+        Self {
+            building_name: String::new(),
+            custom_title: String::new(),
+            per_page: "3".to_string(),
+            meter_reader: String::new(),
+            meter_date: String::new(),
+            as_pdf: false,
+        }
+    }
+}
+
+impl FormValues {
+    fn from_params(params: &DefaultParams, as_pdf: bool) -> Self {
+        Self {
+            building_name: params.building_name.clone(),
+            custom_title: params.custom_title.clone(),
+            per_page: if params.per_page.trim().is_empty() { "3".to_string() } else { params.per_page.clone() },
+            meter_reader: params.meter_reader.clone(),
+            meter_date: params.meter_date.clone(),
+            as_pdf,
+        }
+    }
+}
+
+// 渲染上传页面；error 不为空时在表单上方显示错误提示
+fn render_index(form: &FormValues, error: Option<&str>) -> String {
+    let error_banner = error
+        .map(|message| format!(r#"<div class="error">{}</div>"#, escape_html(message)))
+        .unwrap_or_default();
+    format!(r#"<!doctype html>
 <html lang="zh-CN">
 <head>
 <meta charset="utf-8"/>
 <title>水电表生成系统</title>
 <meta name="viewport" content="width=device-width, initial-scale=1"/>
 <style>
-body{font-family:-apple-system,BlinkMacSystemFont,Segoe UI,Roboto,Helvetica,Arial,sans-serif;padding:24px;}
-.card{max-width:680px;margin:0 auto;border:1px solid #e5e7eb;border-radius:12px;padding:24px;box-shadow:0 10px 25px rgba(0,0,0,0.05)}
-label{display:block;margin:12px 0 6px;color:#374151}
-input[type=file],input[type=text]{width:100%;padding:10px;border:1px solid #d1d5db;border-radius:8px}
-button{margin-top:16px;padding:10px 16px;background:#2563eb;color:white;border:none;border-radius:8px;cursor:pointer}
-small{color:#6b7280}
+body{{font-family:-apple-system,BlinkMacSystemFont,Segoe UI,Roboto,Helvetica,Arial,sans-serif;padding:24px;}}
+.card{{max-width:680px;margin:0 auto;border:1px solid #e5e7eb;border-radius:12px;padding:24px;box-shadow:0 10px 25px rgba(0,0,0,0.05)}}
+label{{display:block;margin:12px 0 6px;color:#374151}}
+input[type=file],input[type=text]{{width:100%;padding:10px;border:1px solid #d1d5db;border-radius:8px}}
+button{{margin-top:16px;padding:10px 16px;background:#2563eb;color:white;border:none;border-radius:8px;cursor:pointer}}
+small{{color:#6b7280}}
+.error{{margin:12px 0;padding:10px 12px;border:1px solid #fca5a5;border-radius:8px;background:#fef2f2;color:#b91c1c}}
 </style>
 </head>
 <body>
 <div class="card">
   <h2>水电表生成系统</h2>
+  {error_banner}
   <form action="/upload" method="post" enctype="multipart/form-data">
     <label>选择文件（.xlsx 或 .csv）</label>
     <input name="file" type="file" accept=".xlsx,.csv" required />
     <!-- 店铺名称列从CSV获取，不在页面展示 -->
+    <label>小区名称（可选，表格中有"小区名称"列时以表格为准）</label>
+    <input name="building_name" type="text" value="{building_name}"/>
     <label>自定义标题（可选，默认：yyyy年MM月抄表计费通知单）</label>
-    <input name="custom_title" type="text" placeholder="例如：2025年08月抄表计费通知单"/>
+    <input name="custom_title" type="text" placeholder="例如：2025年08月抄表计费通知单" value="{custom_title}"/>
     <label>每页表格数量（默认 3）</label>
-    <input name="per_page" type="text" value="3"/>
+    <input name="per_page" type="text" value="{per_page}"/>
     <label>抄表人</label>
-    <input name="meter_reader" type="text" placeholder="请输入抄表人"/>
+    <input name="meter_reader" type="text" placeholder="请输入抄表人" value="{meter_reader}"/>
     <label>抄表日期</label>
-    <input name="meter_date" type="text" placeholder="例如：2025年08月16日"/>
-    <label><input name="as_pdf" type="checkbox" value="1"/> 输出为 PDF</label>
+    <input name="meter_date" type="text" placeholder="例如：2025年08月16日" value="{meter_date}"/>
+    <label><input name="as_pdf" type="checkbox" value="1"{as_pdf}/> 输出为 PDF</label>
     <button type="submit">生成</button>
     <div><small>提示：表头需要与输入框一致或为常见别名。</small></div>
   </form>
 </div>
 </body>
-</html>"#)
+</html>"#,
+        error_banner = error_banner,
+        building_name = escape_html(&form.building_name),
+        custom_title = escape_html(&form.custom_title),
+        per_page = escape_html(&form.per_page),
+        meter_reader = escape_html(&form.meter_reader),
+        meter_date = escape_html(&form.meter_date),
+        as_pdf = if form.as_pdf { " checked" } else { "" },
+    )
 }
 
-async fn upload(mut multipart: Multipart) -> impl IntoResponse {
-    let mut params = DefaultParams::default();
-    let mut saved_path: Option<PathBuf> = None;
-    let mut as_pdf: bool = false;
+// 服务共享状态
+#[derive(Clone)]
+struct AppState {
+    jobs: JobStore,
+    idempotency: IdempotencyCache,
+    // 生成任务并发许可：每个上传占用一个，用完即返回 503
+    generation_slots: Arc<Semaphore>,
+    rate_limiter: RateLimiter,
+    // 单次生成的商户数上限，请求中的 max_merchants 不能超过该值
+    max_merchants: usize,
+}
+
+const DEFAULT_MAX_CONCURRENT_JOBS: usize = 4;
+
+async fn upload(State(state): State<AppState>, headers: HeaderMap, multipart: Multipart) -> Response {
+    // 客户端重试时可带相同的 Idempotency-Key，有效期内直接返回上次结果
+    let idempotency_key = headers
+        .get("Idempotency-Key")
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.trim().to_string())
+        .filter(|v| !v.is_empty());
+    // 请求头带 Prefer: respond-async 时立即返回任务ID（202），之后通过 /api/jobs/:id 查询状态、下载结果
+    let respond_async = headers
+        .get("Prefer")
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.split(',').any(|p| p.trim() == "respond-async"));
+
+    let form = read_upload_form(multipart).await;
+    let form_values = FormValues::from_params(&form.params, form.as_pdf);
+    if form.file.is_none() {
+        return Html(render_index(&form_values, Some("上传失败：未收到文件"))).into_response();
+    }
+
+    // 同一 key 且内容相同的请求：已完成的直接返回结果，仍在生成的等待同一任务
+    let fingerprint = idempotency_key.as_deref().map(|key| upload_fingerprint(key, &form));
+    if let Some(job_id) = fingerprint.and_then(|fp| state.idempotency.get(fp)) {
+        log!("idempotency hit: {} -> job {}", idempotency_key.as_deref().unwrap_or_default(), job_id);
+        return job_response(&state, job_id, respond_async, &form_values).await;
+    }
 
+    // 许可随后台任务结束释放
+    let Ok(permit) = state.generation_slots.clone().try_acquire_owned() else {
+        return (StatusCode::SERVICE_UNAVAILABLE, Html("服务繁忙，请稍后再试")).into_response();
+    };
+    let job_id = state.jobs.create();
+    if let Some(existing) = fingerprint.and_then(|fp| state.idempotency.claim(fp, job_id)) {
+        // 并发的同一请求已先开始生成
+        state.jobs.remove(job_id);
+        drop(permit);
+        log!("idempotency hit: {} -> job {}", idempotency_key.as_deref().unwrap_or_default(), existing);
+        return job_response(&state, existing, respond_async, &form_values).await;
+    }
+    // 生成在后台任务中进行，客户端断开连接也会完成并保留结果
+    let task_state = state.clone();
+    let max_merchants = state.max_merchants;
+    tokio::spawn(REQUEST_ID.scope(current_request_id(), async move {
+        let _permit = permit;
+        match run_blocking(move || generate_upload(form, max_merchants)).await.and_then(|r| r.map_err(anyhow::Error::msg)) {
+            Ok(output) => task_state.jobs.finish(job_id, output),
+            Err(e) => {
+                log!("job {} failed: {}", job_id, e);
+                task_state.jobs.fail(job_id, e.to_string());
+                // 失败的结果不缓存，重试时重新生成
+                if let Some(fp) = fingerprint {
+                    task_state.idempotency.forget(fp, job_id);
+                }
+            }
+        }
+    }));
+    job_response(&state, job_id, respond_async, &form_values).await
+}
+
+// 上传的表单：文件（原文件名, 内容）与各字段
+#[derive(Hash)]
+struct UploadForm {
+    params: DefaultParams,
+    as_pdf: bool,
+    file: Option<(String, Vec<u8>)>,
+}
+
+async fn read_upload_form(mut multipart: Multipart) -> UploadForm {
+    let mut form = UploadForm { params: DefaultParams::default(), as_pdf: false, file: None };
     while let Ok(Some(field)) = multipart.next_field().await {
         let name = field.name().map(|s| s.to_string()).unwrap_or_default();
         if name == "file" {
             let orig_name: String = field.file_name().map(|s| s.to_string()).unwrap_or_else(|| "upload".to_string());
             let bytes = field.bytes().await.unwrap_or_default();
-            // preserve extension for type detection
-            let dir = tempdir().unwrap();
-            let ext = std::path::Path::new(&orig_name).extension().and_then(|e| e.to_str()).unwrap_or("");
-            let fname = if ext.is_empty() { "upload.csv".to_string() } else { orig_name.clone() };
-            let path = dir.path().join(fname);
-            let mut f = File::create(&path).unwrap();
-            f.write_all(&bytes).unwrap();
-            saved_path = Some(path);
-            // keep dir alive until function end by moving it into path parent? We'll leak dir by forgetting it to keep file.
-            std::mem::forget(dir);
-            println!("received file: {} ({} bytes)", orig_name, bytes.len());
+            log!("received file: {} ({} bytes)", orig_name, bytes.len());
+            form.file = Some((orig_name, bytes.to_vec()));
         } else {
             let value = field.text().await.unwrap_or_default();
+            let params = &mut form.params;
             match name.as_str() {
                 "prev_e" => params.prev_e = value,
                 "curr_e" => params.curr_e = value,
@@ -101,46 +325,547 @@ async fn upload(mut multipart: Multipart) -> impl IntoResponse {
                 "meter_date" => params.meter_date = value,
                 "custom_title" => params.custom_title = value,
                 "per_page" => params.per_page = value,
-                "as_pdf" => as_pdf = value == "1" || value.to_lowercase() == "on" || value.to_lowercase() == "true",
+                "building_name" => params.building_name = value,
+                "as_pdf" => form.as_pdf = value == "1" || value.to_lowercase() == "on" || value.to_lowercase() == "true",
                 _ => {}
             }
         }
     }
+    form
+}
 
-    let path = if let Some(p) = saved_path { p } else { return Html("上传失败：未收到文件").into_response() };
-
-    match process_file_to_docx(path, params).await {
-        Ok((filename, bytes)) => {
-            if as_pdf {
-                match convert_docx_bytes_to_pdf(&bytes) {
-                    Ok((_, pdf_bytes)) => {
-                        // 使用前端自定义标题生成的DOCX文件名，替换为 .pdf
-                        let pdf_name = {
-                            let p = std::path::Path::new(&filename);
-                            let stem = p.file_stem().and_then(|s| s.to_str()).unwrap_or("output");
-                            format!("{}.pdf", stem)
-                        };
-                        (
-                            [("Content-Type", "application/pdf"),
-                             ("Content-Disposition", &format!("attachment; filename=\"{}\"", pdf_name))],
-                            pdf_bytes
-                        ).into_response()
-                    },
-                    Err(e) => Html(format!("生成PDF失败：{}", e)).into_response(),
-                }
-            } else {
-                (
-                    [("Content-Type", "application/vnd.openxmlformats-officedocument.wordprocessingml.document"),
-                     ("Content-Disposition", &format!("attachment; filename=\"{}\"", filename))],
-                    bytes
-                ).into_response()
+// 上传任务（阻塞线程中执行）：保存文件、读取并生成文档，按需转换为PDF；失败时返回显示给用户的错误信息
+fn generate_upload(form: UploadForm, max_merchants: usize) -> Result<GeneratedOutput, String> {
+    let (orig_name, bytes) = form.file.ok_or("上传失败：未收到文件")?;
+    // 临时目录在生成结束后删除；保留原扩展名以识别文件类型
+    let dir = tempdir().map_err(|e| format!("生成失败：无法创建临时目录: {}", e))?;
+    let ext = std::path::Path::new(&orig_name).extension().and_then(|e| e.to_str()).unwrap_or("");
+    let fname = if ext.is_empty() { "upload.csv".to_string() } else { orig_name.clone() };
+    let path = dir.path().join(fname);
+    fs::write(&path, &bytes).map_err(|e| format!("生成失败：无法保存上传文件: {}", e))?;
+
+    let (filename, bytes) = process_file_to_docx(path, &form.params, max_merchants).map_err(|e| format!("生成失败：{}", e))?;
+    if !form.as_pdf {
+        return Ok(GeneratedOutput {
+            content_type: "application/vnd.openxmlformats-officedocument.wordprocessingml.document",
+            filename,
+            bytes,
+        });
+    }
+    let (_, pdf_bytes) = convert_docx_bytes_to_pdf(&bytes).map_err(|e| format!("生成PDF失败：{}", e))?;
+    // 使用前端自定义标题生成的DOCX文件名，替换为 .pdf
+    let stem = std::path::Path::new(&filename).file_stem().and_then(|s| s.to_str()).unwrap_or("output");
+    Ok(GeneratedOutput {
+        content_type: "application/pdf",
+        filename: format!("{}.pdf", stem),
+        bytes: pdf_bytes,
+    })
+}
+
+// 上传的响应：异步模式返回任务ID；否则等待任务结束，成功返回文件，失败重新渲染表单并保留已填写的内容
+async fn job_response(state: &AppState, job_id: Uuid, respond_async: bool, form: &FormValues) -> Response {
+    let mut response = if respond_async {
+        (
+            StatusCode::ACCEPTED,
+            Json(serde_json::json!({
+                "id": job_id.to_string(),
+                "status_url": format!("/api/jobs/{}", job_id),
+                "result_url": format!("/api/jobs/{}/result", job_id),
+            })),
+        )
+            .into_response()
+    } else {
+        match state.jobs.wait(job_id).await {
+            Some(Job { status: JobStatus::Done, output: Some(output), .. }) => output.into_response(),
+            Some(Job { status: JobStatus::Failed(message), .. }) => Html(render_index(form, Some(&message))).into_response(),
+            _ => (StatusCode::INTERNAL_SERVER_ERROR, Html("任务不存在或已过期")).into_response(),
+        }
+    };
+    if let Ok(value) = HeaderValue::from_str(&job_id.to_string()) {
+        response.headers_mut().insert("X-Job-Id", value);
+    }
+    response
+}
+
+// 查询生成任务状态
+async fn job_status(State(state): State<AppState>, Path(id): Path<String>) -> impl IntoResponse {
+    let Some(job) = Uuid::parse_str(&id).ok().and_then(|id| state.jobs.get(id)) else {
+        return (StatusCode::NOT_FOUND, Json(serde_json::json!({ "error": "任务不存在或已过期" }))).into_response();
+    };
+    let (status, error) = match &job.status {
+        JobStatus::Running => ("running", None),
+        JobStatus::Done => ("done", None),
+        JobStatus::Failed(message) => ("failed", Some(message.clone())),
+    };
+    Json(serde_json::json!({ "id": id, "status": status, "error": error })).into_response()
+}
+
+// 获取已完成任务的生成结果
+async fn job_result(State(state): State<AppState>, Path(id): Path<String>) -> impl IntoResponse {
+    match Uuid::parse_str(&id).ok().and_then(|id| state.jobs.get(id)).and_then(|job| job.output) {
+        Some(output) => output.into_response(),
+        None => (StatusCode::NOT_FOUND, "任务不存在、未完成或已过期").into_response(),
+    }
+}
+
+// 错误信息可能包含商户名称、文件名等用户输入，输出到HTML前需转义
+fn escape_html(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+// 生成结果（用于响应与幂等缓存）
+#[derive(Clone)]
+struct GeneratedOutput {
+    content_type: &'static str,
+    filename: String,
+    bytes: Vec<u8>,
+}
+
+impl IntoResponse for GeneratedOutput {
+    fn into_response(self) -> Response {
+        (
+            [("Content-Type", self.content_type.to_string()),
+             ("Content-Disposition", content_disposition(&self.filename))],
+            self.bytes
+        ).into_response()
+    }
+}
+
+// RFC 5987 中 attr-char 以外的字符都需百分号编码
+const RFC5987_ENCODE_SET: &percent_encoding::AsciiSet = &percent_encoding::NON_ALPHANUMERIC
+    .remove(b'!').remove(b'#').remove(b'$').remove(b'&').remove(b'+').remove(b'-')
+    .remove(b'.').remove(b'^').remove(b'_').remove(b'`').remove(b'|').remove(b'~');
+
+// 中文文件名需同时提供 filename（ASCII 兜底）与 filename*（UTF-8 编码），否则部分浏览器下载时乱码
+fn content_disposition(filename: &str) -> String {
+    let fallback: String = filename
+        .chars()
+        .map(|c| if c.is_ascii_graphic() && c != '"' && c != '\\' && c != '%' { c } else { '_' })
+        .collect();
+    let encoded = percent_encoding::utf8_percent_encode(filename, RFC5987_ENCODE_SET);
+    format!("attachment; filename=\"{}\"; filename*=UTF-8''{}", fallback, encoded)
+}
+
+// 幂等缓存有效期
+const IDEMPOTENCY_TTL: Duration = Duration::from_secs(10 * 60);
+
+// 幂等指纹：Idempotency-Key 加上表单内容（文件名、文件内容与各字段），同一 key 提交了不同内容时不会取到旧结果
+fn upload_fingerprint(key: &str, form: &UploadForm) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    key.hash(&mut hasher);
+    form.hash(&mut hasher);
+    hasher.finish()
+}
+
+// 幂等缓存：幂等指纹 -> 生成任务ID。任务开始时即记录，生成中的重试等待同一任务，完成后直接取其结果
+#[derive(Clone, Default)]
+struct IdempotencyCache {
+    entries: Arc<Mutex<HashMap<u64, (Instant, Uuid)>>>,
+}
+
+impl IdempotencyCache {
+    fn get(&self, fingerprint: u64) -> Option<Uuid> {
+        let mut entries = self.entries.lock().unwrap();
+        // 顺带清理过期条目
+        entries.retain(|_, (at, _)| at.elapsed() < IDEMPOTENCY_TTL);
+        entries.get(&fingerprint).map(|(_, job_id)| *job_id)
+    }
+
+    // 记录新任务；同一请求已有任务（并发提交时先到的已记录）则不覆盖，返回已有的任务ID
+    fn claim(&self, fingerprint: u64, job_id: Uuid) -> Option<Uuid> {
+        let mut entries = self.entries.lock().unwrap();
+        entries.retain(|_, (at, _)| at.elapsed() < IDEMPOTENCY_TTL);
+        match entries.get(&fingerprint) {
+            Some((_, existing)) => Some(*existing),
+            None => {
+                entries.insert(fingerprint, (Instant::now(), job_id));
+                None
             }
+        }
+    }
+
+    fn forget(&self, fingerprint: u64, job_id: Uuid) {
+        let mut entries = self.entries.lock().unwrap();
+        if entries.get(&fingerprint).is_some_and(|(_, id)| *id == job_id) {
+            entries.remove(&fingerprint);
+        }
+    }
+}
+
+const DEFAULT_RATE_LIMIT_PER_MINUTE: u32 = 30;
+
+// 按客户端IP的令牌桶限流：桶容量为每分钟请求数，令牌按每分钟请求数匀速补充
+#[derive(Clone)]
+struct RateLimiter {
+    per_minute: u32,
+    trust_forwarded_for: bool,
+    buckets: Arc<Mutex<HashMap<IpAddr, (f64, Instant)>>>,
+}
+
+impl RateLimiter {
+    fn new(per_minute: u32, trust_forwarded_for: bool) -> Self {
+        Self { per_minute, trust_forwarded_for, buckets: Arc::new(Mutex::new(HashMap::new())) }
+    }
+
+    // 取一个令牌；桶已空时返回 false
+    fn try_acquire(&self, ip: IpAddr) -> bool {
+        if self.per_minute == 0 {
+            return true;
+        }
+        let capacity = self.per_minute as f64;
+        let refill_per_sec = capacity / 60.0;
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().unwrap();
+        // 顺带清理已补满的桶，避免长期运行后IP表无限增长
+        buckets.retain(|_, (tokens, at)| *tokens + at.elapsed().as_secs_f64() * refill_per_sec < capacity);
+        let (tokens, at) = buckets.entry(ip).or_insert((capacity, now));
+        *tokens = (*tokens + now.duration_since(*at).as_secs_f64() * refill_per_sec).min(capacity);
+        *at = now;
+        if *tokens < 1.0 {
+            return false;
+        }
+        *tokens -= 1.0;
+        true
+    }
+
+    // 客户端IP：信任代理时取 X-Forwarded-For 的第一个地址，否则取连接的对端地址
+    fn client_ip(&self, headers: &HeaderMap, peer: IpAddr) -> IpAddr {
+        if !self.trust_forwarded_for {
+            return peer;
+        }
+        headers
+            .get("X-Forwarded-For")
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.split(',').next())
+            .and_then(|ip| ip.trim().parse().ok())
+            .unwrap_or(peer)
+    }
+}
+
+async fn rate_limit_by_ip(State(state): State<AppState>, ConnectInfo(peer): ConnectInfo<SocketAddr>, req: Request, next: Next) -> Response {
+    let ip = state.rate_limiter.client_ip(req.headers(), peer.ip());
+    if !state.rate_limiter.try_acquire(ip) {
+        log!("客户端 {} 请求过于频繁，已拒绝", ip);
+        return (StatusCode::TOO_MANY_REQUESTS, [("Retry-After", "60")], "请求过于频繁，请稍后再试").into_response();
+    }
+    next.run(req).await
+}
+
+// 任务记录保留时长
+const JOB_TTL: Duration = Duration::from_secs(30 * 60);
+
+#[derive(Clone, Debug, PartialEq)]
+enum JobStatus {
+    Running,
+    Done,
+    Failed(String),
+}
+
+#[derive(Clone)]
+struct Job {
+    status: JobStatus,
+    output: Option<GeneratedOutput>,
+    updated_at: Instant,
+}
+
+// 任务记录及其结束通知（Running 以外的状态发送 true）
+struct JobEntry {
+    job: Job,
+    done: watch::Sender<bool>,
+}
+
+// 生成任务存储：记录状态与结果，超过有效期的任务在访问时淘汰
+#[derive(Clone)]
+struct JobStore {
+    jobs: Arc<Mutex<HashMap<Uuid, JobEntry>>>,
+    ttl: Duration,
+}
+
+impl JobStore {
+    fn new(ttl: Duration) -> Self {
+        Self { jobs: Arc::new(Mutex::new(HashMap::new())), ttl }
+    }
+
+    fn create(&self) -> Uuid {
+        let id = Uuid::new_v4();
+        let mut jobs = self.jobs.lock().unwrap();
+        jobs.retain(|_, entry| entry.job.updated_at.elapsed() < self.ttl);
+        let job = Job { status: JobStatus::Running, output: None, updated_at: Instant::now() };
+        jobs.insert(id, JobEntry { job, done: watch::channel(false).0 });
+        id
+    }
+
+    fn finish(&self, id: Uuid, output: GeneratedOutput) {
+        self.update(id, JobStatus::Done, Some(output));
+    }
+
+    fn fail(&self, id: Uuid, error: String) {
+        self.update(id, JobStatus::Failed(error), None);
+    }
+
+    fn update(&self, id: Uuid, status: JobStatus, output: Option<GeneratedOutput>) {
+        if let Some(entry) = self.jobs.lock().unwrap().get_mut(&id) {
+            let done = status != JobStatus::Running;
+            entry.job = Job { status, output, updated_at: Instant::now() };
+            entry.done.send_replace(done);
+        }
+    }
+
+    fn remove(&self, id: Uuid) {
+        self.jobs.lock().unwrap().remove(&id);
+    }
+
+    fn get(&self, id: Uuid) -> Option<Job> {
+        let mut jobs = self.jobs.lock().unwrap();
+        jobs.retain(|_, entry| entry.job.updated_at.elapsed() < self.ttl);
+        jobs.get(&id).map(|entry| entry.job.clone())
+    }
+
+    // 等待任务结束（完成或失败）后返回；任务不存在或等待期间被淘汰时返回None
+    async fn wait(&self, id: Uuid) -> Option<Job> {
+        let mut done = self.jobs.lock().unwrap().get(&id)?.done.subscribe();
+        done.wait_for(|done| *done).await.ok()?;
+        self.get(id)
+    }
+}
+
+// 校验模板配置JSON：成功返回 {valid:true}，失败返回错误信息及行列号
+async fn validate_config(body: String) -> impl IntoResponse {
+    // JSON语法错误时返回行列号；字段与当前版本不一致时返回缺少/未知的字段
+    if let Err(e) = serde_json::from_str::<serde_json::Value>(&body) {
+        return Json(serde_json::json!({
+            "valid": false,
+            "error": format!("配置无效：{}", e),
+            "line": e.line(),
+            "column": e.column(),
+        }));
+    }
+    match TemplateConfig::from_json_str(&body) {
+        Ok(_) => Json(serde_json::json!({ "valid": true })),
+        Err(e) => Json(serde_json::json!({ "valid": false, "error": e })),
+    }
+}
+
+// 检查上传文件的读取方式（不生成文档）：返回表头、数据行数，以及首行各数值列的原始值与解析结果
+async fn inspect(mut multipart: Multipart) -> impl IntoResponse {
+    while let Ok(Some(field)) = multipart.next_field().await {
+        if field.name() != Some("file") {
+            continue;
+        }
+        let orig_name = field.file_name().map(|s| s.to_string()).unwrap_or_default();
+        let format = if orig_name.to_lowercase().ends_with(".xlsx") { "xlsx" } else { "csv" };
+        let bytes = field.bytes().await.unwrap_or_default();
+        log!("inspect file: {} ({} bytes)", orig_name, bytes.len());
+        let report = run_blocking(move || inspect_data_bytes(&bytes, format, "电表")).await.and_then(|r| r);
+        return match report {
+            Ok(report) => {
+                let first_row: Vec<serde_json::Value> = report
+                    .coercions
+                    .iter()
+                    .map(|c| serde_json::json!({ "column": c.column, "raw": c.raw, "value": c.value, "error": c.error }))
+                    .collect();
+                Json(serde_json::json!({
+                    "headers": report.headers,
+                    "data_rows": report.data_rows,
+                    "first_row": first_row,
+                }))
+                .into_response()
+            }
+            Err(e) => (StatusCode::BAD_REQUEST, Json(serde_json::json!({ "error": format!("读取失败：{}", e) }))).into_response(),
+        };
+    }
+    (StatusCode::BAD_REQUEST, Json(serde_json::json!({ "error": "未收到文件" }))).into_response()
+}
+
+// 可识别的数据列：逻辑字段名、表头（含别名）及是否必需，供前端构建表头映射
+async fn schema() -> impl IntoResponse {
+    let columns: Vec<serde_json::Value> = COLUMN_SCHEMA
+        .iter()
+        .map(|c| serde_json::json!({ "field": c.field, "headers": c.headers, "required": c.required, "note": c.note }))
+        .collect();
+    Json(serde_json::json!({ "columns": columns }))
+}
+
+// 汇总接口读取数据时的表头映射（列按固定表头名识别）
+fn data_headers() -> HeadersMap<'static> {
+    HeadersMap {
+        merchant: "店铺名称",
+        prev_e: "",
+        curr_e: "",
+        prev_w: "",
+        curr_w: "",
+        w_price: "",
+        e_price: "",
+        electricity_price: "",
+        electricity_prefix: "电表",
+        water_electricity_labor_fee: "水电人工费",
+        garbage_disposal_fee: "垃圾处理费",
+    }
+}
+
+fn summary_response(bills: anyhow::Result<Vec<MerchantBill>>) -> Response {
+    match bills {
+        Ok(bills) => Json(summary_json(&bills)).into_response(),
+        Err(e) => (StatusCode::BAD_REQUEST, Json(serde_json::json!({ "error": format!("读取失败：{}", e) }))).into_response(),
+    }
+}
+
+// POST /api/summary：上传文件（字段 file），仅返回汇总JSON
+async fn summary_from_upload(mut multipart: Multipart) -> Response {
+    while let Ok(Some(field)) = multipart.next_field().await {
+        if field.name() != Some("file") {
+            continue;
+        }
+        let orig_name = field.file_name().map(|s| s.to_string()).unwrap_or_default();
+        let format = if orig_name.to_lowercase().ends_with(".xlsx") { "xlsx" } else { "csv" };
+        let bytes = field.bytes().await.unwrap_or_default();
+        log!("summary file: {} ({} bytes)", orig_name, bytes.len());
+        let bills = run_blocking(move || read_data_bytes(&bytes, format, &data_headers(), &ReadOptions::default())).await;
+        return summary_response(bills.and_then(|b| b));
+    }
+    (StatusCode::BAD_REQUEST, Json(serde_json::json!({ "error": "未收到文件" }))).into_response()
+}
+
+#[derive(serde::Deserialize)]
+struct SummaryQuery {
+    url: String,
+}
+
+// GET /api/summary?url=...：下载数据文件，仅返回汇总JSON
+async fn summary_from_url(Query(query): Query<SummaryQuery>) -> Response {
+    log!("summary url: {}", query.url);
+    let bills = run_blocking(move || {
+        let (bytes, format) = fetch_data_url(&query.url)?;
+        read_data_bytes(&bytes, format, &data_headers(), &ReadOptions::default())
+    })
+    .await;
+    summary_response(bills.and_then(|b| b))
+}
+
+// 前端修改后的账单（如 /api/inspect 读取后人工更正读数）及生成选项
+#[derive(serde::Deserialize)]
+struct GenerateFromJsonRequest {
+    bills: Vec<MerchantBill>,
+    #[serde(default)]
+    options: Option<GenerateOptions>,
+    // docx（默认）或 pdf
+    #[serde(default)]
+    format: String,
+}
+
+// POST /api/generate-from-json：按提交的账单重新计算并生成通知单
+async fn generate_from_json(State(state): State<AppState>, Json(request): Json<GenerateFromJsonRequest>) -> Response {
+    let bad_request = |message: String| (StatusCode::BAD_REQUEST, Json(serde_json::json!({ "error": message }))).into_response();
+    let as_pdf = match request.format.as_str() {
+        "" | "docx" => false,
+        "pdf" => true,
+        other => return bad_request(format!("不支持的格式：{}", other)),
+    };
+    if request.bills.is_empty() {
+        return bad_request("账单为空".to_string());
+    }
+    let mut bills = request.bills;
+    for bill in &mut bills {
+        bill.recalculate();
+    }
+    log!("generate from json: {} bills, format={}", bills.len(), if as_pdf { "pdf" } else { "docx" });
+
+    // 与上传共用生成并发许可；请求中的商户数上限不能超过服务端上限
+    let Ok(_permit) = state.generation_slots.clone().try_acquire_owned() else {
+        return (StatusCode::SERVICE_UNAVAILABLE, Json(serde_json::json!({ "error": "服务繁忙，请稍后再试" }))).into_response();
+    };
+    let mut options = request.options.unwrap_or_default();
+    options.max_merchants = options.max_merchants.min(state.max_merchants);
+
+    let generated = run_blocking(move || {
+        let docx = generate_word_document_with_template(&bills, Some(options)).map_err(|e| (StatusCode::BAD_REQUEST, format!("生成失败：{}", e)))?;
+        if !as_pdf {
+            return Ok(GeneratedOutput {
+                content_type: "application/vnd.openxmlformats-officedocument.wordprocessingml.document",
+                filename: "bills.docx".to_string(),
+                bytes: docx,
+            });
+        }
+        let (_, bytes) = convert_docx_bytes_to_pdf(&docx).map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("PDF转换失败：{}", e)))?;
+        Ok(GeneratedOutput { content_type: "application/pdf", filename: "bills.pdf".to_string(), bytes })
+    })
+    .await
+    .unwrap_or_else(|e| Err((StatusCode::INTERNAL_SERVER_ERROR, e.to_string())));
+    match generated {
+        Ok(output) => output.into_response(),
+        Err((status, message)) => (status, Json(serde_json::json!({ "error": message }))).into_response(),
+    }
+}
+
+// 单户快速计算的参数：we_* 为电表读数，w_* 为水表读数
+#[derive(serde::Deserialize)]
+struct QuickParams {
+    #[serde(default)]
+    shop: String,
+    #[serde(default)]
+    name: String,
+    #[serde(default)]
+    we_prev: f64,
+    #[serde(default)]
+    we_curr: f64,
+    #[serde(default)]
+    w_prev: f64,
+    #[serde(default)]
+    w_curr: f64,
+    #[serde(default)]
+    w_price: f64,
+    #[serde(default)]
+    e_price: f64,
+    // json（默认）或 docx
+    #[serde(default)]
+    format: String,
+}
+
+// 不上传文件，直接按查询参数计算单户账单，返回JSON或通知单DOCX
+async fn quick(Query(params): Query<QuickParams>) -> Response {
+    let name = if params.name.trim().is_empty() { params.shop.trim().to_string() } else { params.name.trim().to_string() };
+    let mut bill = MerchantBill::new(name, params.w_price, params.e_price);
+    bill.set_shop_code(params.shop.trim().to_string());
+    bill.set_water_readings(params.w_prev, params.w_curr);
+    bill.add_electricity_meter("1".to_string(), params.we_prev, params.we_curr);
+    log!("quick bill: {} total={:.2}", bill.shop_code, bill.total_fee);
+
+    match params.format.as_str() {
+        "docx" => match run_blocking({ let bill = bill.clone(); move || generate_single_bill(&bill, None) }).await.and_then(|r| r) {
+            Ok(bytes) => GeneratedOutput {
+                content_type: "application/vnd.openxmlformats-officedocument.wordprocessingml.document",
+                filename: format!("{}.docx", sanitize_filename(if bill.shop_code.is_empty() { "quick" } else { &bill.shop_code })),
+                bytes,
+            }
+            .into_response(),
+            Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, Json(serde_json::json!({ "error": format!("生成失败：{}", e) }))).into_response(),
         },
-        Err(e) => Html(format!("生成失败：{}", e)).into_response(),
+        "" | "json" => Json(serde_json::json!({
+            "shop_code": bill.shop_code,
+            "merchant_name": bill.merchant_name,
+            "water_usage": bill.water_usage,
+            "water_amount": bill.water_amount,
+            "electricity_usage": bill.electricity_usage,
+            "electricity_amount": bill.electricity_amount,
+            "total_fee": bill.total_fee,
+        }))
+        .into_response(),
+        other => (StatusCode::BAD_REQUEST, Json(serde_json::json!({ "error": format!("不支持的格式：{}", other) }))).into_response(),
     }
 }
 
-#[derive(Default)]
+#[derive(Default, Hash)]
 struct DefaultParams {
     prev_e: String,
     curr_e: String,
@@ -152,9 +877,10 @@ struct DefaultParams {
     meter_date: String,
     custom_title: String,
     per_page: String,
+    building_name: String,
 }
 
-async fn process_file_to_docx(path: PathBuf, params: DefaultParams) -> anyhow::Result<(String, Vec<u8>)> {
+fn process_file_to_docx(path: PathBuf, params: &DefaultParams, max_merchants: usize) -> anyhow::Result<(String, Vec<u8>)> {
     use anyhow::Context;
     
     // 创建新的HeadersMap结构
@@ -187,34 +913,50 @@ async fn process_file_to_docx(path: PathBuf, params: DefaultParams) -> anyhow::R
 
     // 生成Word文档
     let per_page = params.per_page.trim().parse::<usize>().unwrap_or(1);
-    let opts = GenerateOptions { custom_title: if params.custom_title.trim().is_empty() { None } else { Some(params.custom_title.clone()) }, per_page };
-    let docx_content = generate_word_document_with_template(&bills, Some(opts))
+    let opts = GenerateOptions {
+        custom_title: if params.custom_title.trim().is_empty() { None } else { Some(params.custom_title.clone()) },
+        per_page,
+        building_name: if params.building_name.trim().is_empty() { None } else { Some(params.building_name.trim().to_string()) },
+        max_merchants,
+        ..GenerateOptions::default()
+    };
+    let docx_content = generate_word_document_with_template(&bills, Some(opts.clone()))
         .map_err(|e| anyhow::anyhow!("生成Word文档失败: {}", e))?;
 
     let now = chrono::Local::now();
     let filename = if params.custom_title.trim().is_empty() {
-        format!("report_{}{}.docx", now.format("%m"), now.format("%Y"))
+        let report = format!("report_{}{}", now.format("%m"), now.format("%Y"));
+        // 有小区名称时加在文件名前，便于区分不同楼宇
+        match resolve_building_name(&bills, Some(&opts)) {
+            Some(name) => format!("{}_{}.docx", sanitize_filename(&name), report),
+            None => format!("{}.docx", report),
+        }
     } else {
         // 使用自定义标题作为文件名，移除特殊字符
-        let clean_title = params.custom_title
+        let clean_title = sanitize_filename(&params.custom_title
             .replace("年", "")
             .replace("月", "")
-            .replace("日", "")
-            .replace(" ", "_")
-            .replace("/", "_")
-            .replace("\\", "_")
-            .replace(":", "_")
-            .replace("*", "_")
-            .replace("?", "_")
-            .replace("\"", "_")
-            .replace("<", "_")
-            .replace(">", "_")
-            .replace("|", "_");
+            .replace("日", ""));
         format!("{}.docx", clean_title)
     };
     Ok((filename, docx_content))
 }
 
+// 替换文件名中不允许或易出错的字符
+fn sanitize_filename(name: &str) -> String {
+    name.trim()
+        .replace(" ", "_")
+        .replace("/", "_")
+        .replace("\\", "_")
+        .replace(":", "_")
+        .replace("*", "_")
+        .replace("?", "_")
+        .replace("\"", "_")
+        .replace("<", "_")
+        .replace(">", "_")
+        .replace("|", "_")
+}
+
 fn convert_docx_bytes_to_pdf(docx_bytes: &[u8]) -> anyhow::Result<(String, Vec<u8>)> {
     use anyhow::Context;
     // 将字节写入临时 DOCX 文件
@@ -262,3 +1004,346 @@ fn convert_docx_bytes_to_pdf(docx_bytes: &[u8]) -> anyhow::Result<(String, Vec<u
     anyhow::bail!("未找到可用的转换工具，请安装 LibreOffice(soffice/libreoffice/lowriter) 或 pandoc")
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body::Body, extract::connect_info::MockConnectInfo};
+    use tower::ServiceExt;
+
+    // write_log 输出的所有日志行，测试按请求ID筛选
+    pub(super) static CAPTURED_LOGS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+    const BOUNDARY: &str = "----waem-test-boundary";
+
+    fn test_state(max_jobs: usize) -> AppState {
+        AppState {
+            jobs: JobStore::new(JOB_TTL),
+            idempotency: IdempotencyCache::default(),
+            generation_slots: Arc::new(Semaphore::new(max_jobs)),
+            rate_limiter: RateLimiter::new(0, false),
+            max_merchants: DEFAULT_MAX_MERCHANTS,
+        }
+    }
+
+    fn test_app(state: AppState) -> Router {
+        app(state).layer(MockConnectInfo(SocketAddr::from(([127, 0, 0, 1], 40000))))
+    }
+
+    // multipart/form-data 请求体：fields 为文本字段，file 为 (文件名, 内容)
+    fn multipart_body(fields: &[(&str, &str)], file: Option<(&str, &[u8])>) -> Vec<u8> {
+        let mut body = Vec::new();
+        for (name, value) in fields {
+            body.extend(format!("--{}\r\nContent-Disposition: form-data; name=\"{}\"\r\n\r\n{}\r\n", BOUNDARY, name, value).into_bytes());
+        }
+        if let Some((filename, bytes)) = file {
+            body.extend(format!("--{}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"{}\"\r\nContent-Type: application/octet-stream\r\n\r\n", BOUNDARY, filename).into_bytes());
+            body.extend_from_slice(bytes);
+            body.extend_from_slice(b"\r\n");
+        }
+        body.extend(format!("--{}--\r\n", BOUNDARY).into_bytes());
+        body
+    }
+
+    fn multipart_request(uri: &str) -> axum::http::request::Builder {
+        Request::builder()
+            .method("POST")
+            .uri(uri)
+            .header("Content-Type", format!("multipart/form-data; boundary={}", BOUNDARY))
+    }
+
+    async fn body_bytes(response: Response) -> Vec<u8> {
+        axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap().to_vec()
+    }
+
+    const SAMPLE_CSV: &str = "铺面编号,店铺名称,电表1上期读数,电表1本期读数,上期水表读数,本期水表读数,水费单价,电费单价\n\
+                              A01,商户甲,100,150,10,12,3.5,1\n";
+
+    #[tokio::test]
+    async fn request_id_header_tags_library_warnings_in_blocking_tasks() {
+        // 本期读数无法解析，读取时库会输出警告
+        let csv = SAMPLE_CSV.replace("150", "abc");
+        let body = multipart_body(&[], Some(("bills.csv", csv.as_bytes())));
+        let request = multipart_request("/api/summary").body(Body::from(body)).unwrap();
+        let response = test_app(test_state(1)).oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let id = response.headers().get("X-Request-Id").unwrap().to_str().unwrap().to_string();
+        assert!(Uuid::parse_str(&id).is_ok());
+
+        let logs = CAPTURED_LOGS.lock().unwrap().clone();
+        let prefix = format!("[{}] ", id);
+        assert!(logs.iter().any(|l| l.starts_with(&prefix) && l.contains("summary file: bills.csv")), "{:?}", logs);
+        assert!(logs.iter().any(|l| l.starts_with(&prefix) && l.contains("警告：") && l.contains("abc")), "{:?}", logs);
+    }
+
+    fn sample_output(bytes: &[u8]) -> GeneratedOutput {
+        GeneratedOutput { content_type: "application/pdf", filename: "bills.pdf".to_string(), bytes: bytes.to_vec() }
+    }
+
+    #[tokio::test]
+    async fn job_store_keeps_results_and_evicts_expired_jobs() {
+        let store = JobStore::new(Duration::from_millis(50));
+        let done = store.create();
+        let failed = store.create();
+        assert_eq!(store.get(done).unwrap().status, JobStatus::Running);
+
+        store.finish(done, sample_output(b"%PDF"));
+        store.fail(failed, "生成失败".to_string());
+        let job = store.wait(done).await.unwrap();
+        assert_eq!(job.status, JobStatus::Done);
+        assert_eq!(job.output.unwrap().bytes, b"%PDF");
+        assert_eq!(store.get(failed).unwrap().status, JobStatus::Failed("生成失败".to_string()));
+
+        tokio::time::sleep(Duration::from_millis(80)).await;
+        assert!(store.get(done).is_none());
+        assert!(store.get(failed).is_none());
+        assert!(store.wait(done).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn async_upload_returns_job_id_before_generation_finishes() {
+        let state = test_state(1);
+        let body = multipart_body(&[], Some(("bills.csv", SAMPLE_CSV.as_bytes())));
+        let request = multipart_request("/upload").header("Prefer", "respond-async").body(Body::from(body)).unwrap();
+        let response = test_app(state.clone()).oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::ACCEPTED);
+        let job_id = response.headers().get("X-Job-Id").unwrap().to_str().unwrap().to_string();
+        let json: serde_json::Value = serde_json::from_slice(&body_bytes(response).await).unwrap();
+        assert_eq!(json["id"], job_id.as_str());
+        assert_eq!(json["result_url"], format!("/api/jobs/{}/result", job_id));
+
+        let job = state.jobs.wait(Uuid::parse_str(&job_id).unwrap()).await.unwrap();
+        assert_eq!(job.status, JobStatus::Done);
+        let status = Request::builder().uri(format!("/api/jobs/{}", job_id)).body(Body::empty()).unwrap();
+        let status: serde_json::Value = serde_json::from_slice(&body_bytes(test_app(state.clone()).oneshot(status).await.unwrap()).await).unwrap();
+        assert_eq!(status["status"], "done");
+        let result = Request::builder().uri(format!("/api/jobs/{}/result", job_id)).body(Body::empty()).unwrap();
+        let result = test_app(state).oneshot(result).await.unwrap();
+        assert_eq!(result.status(), StatusCode::OK);
+        assert_eq!(body_bytes(result).await, job.output.unwrap().bytes);
+    }
+
+    fn idempotent_upload(key: &str, csv: &str) -> Request<Body> {
+        let body = multipart_body(&[("meter_reader", "张三")], Some(("bills.csv", csv.as_bytes())));
+        multipart_request("/upload").header("Idempotency-Key", key).body(Body::from(body)).unwrap()
+    }
+
+    #[tokio::test]
+    async fn idempotent_uploads_run_generation_once() {
+        let state = test_state(2);
+        let app = test_app(state.clone());
+        // 同时提交：后到的请求等待先到的任务
+        let (first, second) = tokio::join!(
+            app.clone().oneshot(idempotent_upload("retry-1", SAMPLE_CSV)),
+            app.clone().oneshot(idempotent_upload("retry-1", SAMPLE_CSV)),
+        );
+        let (first, second) = (first.unwrap(), second.unwrap());
+        assert_eq!(first.status(), StatusCode::OK);
+        assert_eq!(second.status(), StatusCode::OK);
+        let job_id = first.headers().get("X-Job-Id").cloned().unwrap();
+        assert_eq!(second.headers().get("X-Job-Id"), Some(&job_id));
+        let bytes = body_bytes(first).await;
+        assert!(!bytes.is_empty());
+        assert_eq!(body_bytes(second).await, bytes);
+
+        // 完成后的重试直接取缓存结果
+        let third = app.clone().oneshot(idempotent_upload("retry-1", SAMPLE_CSV)).await.unwrap();
+        assert_eq!(third.headers().get("X-Job-Id"), Some(&job_id));
+        assert_eq!(body_bytes(third).await, bytes);
+        assert_eq!(state.jobs.jobs.lock().unwrap().len(), 1);
+
+        // 同一 key 但内容不同时重新生成
+        let changed = app.oneshot(idempotent_upload("retry-1", &SAMPLE_CSV.replace("150", "160"))).await.unwrap();
+        assert_ne!(changed.headers().get("X-Job-Id"), Some(&job_id));
+        assert_eq!(state.jobs.jobs.lock().unwrap().len(), 2);
+    }
+
+    fn json_request(body: serde_json::Value) -> Request<Body> {
+        Request::builder()
+            .method("POST")
+            .uri("/api/generate-from-json")
+            .header("Content-Type", "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap()
+    }
+
+    fn edited_bills() -> Vec<MerchantBill> {
+        let mut bills = read_data_bytes(SAMPLE_CSV.as_bytes(), "csv", &data_headers(), &ReadOptions::default()).unwrap();
+        // 前端改正了本期水表读数，金额由服务端重新计算
+        bills[0].curr_water_reading = 30.0;
+        bills
+    }
+
+    #[tokio::test]
+    async fn generate_from_json_recalculates_edited_bills() {
+        let response = test_app(test_state(1)).oneshot(json_request(serde_json::json!({ "bills": edited_bills() }))).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let text = docx_rs::read_docx(&body_bytes(response).await).unwrap().json();
+        // 水费 (30-10)×3.5=70，电费 50，合计 120（未改正时为 57）
+        assert!(text.contains("壹佰贰拾元整    小写：120.00"), "{}", text);
+    }
+
+    #[tokio::test]
+    async fn generate_from_json_respects_server_limits() {
+        let mut state = test_state(1);
+        state.max_merchants = 0;
+        let body = serde_json::json!({ "bills": edited_bills(), "options": { "max_merchants": 5000 } });
+        let response = test_app(state).oneshot(json_request(body.clone())).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let json: serde_json::Value = serde_json::from_slice(&body_bytes(response).await).unwrap();
+        assert!(json["error"].as_str().unwrap().contains("上限 0"), "{}", json);
+
+        let busy = test_app(test_state(0)).oneshot(json_request(body)).await.unwrap();
+        assert_eq!(busy.status(), StatusCode::SERVICE_UNAVAILABLE);
+    }
+
+    #[tokio::test]
+    async fn validate_config_reports_malformed_json_with_position() {
+        let validate = |body: String| {
+            let request = Request::builder().method("POST").uri("/api/validate-config").body(Body::from(body)).unwrap();
+            async move {
+                let response = test_app(test_state(1)).oneshot(request).await.unwrap();
+                serde_json::from_slice::<serde_json::Value>(&body_bytes(response).await).unwrap()
+            }
+        };
+        let json = validate("{\n  \"title\": \"抄表计费通知单\",\n  \"per_page\": \n}".to_string()).await;
+        assert_eq!(json["valid"], false);
+        assert_eq!(json["line"], 4);
+        assert!(json["error"].as_str().unwrap().starts_with("配置无效："), "{}", json);
+
+        let json = validate(include_str!("../config/template_config.json").to_string()).await;
+        assert_eq!(json["valid"], true, "{}", json);
+    }
+
+    #[tokio::test]
+    async fn failed_upload_escapes_user_text_in_html() {
+        assert_eq!(escape_html(r#"A & <B> "x" 'y'"#), "A &amp; &lt;B&gt; &quot;x&quot; &#39;y&#39;");
+        let body = multipart_body(&[("meter_reader", "A & <B>")], Some(("账单.A & <B>", SAMPLE_CSV.as_bytes())));
+        let response = test_app(test_state(1)).oneshot(multipart_request("/upload").body(Body::from(body)).unwrap()).await.unwrap();
+        let html = String::from_utf8(body_bytes(response).await).unwrap();
+        assert!(html.contains(r#"value="A &amp; &lt;B&gt;""#), "{}", html);
+        assert!(html.contains(r#"<div class="error">生成失败："#), "{}", html);
+        assert!(!html.contains("<B>") && !html.contains("<b>"), "{}", html);
+    }
+
+    #[tokio::test]
+    async fn failed_upload_keeps_entered_form_values() {
+        let fields = [("meter_reader", "张三"), ("meter_date", "2025年08月16日"), ("custom_title", "8月水电费"), ("as_pdf", "1")];
+        let missing_file = multipart_body(&fields, None);
+        let bad_file = multipart_body(&fields, Some(("bills.csv", "店铺名称\n商户甲\n".as_bytes())));
+        for (body, error) in [(missing_file, "上传失败：未收到文件"), (bad_file, "生成失败：")] {
+            let response = test_app(test_state(1)).oneshot(multipart_request("/upload").body(Body::from(body)).unwrap()).await.unwrap();
+            let html = String::from_utf8(body_bytes(response).await).unwrap();
+            assert!(html.contains(error), "{}", html);
+            assert!(html.contains(r#"name="meter_reader" type="text" placeholder="请输入抄表人" value="张三""#), "{}", html);
+            assert!(html.contains(r#"value="2025年08月16日""#) && html.contains(r#"value="8月水电费""#), "{}", html);
+            assert!(html.contains(r#"value="1" checked"#), "{}", html);
+        }
+    }
+
+    #[tokio::test]
+    async fn uploads_beyond_the_generation_limit_are_rejected() {
+        let state = test_state(1);
+        let upload = || {
+            let body = multipart_body(&[], Some(("bills.csv", SAMPLE_CSV.as_bytes())));
+            multipart_request("/upload").body(Body::from(body)).unwrap()
+        };
+        // 占用唯一的生成许可，模拟正在进行的生成
+        let busy = state.generation_slots.clone().try_acquire_owned().unwrap();
+        let response = test_app(state.clone()).oneshot(upload()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert!(String::from_utf8(body_bytes(response).await).unwrap().contains("服务繁忙，请稍后再试"));
+
+        drop(busy);
+        let response = test_app(state).oneshot(upload()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn inspect_reports_coerced_currency_price() {
+        let csv = SAMPLE_CSV.replace(",3.5,1\n", ",￥3.50,abc\n");
+        let body = multipart_body(&[], Some(("bills.csv", csv.as_bytes())));
+        let response = test_app(test_state(1)).oneshot(multipart_request("/api/inspect").body(Body::from(body)).unwrap()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let json: serde_json::Value = serde_json::from_slice(&body_bytes(response).await).unwrap();
+        assert_eq!(json["data_rows"], 1);
+        let column = |name: &str| json["first_row"].as_array().unwrap().iter().find(|c| c["column"] == name).unwrap().clone();
+        assert_eq!(column("水费单价"), serde_json::json!({ "column": "水费单价", "raw": "￥3.50", "value": 3.5, "error": null }));
+        let electricity = column("电费单价");
+        assert_eq!((electricity["raw"].as_str(), electricity["value"].as_f64()), (Some("abc"), Some(0.0)));
+        assert!(electricity["error"].is_string(), "{}", electricity);
+    }
+
+    #[tokio::test]
+    async fn chinese_title_download_has_encoded_filename() {
+        assert_eq!(content_disposition("a \"b\"%.docx"), "attachment; filename=\"a__b__.docx\"; filename*=UTF-8''a%20%22b%22%25.docx");
+
+        let body = multipart_body(&[("custom_title", "2025年8月水电费")], Some(("bills.csv", SAMPLE_CSV.as_bytes())));
+        let response = test_app(test_state(1)).oneshot(multipart_request("/upload").body(Body::from(body)).unwrap()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers().get("Content-Disposition").unwrap().to_str().unwrap(),
+            "attachment; filename=\"20258___.docx\"; filename*=UTF-8''20258%E6%B0%B4%E7%94%B5%E8%B4%B9.docx"
+        );
+    }
+
+    #[tokio::test]
+    async fn schema_lists_required_and_optional_columns() {
+        let request = Request::builder().uri("/api/schema").body(Body::empty()).unwrap();
+        let response = test_app(test_state(1)).oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let json: serde_json::Value = serde_json::from_slice(&body_bytes(response).await).unwrap();
+        let column = |header: &str| {
+            json["columns"].as_array().unwrap().iter()
+                .find(|c| c["headers"].as_array().unwrap().iter().any(|h| h == header))
+                .cloned()
+                .unwrap()
+        };
+        assert_eq!(column("铺面编号")["field"], "shop_code");
+        assert_eq!(column("铺面编号")["required"], true);
+        assert_eq!(column("水电人工费")["required"], false);
+    }
+
+    #[tokio::test]
+    async fn quick_bill_returns_computed_total() {
+        let query = "shop=A101&name=X&we_prev=100&we_curr=150&w_prev=10&w_curr=12&w_price=3.5&e_price=1";
+        let request = Request::builder().uri(format!("/api/quick?{}", query)).body(Body::empty()).unwrap();
+        let response = test_app(test_state(1)).oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let json: serde_json::Value = serde_json::from_slice(&body_bytes(response).await).unwrap();
+        assert_eq!(json["shop_code"], "A101");
+        assert_eq!(json["electricity_amount"], 50.0);
+        assert_eq!(json["total_fee"], 57.0);
+
+        let request = Request::builder().uri(format!("/api/quick?{}&format=docx", query)).body(Body::empty()).unwrap();
+        let response = test_app(test_state(1)).oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(response.headers().get("Content-Disposition").unwrap().to_str().unwrap().contains("A101.docx"));
+        let text = docx_rs::read_docx(&body_bytes(response).await).unwrap().json();
+        assert!(text.contains("小写：57.00"), "{}", text);
+    }
+
+    #[tokio::test]
+    async fn exceeding_rate_limit_from_one_ip_returns_429() {
+        let state = AppState { rate_limiter: RateLimiter::new(2, false), ..test_state(1) };
+        let from = |ip: [u8; 4], state: AppState| async move {
+            let app = app(state).layer(MockConnectInfo(SocketAddr::from((ip, 40000))));
+            app.oneshot(Request::builder().uri("/api/schema").body(Body::empty()).unwrap()).await.unwrap()
+        };
+        assert_eq!(from([10, 0, 0, 1], state.clone()).await.status(), StatusCode::OK);
+        assert_eq!(from([10, 0, 0, 1], state.clone()).await.status(), StatusCode::OK);
+        let limited = from([10, 0, 0, 1], state.clone()).await;
+        assert_eq!(limited.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(String::from_utf8(body_bytes(limited).await).unwrap(), "请求过于频繁，请稍后再试");
+        assert_eq!(from([10, 0, 0, 2], state.clone()).await.status(), StatusCode::OK);
+
+        // 信任代理时按 X-Forwarded-For 区分客户端
+        let state = AppState { rate_limiter: RateLimiter::new(1, true), ..test_state(1) };
+        let forwarded = |ip: &'static str| {
+            let request = Request::builder().uri("/api/schema").header("X-Forwarded-For", ip).body(Body::empty()).unwrap();
+            test_app(state.clone()).oneshot(request)
+        };
+        assert_eq!(forwarded("203.0.113.5").await.unwrap().status(), StatusCode::OK);
+        assert_eq!(forwarded("203.0.113.5, 10.0.0.1").await.unwrap().status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(forwarded("203.0.113.6").await.unwrap().status(), StatusCode::OK);
+    }
+}
@@ -1,5 +1,5 @@
 use anyhow::{Context, Result};
-use crate::MerchantBill;
+use crate::{MerchantBill, Totals};
 use chrono::{Datelike, Local};
 use docx_rs::*;
 use serde::{Deserialize, Serialize};
@@ -390,9 +390,7 @@ impl DocumentGenerator {
         }
 
         // 添加合计行
-        let total_water: f64 = bills.iter().map(|b| b.water_amount).sum();
-        let total_electricity: f64 = bills.iter().map(|b| b.electricity_amount).sum();
-        let grand_total: f64 = bills.iter().map(|b| b.total_fee).sum();
+        let Totals { water_amount: total_water, electricity_amount: total_electricity, grand_total, .. } = Totals::from_merchants(bills);
 
         table = table.add_row(TableRow::new(vec![
             TableCell::new()
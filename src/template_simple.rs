@@ -1,11 +1,33 @@
-use crate::MerchantBill;
+use crate::{format_price, resolve_date_format, MerchantBill, PRICE_MAX_DECIMALS};
 use chrono::{Datelike, Local};
 use docx_rs::*;
 use serde::Deserialize;
 use std::clone::Clone;
 
+// 配置格式版本：必填字段有增减时递增
+pub const TEMPLATE_CONFIG_VERSION: u32 = 1;
+
+// TemplateConfig 的必填字段（version 为可选）
+pub(crate) const REQUIRED_FIELDS: &[&str] = &[
+    "document_title",
+    "title_font_size",
+    "title_alignment",
+    "section_font_size",
+    "timestamp_font_size",
+    "merchant_template",
+    "output_format",
+    "default_output_name",
+    "individual_bills",
+];
+
+// TemplateConfig 的可选字段
+pub(crate) const OPTIONAL_FIELDS: &[&str] = &["version", "date_format"];
+
 #[derive(Debug, Deserialize, Clone)]
 pub struct TemplateConfig {
+    // 配置格式版本（可选），未标注时按当前版本解析
+    #[serde(default)]
+    pub version: Option<u32>,
     pub document_title: String,
     pub title_font_size: usize,
     pub title_alignment: String,
@@ -15,6 +37,9 @@ pub struct TemplateConfig {
     pub output_format: String,
     pub default_output_name: String,
     pub individual_bills: bool,
+    // 生成时间的日期部分：命名样式 cn/iso/slash 或 chrono 格式串（可选）
+    #[serde(default)]
+    pub date_format: Option<String>,
 }
 
 #[derive(Debug, Deserialize, Clone)]
@@ -38,13 +63,66 @@ pub struct Section {
 impl TemplateConfig {
     pub fn load_from_file(path: &str) -> Result<Self, Box<dyn std::error::Error>> {
         let content = std::fs::read_to_string(path)?;
-        let config: TemplateConfig = serde_json::from_str(&content)?;
-        Ok(config)
+        Ok(Self::from_json_str(&content)?)
+    }
+
+    // 解析配置JSON：字段与当前版本不一致时返回"配置版本不兼容"及缺少/未知的字段，而不是原始的serde错误
+    pub fn from_json_str(content: &str) -> Result<Self, String> {
+        let value: serde_json::Value = serde_json::from_str(content).map_err(|e| format!("配置JSON格式错误：{}", e))?;
+        if let Some(message) = compatibility_error(&value) {
+            return Err(message);
+        }
+        serde_json::from_value(value).map_err(|e| format!("配置无效：{}", e))
     }
 
     pub fn load_default() -> Self {
         serde_json::from_str(include_str!("../config/template_config.json")).unwrap()
     }
+
+    // 最小可用配置：无任何分节，字号等使用默认配置中的取值，便于测试或在代码中逐项构造
+    pub fn minimal() -> Self {
+        Self {
+            version: Some(TEMPLATE_CONFIG_VERSION),
+            document_title: "商家水费电费账单".to_string(),
+            title_font_size: 40,
+            title_alignment: "center".to_string(),
+            section_font_size: 28,
+            timestamp_font_size: 22,
+            merchant_template: MerchantTemplate { sections: Vec::new() },
+            output_format: "docx".to_string(),
+            default_output_name: "merchant_bills.docx".to_string(),
+            individual_bills: false,
+            date_format: None,
+        }
+    }
+}
+
+// 缺少必填字段或版本高于程序支持的版本时返回提示（同时列出未知字段）；仅有未知字段时照常忽略
+fn compatibility_error(value: &serde_json::Value) -> Option<String> {
+    let fields = value.as_object()?;
+    let version = fields.get("version").and_then(|v| v.as_u64());
+    let missing: Vec<&str> = REQUIRED_FIELDS.iter().copied().filter(|f| !fields.contains_key(*f)).collect();
+    let unknown: Vec<&str> = fields
+        .keys()
+        .map(String::as_str)
+        .filter(|k| !OPTIONAL_FIELDS.contains(k) && !REQUIRED_FIELDS.contains(k))
+        .collect();
+    let too_new = version.is_some_and(|v| v > TEMPLATE_CONFIG_VERSION as u64);
+    if missing.is_empty() && !too_new {
+        return None;
+    }
+    let mut message = format!(
+        "配置版本不兼容（配置版本：{}，程序支持版本：{}）",
+        version.map(|v| v.to_string()).unwrap_or_else(|| "未标注".to_string()),
+        TEMPLATE_CONFIG_VERSION
+    );
+    if !missing.is_empty() {
+        message.push_str(&format!("；缺少字段：{}", missing.join("、")));
+    }
+    if !unknown.is_empty() {
+        message.push_str(&format!("；未知字段：{}", unknown.join("、")));
+    }
+    Some(message)
 }
 
 pub struct DocumentGenerator {
@@ -58,6 +136,7 @@ impl DocumentGenerator {
 
     // 生成完整文档（包含所有商家账单）
     pub fn generate_complete_document(&self, bills: &[MerchantBill]) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        let date_format = resolve_date_format(self.config.date_format.as_deref())?;
         let mut doc = Docx::new();
         
         // 添加文档标题
@@ -116,7 +195,7 @@ impl DocumentGenerator {
                         if let Some(format) = &section.content {
                             let datetime = Local::now();
                             let timestamp_content = format
-                                .replace("{datetime}", &datetime.format("%Y-%m-%d %H:%M:%S").to_string());
+                                .replace("{datetime}", &datetime.format(&format!("{} %H:%M:%S", date_format)).to_string());
                             
                             doc = doc.add_paragraph(
                                 Paragraph::new()
@@ -158,15 +237,15 @@ impl DocumentGenerator {
         result = result.replace("{electricity_usage}", &bill.electricity_usage.to_string());
         
         // 替换费用计算
-        result = result.replace("{water_unit_price}", &format!("{:.2}", bill.water_unit_price));
-        result = result.replace("{electricity_unit_price}", &format!("{:.2}", bill.electricity_unit_price));
+        result = result.replace("{water_unit_price}", &format_price(bill.water_unit_price, 2, PRICE_MAX_DECIMALS));
+        result = result.replace("{electricity_unit_price}", &format_price(bill.electricity_unit_price, 2, PRICE_MAX_DECIMALS));
         result = result.replace("{water_amount}", &format!("{:.2}", bill.water_amount));
         result = result.replace("{electricity_amount}", &format!("{:.2}", bill.electricity_amount));
         result = result.replace("{total_amount}", &format!("{:.2}", bill.total_fee));
         
         // 替换电表详细信息
         if result.contains("{electricity_details}") {
-            let details = bill.get_electricity_details().join("\n");
+            let details = bill.get_electricity_details();
             result = result.replace("{electricity_details}", &details);
         }
         